@@ -0,0 +1,412 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, ValueEnum};
+use client::game::{GameState, PlayerMovement};
+use client::headless::{HeadlessClient, InputScript, ScriptedMovement};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use server::config::ServerConfig;
+use server::game_loop::TickSnapshot;
+use server::ServerHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::Level;
+
+/// Connects a swarm of simulated players to a server and reports how both sides hold up.
+#[derive(Parser, Debug)]
+#[command(name = "rbmp-bots")]
+struct Args {
+    /// Server to connect to. An in-process server is started when omitted, which also makes
+    /// its tick times available.
+    #[arg(long)]
+    server: Option<SocketAddr>,
+    /// Number of bots to connect.
+    #[arg(long, default_value_t = 100)]
+    clients: u32,
+    /// Bots connected per second while ramping up.
+    #[arg(long, default_value_t = 50.0)]
+    spawn_rate: f64,
+    /// Seconds each bot stays connected.
+    #[arg(long, default_value_t = 30)]
+    duration: u64,
+    /// Movement packets each bot sends per second.
+    #[arg(long, default_value_t = 20)]
+    tick_rate: u32,
+    /// How the bots move around.
+    #[arg(long, value_enum, default_value_t = Path::Random)]
+    path: Path,
+    /// Seconds between progress reports.
+    #[arg(long, default_value_t = 1)]
+    report_interval: u64,
+    /// Seed for the random walks.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Path {
+    /// Wander around, changing direction every so often.
+    Random,
+    /// Walk in a square forever.
+    Square,
+    /// Stand still, only sending keep-alive movement.
+    Idle,
+}
+
+const FORWARD: usize = 1;
+const LEFT: usize = 0;
+const RIGHT: usize = 2;
+const BACKWARD: usize = 3;
+const JUMP: usize = 5;
+
+/// Holds a random set of directions and a random heading for a random number of ticks.
+struct RandomWalk {
+    rng: SmallRng,
+    movement: PlayerMovement,
+    remaining: u64,
+}
+
+impl RandomWalk {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+            movement: PlayerMovement::default(),
+            remaining: 0,
+        }
+    }
+}
+
+impl InputScript for RandomWalk {
+    fn movement(&mut self, _tick: u64, _game: &GameState) -> PlayerMovement {
+        if self.remaining == 0 {
+            let mut directions = [false; 6];
+            for direction in [LEFT, FORWARD, RIGHT, BACKWARD] {
+                directions[direction] = self.rng.gen_bool(0.3);
+            }
+            directions[JUMP] = self.rng.gen_bool(0.1);
+
+            let [yaw, _] = self.movement.rotations;
+            self.movement = PlayerMovement {
+                directions,
+                rotations: [
+                    (yaw + self.rng.gen_range(-90.0..90.0)).rem_euclid(360.0),
+                    self.rng.gen_range(-30.0..30.0),
+                ],
+                ..self.movement
+            };
+            self.remaining = self.rng.gen_range(10..60);
+        }
+
+        self.remaining -= 1;
+        self.movement
+    }
+}
+
+enum BotScript {
+    Random(RandomWalk),
+    Scripted(ScriptedMovement),
+}
+
+impl BotScript {
+    fn new(path: Path, seed: u64) -> Self {
+        let walk = |direction: usize| {
+            let mut directions = [false; 6];
+            directions[direction] = true;
+            PlayerMovement {
+                directions,
+                rotations: [0.0, 0.0],
+                ..Default::default()
+            }
+        };
+
+        match path {
+            Path::Random => BotScript::Random(RandomWalk::new(seed)),
+            Path::Square => BotScript::Scripted(
+                ScriptedMovement::new()
+                    .then(walk(FORWARD), 40)
+                    .then(walk(RIGHT), 40)
+                    .then(walk(BACKWARD), 40)
+                    .then(walk(LEFT), 40)
+                    .repeat(),
+            ),
+            Path::Idle => BotScript::Scripted(ScriptedMovement::new()),
+        }
+    }
+}
+
+impl InputScript for BotScript {
+    fn movement(&mut self, tick: u64, game: &GameState) -> PlayerMovement {
+        match self {
+            BotScript::Random(script) => script.movement(tick, game),
+            BotScript::Scripted(script) => script.movement(tick, game),
+        }
+    }
+}
+
+/// Counters updated by the bots as they go, read by the progress reports.
+#[derive(Default)]
+struct Counters {
+    connected: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    finished: AtomicU64,
+}
+
+enum BotOutcome {
+    /// The bot could not connect or the handshake failed.
+    Failed(String),
+    /// The bot connected and ran until the end or until its connection was lost.
+    Ran {
+        connect_latency: Duration,
+        bytes_sent: u64,
+        bytes_received: u64,
+        dropped: Option<String>,
+    },
+}
+
+async fn run_bot(
+    index: u32,
+    address: SocketAddr,
+    script: BotScript,
+    ticks: u64,
+    period: Duration,
+    counters: Arc<Counters>,
+) -> BotOutcome {
+    let started = Instant::now();
+    let mut bot = match HeadlessClient::connect(address, &format!("bot{index}"), script).await {
+        Ok(bot) => bot,
+        Err(err) => {
+            counters.failed.fetch_add(1, Ordering::Relaxed);
+            return BotOutcome::Failed(err.to_string());
+        }
+    };
+    let connect_latency = started.elapsed();
+    counters.connected.fetch_add(1, Ordering::Relaxed);
+
+    bot.run(ticks, period).await;
+
+    let traffic = bot.game().connection.traffic();
+    let dropped = bot.game().disconnect_reason().map(str::to_string);
+    counters.connected.fetch_sub(1, Ordering::Relaxed);
+    if dropped.is_some() {
+        counters.dropped.fetch_add(1, Ordering::Relaxed);
+    } else {
+        counters.finished.fetch_add(1, Ordering::Relaxed);
+    }
+    bot.disconnect();
+
+    BotOutcome::Ran {
+        connect_latency,
+        bytes_sent: traffic.bytes_sent,
+        bytes_received: traffic.bytes_received,
+        dropped,
+    }
+}
+
+/// Tick times seen while no bot was connected and the worst ones seen under load.
+#[derive(Default)]
+struct TickReport {
+    baseline: Option<TickSnapshot>,
+    worst_average: Duration,
+    worst_max: Duration,
+}
+
+fn report_progress(counters: &Counters, server: Option<&ServerHandle>, ticks: &mut TickReport) {
+    let mut line = format!(
+        "connected: {}, finished: {}, failed: {}, dropped: {}",
+        counters.connected.load(Ordering::Relaxed),
+        counters.finished.load(Ordering::Relaxed),
+        counters.failed.load(Ordering::Relaxed),
+        counters.dropped.load(Ordering::Relaxed),
+    );
+
+    if let Some(server) = server {
+        let snapshot = server.tick_stats().take();
+        line.push_str(&format!(
+            ", server ticks: {}, avg: {:?}, max: {:?}",
+            snapshot.ticks, snapshot.average, snapshot.max
+        ));
+        ticks.worst_average = ticks.worst_average.max(snapshot.average);
+        ticks.worst_max = ticks.worst_max.max(snapshot.max);
+    }
+
+    println!("{line}");
+}
+
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+fn report_summary(outcomes: &[BotOutcome], ticks: &TickReport) {
+    let mut latencies = Vec::new();
+    let mut sent = Vec::new();
+    let mut received = Vec::new();
+    let mut failures = Vec::new();
+    let mut drops = Vec::new();
+
+    for outcome in outcomes {
+        match outcome {
+            BotOutcome::Failed(reason) => failures.push(reason),
+            BotOutcome::Ran {
+                connect_latency,
+                bytes_sent,
+                bytes_received,
+                dropped,
+            } => {
+                latencies.push(*connect_latency);
+                sent.push(*bytes_sent);
+                received.push(*bytes_received);
+                if let Some(reason) = dropped {
+                    drops.push(reason);
+                }
+            }
+        }
+    }
+    latencies.sort();
+
+    println!();
+    println!("bots: {}, connected: {}", outcomes.len(), latencies.len());
+    if !latencies.is_empty() {
+        let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        println!(
+            "connect latency: min {:?}, avg {:?}, p50 {:?}, p95 {:?}, max {:?}",
+            latencies[0],
+            average,
+            percentile(&latencies, 0.5),
+            percentile(&latencies, 0.95),
+            latencies[latencies.len() - 1],
+        );
+
+        for (label, bytes) in [("bytes out", &sent), ("bytes in", &received)] {
+            let total: u64 = bytes.iter().sum();
+            println!(
+                "{label} per client: avg {}, max {}, total {total}",
+                total / bytes.len() as u64,
+                bytes.iter().max().unwrap(),
+            );
+        }
+    }
+
+    println!("failed connections: {}", failures.len());
+    for reason in failures.iter().take(5) {
+        println!("  {reason}");
+    }
+    println!("dropped connections: {}", drops.len());
+    for reason in drops.iter().take(5) {
+        println!("  {reason}");
+    }
+
+    if let Some(baseline) = ticks.baseline {
+        println!(
+            "server tick time: idle avg {:?} / max {:?}, under load worst avg {:?} / max {:?}",
+            baseline.average, baseline.max, ticks.worst_average, ticks.worst_max
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    tracing_subscriber::fmt::fmt()
+        .with_max_level(Level::WARN)
+        .init();
+
+    let server = match args.server {
+        Some(_) => None,
+        None => Some(
+            server::start(ServerConfig {
+                port: 0,
+                max_players: args.clients,
+                certificate: std::env::temp_dir().join("rbmp-bots.crt"),
+                private_key: std::env::temp_dir().join("rbmp-bots.key"),
+                world_dir: std::env::temp_dir().join("rbmp-bots-world"),
+                permissions: std::env::temp_dir().join("rbmp-bots-permissions.toml"),
+                access_list: std::env::temp_dir().join("rbmp-bots-access.toml"),
+                lan_discovery: false,
+                ..Default::default()
+            })
+            .await
+            .expect("failed to start the server"),
+        ),
+    };
+    let address = match (&server, args.server) {
+        (Some(server), _) => server
+            .local_addr()
+            .expect("failed to get the server address"),
+        (None, Some(address)) => address,
+        (None, None) => unreachable!(),
+    };
+
+    let report_interval = Duration::from_secs(args.report_interval.max(1));
+    let mut ticks = TickReport::default();
+    if let Some(server) = &server {
+        server.tick_stats().take();
+        tokio::time::sleep(report_interval).await;
+        ticks.baseline = Some(server.tick_stats().take());
+    }
+
+    println!(
+        "connecting {} bots to {address} at {}/s",
+        args.clients, args.spawn_rate
+    );
+
+    let counters = Arc::new(Counters::default());
+    let period = Duration::from_secs(1) / args.tick_rate.max(1);
+    let bot_ticks = args.duration * args.tick_rate as u64;
+
+    let spawner = {
+        let counters = counters.clone();
+        tokio::spawn(async move {
+            let mut spawn_interval = interval(Duration::from_secs_f64(
+                1.0 / args.spawn_rate.max(f64::EPSILON),
+            ));
+            spawn_interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+
+            let mut bots = Vec::with_capacity(args.clients as usize);
+            for index in 0..args.clients {
+                spawn_interval.tick().await;
+                let script = BotScript::new(args.path, args.seed.wrapping_add(index as u64));
+                bots.push(tokio::spawn(run_bot(
+                    index,
+                    address,
+                    script,
+                    bot_ticks,
+                    period,
+                    counters.clone(),
+                )));
+            }
+
+            let mut outcomes = Vec::with_capacity(bots.len());
+            for bot in bots {
+                outcomes.push(match bot.await {
+                    Ok(outcome) => outcome,
+                    Err(err) => BotOutcome::Failed(format!("bot crashed: {err}")),
+                });
+            }
+            outcomes
+        })
+    };
+
+    let mut report = interval(report_interval);
+    report.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    report.tick().await;
+    while !spawner.is_finished() {
+        report.tick().await;
+        report_progress(&counters, server.as_ref(), &mut ticks);
+    }
+
+    let outcomes = spawner.await.expect("the bot spawner crashed");
+    report_summary(&outcomes, &ticks);
+
+    if let Some(server) = server {
+        if let Err(err) = tokio::task::block_in_place(|| server.stop()) {
+            eprintln!("the local server did not shut down cleanly: {err}");
+        }
+    }
+}
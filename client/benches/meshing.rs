@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use client::meshing::{mesh_section, FullTexture, MeshContext, MeshingMode, SectionNeighbours};
+use shared::block::BlockRegistry;
+use shared::chunk::{ChunkSection, SECTION_SIZE};
+
+const SECTIONS: i32 = 8;
+const ITERATIONS: u32 = 20;
+
+/// Rolling hills of grass over dirt and stone, with the odd stone column poking through.
+fn generate_terrain(registry: &BlockRegistry, sx: i32, sz: i32) -> ChunkSection {
+    let stone = registry.id("stone").unwrap();
+    let dirt = registry.id("dirt").unwrap();
+    let grass = registry.id("grass").unwrap();
+
+    let mut section = ChunkSection::new();
+    for z in 0..SECTION_SIZE {
+        for x in 0..SECTION_SIZE {
+            let wx = (sx * SECTION_SIZE as i32 + x as i32) as f32;
+            let wz = (sz * SECTION_SIZE as i32 + z as i32) as f32;
+            let height = 8.0 + (wx * 0.15).sin() * 3.0 + (wz * 0.1).cos() * 3.0;
+            let height = (height as usize).min(SECTION_SIZE - 1);
+
+            for y in 0..=height {
+                let block = if y == height {
+                    grass
+                } else if y + 3 >= height {
+                    dirt
+                } else {
+                    stone
+                };
+                section.set(x, y, z, block);
+            }
+
+            if (x * 7 + z * 13) % 29 == 0 {
+                for y in height..SECTION_SIZE {
+                    section.set(x, y, z, stone);
+                }
+            }
+        }
+    }
+    section
+}
+
+fn main() {
+    let registry = BlockRegistry::default();
+    let sections: Vec<_> = (0..SECTIONS)
+        .flat_map(|x| (0..SECTIONS).map(move |z| (x, z)))
+        .map(|(x, z)| generate_terrain(&registry, x, z))
+        .collect();
+
+    for mode in [MeshingMode::Naive, MeshingMode::Greedy] {
+        let mut vertices = 0;
+        let mut indices = 0;
+        let mut elapsed = Duration::ZERO;
+
+        for _ in 0..ITERATIONS {
+            vertices = 0;
+            indices = 0;
+            for section in &sections {
+                let ctx = MeshContext {
+                    section,
+                    neighbours: SectionNeighbours::default(),
+                    registry: &registry,
+                    textures: &FullTexture,
+                };
+
+                let start = Instant::now();
+                let mesh = mesh_section(mode, &ctx);
+                elapsed += start.elapsed();
+
+                vertices += mesh.vertices.len();
+                indices += mesh.indices.len();
+            }
+        }
+
+        let per_section = elapsed / (ITERATIONS * sections.len() as u32);
+        println!(
+            "{mode:?}: {} sections, {vertices} vertices, {indices} indices, {per_section:?} per section",
+            sections.len()
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use shared::block::BlockRegistry;
+use tracing::warn;
+
+use crate::meshing::{TextureLookup, UvRect};
+
+pub const MISSING_TEXTURE: &str = "missing";
+
+const MISSING_TEXTURE_SIZE: u32 = 16;
+const MAX_ATLAS_SIZE: u32 = 4096;
+
+/// An RGBA8 image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl TextureImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&pixel);
+    }
+
+    /// A magenta and black checkerboard, used in place of textures that failed to load.
+    pub fn missing() -> Self {
+        let mut image = Self::new(MISSING_TEXTURE_SIZE, MISSING_TEXTURE_SIZE);
+        let half = MISSING_TEXTURE_SIZE / 2;
+        for y in 0..MISSING_TEXTURE_SIZE {
+            for x in 0..MISSING_TEXTURE_SIZE {
+                let pixel = if (x < half) == (y < half) {
+                    [255, 0, 255, 255]
+                } else {
+                    [0, 0, 0, 255]
+                };
+                image.set_pixel(x, y, pixel);
+            }
+        }
+        image
+    }
+
+    pub fn load_png(path: &Path) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(to_io_error)?;
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(to_io_error)?;
+        buf.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|c| [c[0], c[1], c[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|c| [c[0], c[0], c[0], c[1]])
+                .collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|&c| [c, c, c, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "indexed png was not expanded",
+                ))
+            }
+        };
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+fn to_io_error(err: png::DecodingError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Packs textures into a single image. Every texture is surrounded by `padding` pixels copied
+/// from its own edges, so filtering and lower mip levels do not bleed into neighbouring tiles.
+pub struct AtlasBuilder {
+    padding: u32,
+    textures: Vec<(String, TextureImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(padding: u32) -> Self {
+        Self {
+            padding,
+            textures: vec![(MISSING_TEXTURE.to_string(), TextureImage::missing())],
+        }
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, image: TextureImage) -> &mut Self {
+        let name = name.into();
+        match self.textures.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, existing)) => *existing = image,
+            None => self.textures.push((name, image)),
+        }
+        self
+    }
+
+    /// Loads `<directory>/<name>.png` for every texture named in `registry`. Textures that
+    /// cannot be loaded are left out, so lookups fall back to the missing texture.
+    pub fn add_block_textures(&mut self, registry: &BlockRegistry, directory: &Path) -> &mut Self {
+        let names: BTreeSet<&str> = registry
+            .blocks()
+            .flat_map(|(_, block)| block.textures().iter().copied())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        for name in names {
+            let path = directory.join(format!("{name}.png"));
+            match TextureImage::load_png(&path) {
+                Ok(image) => {
+                    self.add(name, image);
+                }
+                Err(err) => warn!("failed to load texture {}: {err}", path.display()),
+            }
+        }
+        self
+    }
+
+    /// Packs the textures with a shelf packer, doubling the atlas size until everything fits.
+    pub fn build(&self) -> Option<TextureAtlas> {
+        let mut order: Vec<usize> = (0..self.textures.len()).collect();
+        order.sort_by_key(|&i| {
+            let image = &self.textures[i].1;
+            (
+                std::cmp::Reverse(image.height),
+                std::cmp::Reverse(image.width),
+            )
+        });
+
+        let mut size = 16;
+        while size <= MAX_ATLAS_SIZE {
+            if let Some(placements) = self.pack(&order, size) {
+                return Some(self.blit(&placements, size));
+            }
+            size *= 2;
+        }
+        None
+    }
+
+    fn pack(&self, order: &[usize], size: u32) -> Option<Vec<(usize, u32, u32)>> {
+        let mut placements = Vec::with_capacity(order.len());
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+        for &i in order {
+            let image = &self.textures[i].1;
+            let width = image.width + self.padding * 2;
+            let height = image.height + self.padding * 2;
+            if width > size {
+                return None;
+            }
+
+            if x + width > size {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            if y + height > size {
+                return None;
+            }
+
+            placements.push((i, x + self.padding, y + self.padding));
+            x += width;
+            shelf_height = shelf_height.max(height);
+        }
+
+        Some(placements)
+    }
+
+    fn blit(&self, placements: &[(usize, u32, u32)], size: u32) -> TextureAtlas {
+        let mut image = TextureImage::new(size, size);
+        let mut rects = HashMap::with_capacity(placements.len());
+        let padding = self.padding as i64;
+
+        for &(i, x, y) in placements {
+            let (name, texture) = &self.textures[i];
+
+            for ty in -padding..texture.height as i64 + padding {
+                for tx in -padding..texture.width as i64 + padding {
+                    let sx = tx.clamp(0, texture.width as i64 - 1) as u32;
+                    let sy = ty.clamp(0, texture.height as i64 - 1) as u32;
+                    image.set_pixel(
+                        (x as i64 + tx) as u32,
+                        (y as i64 + ty) as u32,
+                        texture.pixel(sx, sy),
+                    );
+                }
+            }
+
+            rects.insert(
+                name.clone(),
+                UvRect {
+                    u: x as f32 / size as f32,
+                    v: y as f32 / size as f32,
+                    width: texture.width as f32 / size as f32,
+                    height: texture.height as f32 / size as f32,
+                },
+            );
+        }
+
+        let missing = rects[MISSING_TEXTURE];
+        TextureAtlas {
+            image,
+            rects,
+            missing,
+        }
+    }
+}
+
+pub struct TextureAtlas {
+    image: TextureImage,
+    rects: HashMap<String, UvRect>,
+    missing: UvRect,
+}
+
+impl TextureAtlas {
+    pub fn image(&self) -> &TextureImage {
+        &self.image
+    }
+
+    pub fn get(&self, name: &str) -> Option<UvRect> {
+        self.rects.get(name).copied()
+    }
+
+    pub fn missing(&self) -> UvRect {
+        self.missing
+    }
+
+    pub fn rects(&self) -> &HashMap<String, UvRect> {
+        &self.rects
+    }
+}
+
+impl TextureLookup for TextureAtlas {
+    fn uv_rect(&self, texture: &str) -> UvRect {
+        self.get(texture).unwrap_or(self.missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> TextureImage {
+        let mut image = TextureImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, pixel);
+            }
+        }
+        image
+    }
+
+    /// Textures of assorted sizes, each a colour of its own.
+    fn textures() -> Vec<(String, TextureImage)> {
+        [
+            (16, 16),
+            (16, 16),
+            (32, 32),
+            (8, 24),
+            (40, 8),
+            (16, 16),
+            (5, 7),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (width, height))| {
+            let colour = [i as u8 * 30, 255 - i as u8 * 30, 7, 255];
+            (format!("texture{i}"), solid(width, height, colour))
+        })
+        .collect()
+    }
+
+    /// `rect` in pixels of an atlas of `size`, widened by `padding`.
+    fn pixel_rect(rect: UvRect, size: u32, padding: u32) -> [u32; 4] {
+        let size = size as f32;
+        [
+            (rect.u * size) as u32 - padding,
+            (rect.v * size) as u32 - padding,
+            (rect.width * size) as u32 + padding * 2,
+            (rect.height * size) as u32 + padding * 2,
+        ]
+    }
+
+    #[test]
+    fn textures_are_placed_apart_and_in_bounds() {
+        let padding = 2;
+        let mut builder = AtlasBuilder::new(padding);
+        for (name, image) in textures() {
+            builder.add(name, image);
+        }
+        let atlas = builder.build().unwrap();
+        let size = atlas.image().width;
+        assert_eq!(atlas.image().height, size);
+        assert_eq!(atlas.rects().len(), textures().len() + 1);
+
+        let rects: Vec<_> = atlas.rects().values().copied().collect();
+        for rect in &rects {
+            assert!(rect.u >= 0.0 && rect.v >= 0.0);
+            assert!(rect.u + rect.width <= 1.0 && rect.v + rect.height <= 1.0);
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                let [ax, ay, aw, ah] = pixel_rect(*a, size, padding);
+                let [bx, by, bw, bh] = pixel_rect(*b, size, padding);
+                let apart = ax + aw <= bx || bx + bw <= ax || ay + ah <= by || by + bh <= ay;
+                assert!(apart, "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn textures_and_their_padding_are_copied() {
+        let padding = 2;
+        let mut builder = AtlasBuilder::new(padding);
+        for (name, image) in textures() {
+            builder.add(name, image);
+        }
+        let atlas = builder.build().unwrap();
+        let size = atlas.image().width;
+
+        for (name, texture) in textures() {
+            let rect = atlas.get(&name).unwrap();
+            assert_eq!(rect.width * size as f32, texture.width as f32);
+            assert_eq!(rect.height * size as f32, texture.height as f32);
+
+            let [x, y, width, height] = pixel_rect(rect, size, padding);
+            for py in y..y + height {
+                for px in x..x + width {
+                    assert_eq!(atlas.image().pixel(px, py), texture.pixel(0, 0), "{name}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_textures_look_up_the_missing_texture() {
+        let mut builder = AtlasBuilder::new(1);
+        builder.add("stone", solid(16, 16, [128, 128, 128, 255]));
+        let atlas = builder.build().unwrap();
+
+        let missing = atlas.get(MISSING_TEXTURE).unwrap();
+        assert_eq!(atlas.missing(), missing);
+        assert_eq!(atlas.uv_rect("dirt"), missing);
+        assert_eq!(atlas.uv_rect(""), missing);
+        assert_ne!(atlas.uv_rect("stone"), missing);
+
+        let size = atlas.image().width as f32;
+        let (x, y) = ((missing.u * size) as u32, (missing.v * size) as u32);
+        assert_eq!(atlas.image().pixel(x, y), [255, 0, 255, 255]);
+        assert_eq!(atlas.image().pixel(x + 15, y), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn added_textures_replace_those_of_the_same_name() {
+        let mut builder = AtlasBuilder::new(0);
+        builder.add("stone", solid(16, 16, [1, 1, 1, 255]));
+        builder.add("stone", solid(8, 8, [2, 2, 2, 255]));
+        builder.add(MISSING_TEXTURE, solid(4, 4, [3, 3, 3, 255]));
+        let atlas = builder.build().unwrap();
+
+        assert_eq!(atlas.rects().len(), 2);
+        let size = atlas.image().width as f32;
+        let stone = atlas.get("stone").unwrap();
+        assert_eq!(stone.width * size, 8.0);
+        let missing = atlas.missing();
+        let (x, y) = ((missing.u * size) as u32, (missing.v * size) as u32);
+        assert_eq!(atlas.image().pixel(x, y), [3, 3, 3, 255]);
+    }
+
+    #[test]
+    fn oversized_textures_do_not_fit() {
+        let mut builder = AtlasBuilder::new(0);
+        builder.add("huge", TextureImage::new(MAX_ATLAS_SIZE + 1, 1));
+        assert!(builder.build().is_none());
+    }
+}
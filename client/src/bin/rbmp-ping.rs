@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use clap::Parser;
+use client::known_hosts::{KnownHosts, KNOWN_HOSTS_PATH};
+use client::networking::{ping, ServerAddress};
+
+/// Asks a server for its status without logging in.
+#[derive(Parser, Debug)]
+#[command(name = "rbmp-ping")]
+struct Args {
+    /// Server to ask, as host[:port].
+    #[arg(default_value = "127.0.0.1")]
+    server: ServerAddress,
+    /// Seconds to wait for an answer.
+    #[arg(long, default_value_t = 5)]
+    timeout: u64,
+    /// Writes the server icon there, if it has one.
+    #[arg(long)]
+    icon: Option<PathBuf>,
+    /// Checks the certificate against the known hosts of the client, instead of trusting any.
+    #[arg(long)]
+    known_hosts: bool,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let known_hosts = if args.known_hosts {
+        match KnownHosts::load(Path::new(KNOWN_HOSTS_PATH)) {
+            Ok(known_hosts) => known_hosts,
+            Err(err) => {
+                eprintln!("failed to load {KNOWN_HOSTS_PATH}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        KnownHosts::in_memory()
+    };
+
+    let timeout = Duration::from_secs(args.timeout);
+    let result = tokio::time::timeout(
+        timeout,
+        ping(&args.server, Arc::new(Mutex::new(known_hosts))),
+    )
+    .await;
+    let ping = match result {
+        Ok(Ok(ping)) => ping,
+        Ok(Err(err)) => {
+            eprintln!("{}: {err}", args.server);
+            return ExitCode::FAILURE;
+        }
+        Err(_) => {
+            eprintln!("{}: no answer within {timeout:?}", args.server);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("{} answered in {:?}", args.server, ping.latency);
+    match ping.compatibility() {
+        Ok(()) => println!("protocol version {}", ping.version),
+        Err(mismatch) => println!("protocol version {}, {mismatch}", ping.version),
+    }
+
+    let Some(status) = ping.status else {
+        return ExitCode::SUCCESS;
+    };
+    println!("motd: {}", status.motd);
+    println!("players: {}/{}", status.online, status.max_players);
+    for username in &status.sample {
+        println!("  {username}");
+    }
+
+    match (status.icon, args.icon) {
+        (Some(icon), Some(path)) => {
+            if let Err(err) = std::fs::write(&path, &icon) {
+                eprintln!("failed to write {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+            println!("icon: {} bytes, written to {}", icon.len(), path.display());
+        }
+        (Some(icon), None) => println!("icon: {} bytes", icon.len()),
+        (None, _) => println!("icon: none"),
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,231 @@
+use cgmath::{perspective, Deg, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3};
+
+/// Just short of straight up or down, where the forward vector would be parallel to the up
+/// vector and the view matrix undefined.
+const MAX_PITCH: Deg<f32> = Deg(89.9);
+
+/// cgmath produces OpenGL clip space, with depth in `[-1, 1]`; wgpu expects `[0, 1]`.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// A first person camera. A yaw of zero looks towards -Z and grows turning right, a positive
+/// pitch looks up.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    pub fovy: Deg<f32>,
+    pub aspect: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Camera {
+    pub fn new(position: Point3<f32>, aspect: f32) -> Self {
+        Self {
+            position,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            fovy: Deg(70.0),
+            aspect,
+            znear: 0.1,
+            zfar: 1000.0,
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
+        Vector3::new(cos_pitch * sin_yaw, sin_pitch, -cos_pitch * cos_yaw).normalize()
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.forward(), Vector3::unit_y())
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
+    }
+
+    pub fn view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+}
+
+/// The camera as laid out in the world shader's uniform buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_projection: [[f32; 4]; 4],
+    pub position: [f32; 4],
+}
+
+impl CameraUniform {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let position = camera.position;
+        Self {
+            view_projection: camera.view_projection_matrix().into(),
+            position: [position.x, position.y, position.z, 1.0],
+        }
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self {
+            view_projection: Matrix4::identity().into(),
+            position: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Turns raw mouse motion into yaw and pitch.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraController {
+    /// Degrees turned per unit of mouse motion.
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    pub yaw: Deg<f32>,
+    pub pitch: Deg<f32>,
+}
+
+impl CameraController {
+    pub fn new(sensitivity: f32, invert_y: bool) -> Self {
+        Self {
+            sensitivity,
+            invert_y,
+            yaw: Deg(0.0),
+            pitch: Deg(0.0),
+        }
+    }
+
+    /// Applies a mouse delta, where positive `dy` means the mouse moved down. The yaw wraps
+    /// around to `[0, 360)` and the pitch is clamped to `MAX_PITCH` either way.
+    pub fn rotate(&mut self, dx: f64, dy: f64) {
+        let dy = if self.invert_y { -dy } else { dy };
+
+        self.yaw = Deg((self.yaw.0 + dx as f32 * self.sensitivity).rem_euclid(360.0));
+        self.pitch =
+            Deg((self.pitch.0 - dy as f32 * self.sensitivity).clamp(-MAX_PITCH.0, MAX_PITCH.0));
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.yaw = self.yaw.into();
+        camera.pitch = self.pitch.into();
+    }
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new(0.15, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{align_of, offset_of, size_of};
+
+    use cgmath::{assert_abs_diff_eq, Point3, Transform, Vector4};
+
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(Point3::new(1.0, 2.0, 3.0), 16.0 / 9.0)
+    }
+
+    /// `point` in normalized device coordinates.
+    fn project(matrix: Matrix4<f32>, point: Point3<f32>) -> Point3<f32> {
+        let clip = matrix * Vector4::new(point.x, point.y, point.z, 1.0);
+        Point3::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+    }
+
+    #[test]
+    fn forward_follows_yaw_and_pitch() {
+        let mut camera = camera();
+        assert_abs_diff_eq!(camera.forward(), -Vector3::unit_z(), epsilon = 1e-6);
+        camera.yaw = Deg(90.0).into();
+        assert_abs_diff_eq!(camera.forward(), Vector3::unit_x(), epsilon = 1e-6);
+        camera.pitch = Deg(90.0).into();
+        assert_abs_diff_eq!(camera.forward(), Vector3::unit_y(), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn view_matrix_moves_the_camera_to_the_origin() {
+        let mut camera = camera();
+        camera.yaw = Deg(90.0).into();
+        let view = camera.view_matrix();
+        assert_abs_diff_eq!(
+            view.transform_point(camera.position),
+            Point3::new(0.0, 0.0, 0.0),
+            epsilon = 1e-6
+        );
+        // what the camera looks at ends up straight ahead, towards -Z
+        assert_abs_diff_eq!(
+            view.transform_point(camera.position + camera.forward() * 5.0),
+            Point3::new(0.0, 0.0, -5.0),
+            epsilon = 1e-5
+        );
+        assert_abs_diff_eq!(
+            view.transform_point(camera.position + Vector3::unit_y()),
+            Point3::new(0.0, 1.0, 0.0),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn projection_uses_wgpu_depth() {
+        let camera = camera();
+        let projection = camera.projection_matrix();
+        let near = project(projection, Point3::new(0.0, 0.0, -camera.znear));
+        let far = project(projection, Point3::new(0.0, 0.0, -camera.zfar));
+        assert_abs_diff_eq!(near, Point3::new(0.0, 0.0, 0.0), epsilon = 1e-5);
+        assert_abs_diff_eq!(far, Point3::new(0.0, 0.0, 1.0), epsilon = 1e-5);
+
+        // the top of the field of view is the top of the screen
+        let half = Rad::from(camera.fovy).0 / 2.0;
+        let top = project(projection, Point3::new(0.0, half.tan() * 10.0, -10.0));
+        assert_abs_diff_eq!(top.y, 1.0, epsilon = 1e-5);
+        let right = project(
+            projection,
+            Point3::new(half.tan() * camera.aspect * 10.0, 0.0, -10.0),
+        );
+        assert_abs_diff_eq!(right.x, 1.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn uniform_matches_the_shader_layout() {
+        // mat4x4<f32> then vec4<f32>, 16 byte aligned in WGSL
+        assert_eq!(size_of::<CameraUniform>(), 80);
+        assert_eq!(offset_of!(CameraUniform, view_projection), 0);
+        assert_eq!(offset_of!(CameraUniform, position), 64);
+        assert_eq!(align_of::<CameraUniform>(), 4);
+
+        let camera = camera();
+        let uniform = CameraUniform::from_camera(&camera);
+        assert_eq!(uniform.position, [1.0, 2.0, 3.0, 1.0]);
+        let matrix: [[f32; 4]; 4] = camera.view_projection_matrix().into();
+        assert_eq!(uniform.view_projection, matrix);
+    }
+
+    #[test]
+    fn pitch_stops_short_of_vertical() {
+        let mut controller = CameraController::new(1.0, false);
+        let mut camera = camera();
+        for dy in [-1000.0, 2000.0] {
+            controller.rotate(0.0, dy);
+            assert_eq!(controller.pitch.0.abs(), MAX_PITCH.0);
+
+            // the view stays well defined looking as far up or down as allowed
+            controller.apply(&mut camera);
+            let view = camera.view_matrix();
+            assert!(view.invert().is_some());
+            assert!(camera.forward().cross(Vector3::unit_y()).magnitude() > 1e-3);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use imgui::{Condition, InputTextCallback, InputTextCallbackHandler, TextCallbackData, Ui};
+use shared::protocol::ChatMessage;
+use tracing::warn;
+
+use crate::game_loop::FrameContext;
+use crate::input::Action;
+use crate::player::set_cursor_grab;
+use crate::state::ClientState;
+use client::game::GameState;
+
+/// The chat input box, opened while playing to type a message.
+#[derive(Default)]
+pub struct Chat {
+    open: bool,
+    /// Set on the frame the box opens, as the key opening it may also be typed into it.
+    just_opened: bool,
+    input: String,
+    /// Newest message shown so far, to scroll down when another arrives.
+    last_seen: Option<ChatMessage>,
+    /// Id and input of the last completion requested, to ask again only when the command
+    /// changes and to ignore answers to older ones.
+    completion: Option<(u32, String)>,
+}
+
+impl Chat {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+        self.input.clear();
+        self.completion = None;
+    }
+
+    /// The suggestions for the command being typed, once the server answered.
+    fn suggestions<'a>(&self, game: &'a GameState) -> Option<&'a [String]> {
+        let (id, _) = self.completion.as_ref()?;
+        let suggestions = game.suggestions().filter(|s| s.id == *id)?;
+        Some(&suggestions.suggestions)
+    }
+
+    /// The input with its last word completed as far as all suggestions agree, followed by a
+    /// space when only one is left.
+    fn completed_input(&self, game: &GameState) -> Option<String> {
+        let (id, input) = self.completion.as_ref()?;
+        let suggestions = game.suggestions().filter(|s| s.id == *id)?;
+        let (first, rest) = suggestions.suggestions.split_first()?;
+
+        let mut completion = first.clone();
+        for suggestion in rest {
+            let common = completion
+                .chars()
+                .zip(suggestion.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum();
+            completion.truncate(common);
+        }
+        if rest.is_empty() {
+            completion.push(' ');
+        }
+
+        // the server counts characters from after the leading '/'
+        let start = input
+            .char_indices()
+            .nth(suggestions.start + 1)
+            .map_or(input.len(), |(i, _)| i);
+        Some(format!("{}{completion}", &input[..start]))
+    }
+}
+
+/// Replaces the input with a completion on Tab.
+struct Completer(Option<String>);
+
+impl InputTextCallbackHandler for Completer {
+    fn on_completion(&mut self, mut data: TextCallbackData) {
+        if let Some(completed) = self.0.take() {
+            data.clear();
+            data.push_str(&completed);
+        }
+    }
+}
+
+/// Opens the chat box while playing and closes it again on `Action::ToggleCursor`, giving the
+/// cursor back to the game.
+pub fn update_chat(ctx: &mut FrameContext, state: &mut ClientState) {
+    if state.game.is_none() {
+        state.chat.close();
+        return;
+    }
+
+    if state.chat.open {
+        if state.input_map.just_pressed(ctx, Action::ToggleCursor) {
+            state.chat.close();
+            set_cursor_grab(state, true);
+        }
+    } else if state.cursor_grabbed
+        && !state.binding_editor.is_capturing()
+        && state.input_map.just_pressed(ctx, Action::OpenChat)
+    {
+        state.chat.open = true;
+        state.chat.just_opened = true;
+        set_cursor_grab(state, false);
+    }
+}
+
+pub fn chat_window(_ctx: &mut FrameContext, state: &mut ClientState, ui: &mut Ui) {
+    let Some(game) = &mut state.game else {
+        return;
+    };
+    let chat = &mut state.chat;
+
+    let is_command = chat.input.starts_with('/');
+    if !is_command {
+        chat.completion = None;
+    } else if chat
+        .completion
+        .as_ref()
+        .is_none_or(|(_, input)| *input != chat.input)
+    {
+        chat.completion = match game.request_completion(&chat.input[1..]) {
+            Ok(id) => Some((id, chat.input.clone())),
+            Err(_) => None,
+        };
+    }
+    let suggestions = chat.suggestions(game).filter(|s| !s.is_empty());
+    let completed = chat.completed_input(game);
+
+    let mut sent = None;
+    ui.window("Chat")
+        .size([420.0, 220.0], Condition::FirstUseEver)
+        .build(|| {
+            let mut input_height = 0.0;
+            if chat.open {
+                input_height += ui.frame_height_with_spacing();
+                if suggestions.is_some() {
+                    input_height += ui.text_line_height_with_spacing();
+                }
+            }
+            ui.child_window("history")
+                .size([0.0, -input_height])
+                .build(|| {
+                    for message in game.chat() {
+                        ui.text_wrapped(message.to_string());
+                    }
+
+                    let last = game.chat().last();
+                    if last != chat.last_seen.as_ref() {
+                        // only follow new messages if the player was not scrolled up reading
+                        if ui.scroll_y() >= ui.scroll_max_y() - 1.0 {
+                            ui.set_scroll_here_y_with_ratio(1.0);
+                        }
+                        chat.last_seen = last.cloned();
+                    }
+                });
+
+            if chat.open {
+                if chat.just_opened {
+                    ui.set_keyboard_focus_here();
+                }
+                ui.set_next_item_width(-1.0);
+                let entered = ui
+                    .input_text("##message", &mut chat.input)
+                    .hint("press Enter to send")
+                    .enter_returns_true(true)
+                    .callback(InputTextCallback::COMPLETION, Completer(completed))
+                    .build();
+                if let Some(suggestions) = suggestions {
+                    ui.text_disabled(suggestions.join("  "));
+                }
+                if chat.just_opened {
+                    chat.input.clear();
+                    chat.just_opened = false;
+                }
+                if entered {
+                    sent = Some(std::mem::take(&mut chat.input));
+                }
+            }
+        });
+
+    if let Some(text) = sent {
+        if let Err(err) = game.send_chat(&text) {
+            warn!("failed to send the message: {err}");
+        }
+        state.chat.close();
+        set_cursor_grab(state, true);
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use shared::protocol::{validate_username, Credentials};
+use shared::toml_file::{self, TomlFileError};
+
+pub const CONFIG_PATH: &str = "client.toml";
+
+pub const MIN_RENDER_DISTANCE: i32 = 2;
+pub const MAX_RENDER_DISTANCE: i32 = 32;
+
+/// Launch options, read from `client.toml` and overridable on the command line.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    pub username: String,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// In sections, around the camera.
+    pub render_distance: i32,
+    /// Sent to servers authenticating with local accounts.
+    pub password: Option<String>,
+    /// Sent to servers authenticating with signed tokens. Takes precedence over the password.
+    pub token: Option<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            username: "player".to_string(),
+            width: 1280,
+            height: 720,
+            fullscreen: false,
+            vsync: true,
+            render_distance: 8,
+            password: None,
+            token: None,
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn load(path: &Path) -> Result<Self, TomlFileError> {
+        toml_file::load(path)
+    }
+
+    /// Loads the config at `path`, falling back to the defaults if the file is missing or
+    /// invalid. An invalid file is moved aside rather than lost.
+    pub fn load_or_default(path: &Path) -> Self {
+        toml_file::load_or_default(path, Self::load)
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        match (&self.token, &self.password) {
+            (Some(token), _) => Credentials::Token(token.clone()),
+            (None, Some(password)) => Credentials::Password(password.clone()),
+            (None, None) => Credentials::None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        validate_username(&self.username)?;
+
+        if self.width == 0 || self.height == 0 {
+            return Err(format!(
+                "window size must not be zero, got {}x{}",
+                self.width, self.height
+            ));
+        }
+        if !(MIN_RENDER_DISTANCE..=MAX_RENDER_DISTANCE).contains(&self.render_distance) {
+            return Err(format!(
+                "render_distance must be between {MIN_RENDER_DISTANCE} and {MAX_RENDER_DISTANCE}, got {}",
+                self.render_distance
+            ));
+        }
+        Ok(())
+    }
+}
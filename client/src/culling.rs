@@ -0,0 +1,395 @@
+use std::collections::{HashSet, VecDeque};
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4};
+use shared::block::{BlockFace, BlockRegistry};
+use shared::chunk::{ChunkSection, SectionPos, SECTION_SIZE, SECTION_VOLUME};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = row.truncate();
+        let length = normal.magnitude();
+        Self {
+            normal: normal / length,
+            distance: row.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(Vector3::new(point.x, point.y, point.z)) + self.distance
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn of_section(pos: SectionPos) -> Self {
+        let [x, y, z] = pos.origin();
+        let size = SECTION_SIZE as f32;
+        let min = Point3::new(x as f32, y as f32, z as f32);
+        Self {
+            min,
+            max: Point3::new(min.x + size, min.y + size, min.z + size),
+        }
+    }
+}
+
+/// The six planes bounding a view volume, pointing inwards.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix using wgpu's `[0, 1]` depth range.
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(matrix.x[i], matrix.y[i], matrix.z[i], matrix.w[i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                Plane::from_row(r3 + r0),
+                Plane::from_row(r3 - r0),
+                Plane::from_row(r3 + r1),
+                Plane::from_row(r3 - r1),
+                Plane::from_row(r2),
+                Plane::from_row(r3 - r2),
+            ],
+        }
+    }
+
+    /// Conservative test: boxes straddling a plane count as inside.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let furthest = Point3::new(
+                if plane.normal.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.normal.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.normal.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.signed_distance(furthest) >= 0.0
+        })
+    }
+}
+
+/// Which pairs of section faces are connected through non-opaque blocks.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SectionVisibility(u64);
+
+impl SectionVisibility {
+    pub const NONE: SectionVisibility = SectionVisibility(0);
+    pub const ALL: SectionVisibility = SectionVisibility((1 << 36) - 1);
+
+    fn bit(a: BlockFace, b: BlockFace) -> u64 {
+        1 << (a as usize * 6 + b as usize)
+    }
+
+    pub fn connect(&mut self, a: BlockFace, b: BlockFace) {
+        self.0 |= Self::bit(a, b) | Self::bit(b, a);
+    }
+
+    pub fn connects(&self, a: BlockFace, b: BlockFace) -> bool {
+        self.0 & Self::bit(a, b) != 0
+    }
+
+    /// Flood fills every region of non-opaque blocks and connects all faces each one touches.
+    pub fn compute(section: &ChunkSection, registry: &BlockRegistry) -> Self {
+        let mut visited = vec![false; SECTION_VOLUME];
+        let mut opaque_count = 0;
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    if registry.is_opaque(section.get(x, y, z)) {
+                        visited[ChunkSection::index(x, y, z)] = true;
+                        opaque_count += 1;
+                    }
+                }
+            }
+        }
+
+        if opaque_count == 0 {
+            return Self::ALL;
+        }
+
+        let mut visibility = Self::NONE;
+        let mut stack = Vec::new();
+        let last = SECTION_SIZE - 1;
+
+        for start in 0..SECTION_VOLUME {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+
+            let mut touched = [false; 6];
+            while let Some(index) = stack.pop() {
+                let x = index % SECTION_SIZE;
+                let z = (index / SECTION_SIZE) % SECTION_SIZE;
+                let y = index / (SECTION_SIZE * SECTION_SIZE);
+
+                touched[BlockFace::West as usize] |= x == 0;
+                touched[BlockFace::East as usize] |= x == last;
+                touched[BlockFace::Down as usize] |= y == 0;
+                touched[BlockFace::Up as usize] |= y == last;
+                touched[BlockFace::North as usize] |= z == 0;
+                touched[BlockFace::South as usize] |= z == last;
+
+                for face in BlockFace::ALL {
+                    let [dx, dy, dz] = face.normal();
+                    let (nx, ny, nz) = (x as i32 + dx, y as i32 + dy, z as i32 + dz);
+                    if [nx, ny, nz]
+                        .iter()
+                        .any(|c| *c < 0 || *c >= SECTION_SIZE as i32)
+                    {
+                        continue;
+                    }
+
+                    let neighbour = ChunkSection::index(nx as usize, ny as usize, nz as usize);
+                    if !visited[neighbour] {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+
+            for a in BlockFace::ALL {
+                for b in BlockFace::ALL {
+                    if touched[a as usize] && touched[b as usize] {
+                        visibility.connect(a, b);
+                    }
+                }
+            }
+        }
+
+        visibility
+    }
+}
+
+/// Walks the section graph outwards from the camera, only crossing a section between two of its
+/// faces if they are connected, never turning back towards the camera and never leaving the
+/// frustum. Sections for which `visibility` returns `None` are not loaded: they are walked
+/// through as if empty but never reported as visible.
+pub fn visible_sections(
+    camera: Point3<f32>,
+    frustum: &Frustum,
+    max_distance: i32,
+    visibility: impl Fn(SectionPos) -> Option<SectionVisibility>,
+) -> Vec<SectionPos> {
+    let start = SectionPos::of_block(
+        camera.x.floor() as i32,
+        camera.y.floor() as i32,
+        camera.z.floor() as i32,
+    );
+
+    let mut visible = Vec::new();
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, None::<BlockFace>, 0u8)]);
+
+    while let Some((pos, entered, directions)) = queue.pop_front() {
+        let connections = match visibility(pos) {
+            Some(connections) => {
+                visible.push(pos);
+                connections
+            }
+            None => SectionVisibility::ALL,
+        };
+
+        for face in BlockFace::ALL {
+            if directions & (1 << face.opposite() as u8) != 0 {
+                continue;
+            }
+            if let Some(entered) = entered {
+                if !connections.connects(entered, face) {
+                    continue;
+                }
+            }
+
+            let next = pos.offset(face);
+            if (next.x - start.x).abs() > max_distance
+                || (next.y - start.y).abs() > max_distance
+                || (next.z - start.z).abs() > max_distance
+            {
+                continue;
+            }
+            if !visited.insert(next) || !frustum.intersects(&Aabb::of_section(next)) {
+                continue;
+            }
+
+            queue.push_back((next, Some(face.opposite()), directions | (1 << face as u8)));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::block::AIR;
+
+    use super::*;
+    use crate::camera::Camera;
+
+    /// In the middle of section (0, 0, 0), looking towards -Z.
+    fn camera() -> Camera {
+        Camera::new(Point3::new(8.0, 8.0, 8.0), 1.0)
+    }
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    #[test]
+    fn frustum_planes_bound_the_view() {
+        let frustum = Frustum::from_matrix(camera().view_projection_matrix());
+        for plane in frustum.planes {
+            assert!((plane.normal.magnitude() - 1.0).abs() < 1e-4);
+            assert!(plane.signed_distance(Point3::new(8.0, 8.0, -10.0)) > 0.0);
+        }
+
+        // ahead, around the camera, behind it, off to the side and past the far plane
+        assert!(frustum.intersects(&aabb([7.0, 7.0, -11.0], [9.0, 9.0, -9.0])));
+        assert!(frustum.intersects(&aabb([7.0, 7.0, 7.0], [9.0, 9.0, 9.0])));
+        assert!(!frustum.intersects(&aabb([7.0, 7.0, 20.0], [9.0, 9.0, 22.0])));
+        assert!(!frustum.intersects(&aabb([-100.0, 7.0, -11.0], [-98.0, 9.0, -9.0])));
+        assert!(!frustum.intersects(&aabb([7.0, 7.0, -2000.0], [9.0, 9.0, -1998.0])));
+    }
+
+    fn stone(registry: &BlockRegistry) -> u16 {
+        registry.id("stone").unwrap()
+    }
+
+    #[test]
+    fn visibility_of_uniform_sections() {
+        let registry = BlockRegistry::default();
+        let empty = ChunkSection::filled(AIR);
+        let solid = ChunkSection::filled(stone(&registry));
+        let glass = ChunkSection::filled(registry.id("glass").unwrap());
+        assert_eq!(
+            SectionVisibility::compute(&empty, &registry),
+            SectionVisibility::ALL
+        );
+        assert_eq!(
+            SectionVisibility::compute(&solid, &registry),
+            SectionVisibility::NONE
+        );
+        assert_eq!(
+            SectionVisibility::compute(&glass, &registry),
+            SectionVisibility::ALL
+        );
+    }
+
+    #[test]
+    fn walls_split_sections() {
+        let registry = BlockRegistry::default();
+        let mut section = ChunkSection::new();
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                section.set(8, y, z, stone(&registry));
+            }
+        }
+
+        let visibility = SectionVisibility::compute(&section, &registry);
+        assert!(!visibility.connects(BlockFace::West, BlockFace::East));
+        assert!(visibility.connects(BlockFace::West, BlockFace::Up));
+        assert!(visibility.connects(BlockFace::East, BlockFace::Up));
+        assert!(visibility.connects(BlockFace::North, BlockFace::South));
+    }
+
+    #[test]
+    fn tunnels_connect_their_ends_only() {
+        let registry = BlockRegistry::default();
+        let mut section = ChunkSection::filled(stone(&registry));
+        for x in 0..SECTION_SIZE {
+            section.set(x, 8, 8, AIR);
+        }
+
+        let visibility = SectionVisibility::compute(&section, &registry);
+        assert!(visibility.connects(BlockFace::West, BlockFace::East));
+        assert!(visibility.connects(BlockFace::East, BlockFace::West));
+        assert!(!visibility.connects(BlockFace::West, BlockFace::Up));
+        assert!(!visibility.connects(BlockFace::Up, BlockFace::Down));
+    }
+
+    #[test]
+    fn sealed_rooms_connect_nothing() {
+        let registry = BlockRegistry::default();
+        let mut section = ChunkSection::filled(stone(&registry));
+        for y in 4..12 {
+            for z in 4..12 {
+                for x in 4..12 {
+                    section.set(x, y, z, AIR);
+                }
+            }
+        }
+        assert_eq!(
+            SectionVisibility::compute(&section, &registry),
+            SectionVisibility::NONE
+        );
+    }
+
+    fn visible(visibility: impl Fn(SectionPos) -> Option<SectionVisibility>) -> Vec<SectionPos> {
+        let camera = camera();
+        let frustum = Frustum::from_matrix(camera.view_projection_matrix());
+        visible_sections(camera.position, &frustum, 3, visibility)
+    }
+
+    #[test]
+    fn open_worlds_are_visible_ahead_only() {
+        let visible = visible(|_| Some(SectionVisibility::ALL));
+        assert_eq!(visible[0], SectionPos::new(0, 0, 0));
+        assert!(visible.contains(&SectionPos::new(0, 0, -1)));
+        assert!(visible.contains(&SectionPos::new(0, 0, -3)));
+        assert!(!visible.contains(&SectionPos::new(0, 0, -4)));
+        assert!(!visible.contains(&SectionPos::new(0, 0, 1)));
+        assert!(visible.iter().all(|pos| pos.z <= 0));
+
+        let unique: HashSet<_> = visible.iter().collect();
+        assert_eq!(unique.len(), visible.len());
+    }
+
+    #[test]
+    fn solid_sections_hide_what_is_behind_them() {
+        let start = SectionPos::new(0, 0, 0);
+        let visible = visible(|pos| {
+            Some(if pos == start {
+                SectionVisibility::ALL
+            } else {
+                SectionVisibility::NONE
+            })
+        });
+        assert!(visible.contains(&SectionPos::new(0, 0, -1)));
+        assert!(!visible.contains(&SectionPos::new(0, 0, -2)));
+    }
+
+    #[test]
+    fn unloaded_sections_are_seen_through() {
+        let unloaded = SectionPos::new(0, 0, -1);
+        let visible = visible(|pos| (pos != unloaded).then_some(SectionVisibility::ALL));
+        assert!(!visible.contains(&unloaded));
+        assert!(visible.contains(&SectionPos::new(0, 0, -2)));
+    }
+}
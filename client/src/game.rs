@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::io;
+
+use shared::error::NetworkError;
+use shared::movement;
+use shared::protocol::{
+    sanitize_chat, ChatMessage, PacketAction, ReliablePacket, UnreliablePacket,
+};
+use tracing::{info, warn};
+
+use crate::networking::{ServerConnection, TcpEvent};
+use crate::world::ClientWorld;
+
+/// Chat messages kept around, the oldest being dropped first.
+const MAX_CHAT_HISTORY: usize = 100;
+
+pub struct Player {
+    id: u32,
+    pub movement: PlayerMovement,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PlayerMovement {
+    /// Left, forward, right, backward, sneak and jump.
+    pub directions: [bool; 6],
+    /// Yaw and pitch in degrees.
+    pub rotations: [f32; 2],
+    pub position: [f32; 3],
+}
+
+impl PlayerMovement {
+    /// Moves the player as its directions say for `dt` seconds, ahead of the server hearing
+    /// about it.
+    pub fn predict(&mut self, dt: f32) {
+        self.position = movement::step(self.position, self.directions, self.rotations[0], dt);
+    }
+}
+
+impl Player {
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            movement: PlayerMovement::default(),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Ways to complete a command, answering `GameState::request_completion`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suggestions {
+    pub id: u32,
+    /// Character of the command, without the leading `/`, the suggestions replace from.
+    pub start: usize,
+    pub suggestions: Vec<String>,
+}
+
+/// Everything the client simulates, independent of any window or GPU.
+pub struct GameState {
+    pub player: Player,
+    pub world: ClientWorld,
+    pub connection: ServerConnection,
+    chat: VecDeque<ChatMessage>,
+    completion_id: u32,
+    suggestions: Option<Suggestions>,
+    disconnect_reason: Option<String>,
+}
+
+impl GameState {
+    pub fn new(connection: ServerConnection) -> Self {
+        Self {
+            player: Player::new(connection.player_id),
+            world: ClientWorld::new(),
+            connection,
+            chat: VecDeque::new(),
+            completion_id: 0,
+            suggestions: None,
+            disconnect_reason: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.disconnect_reason.is_none()
+    }
+
+    pub fn disconnect_reason(&self) -> Option<&str> {
+        self.disconnect_reason.as_deref()
+    }
+
+    /// Fails once the connection is lost, `receive_packets` then tells why.
+    pub fn send_packet_action(&self, packet_action: PacketAction) -> Result<(), NetworkError> {
+        self.connection
+            .packet_action_sender
+            .send(packet_action)
+            .map_err(|_| {
+                NetworkError::ConnectionLost(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "the connection is closed",
+                ))
+            })
+    }
+
+    pub fn send_reliable_packet(
+        &self,
+        reliable_packet: ReliablePacket,
+    ) -> Result<(), NetworkError> {
+        self.send_packet_action(PacketAction::Reliable(reliable_packet))
+    }
+
+    pub fn send_unreliable_packet(
+        &self,
+        unreliable_packet: UnreliablePacket,
+    ) -> Result<(), NetworkError> {
+        self.send_packet_action(PacketAction::Unreliable(unreliable_packet))
+    }
+
+    pub fn send_movement(&self) -> Result<(), NetworkError> {
+        let movement = self.player.movement;
+        self.send_reliable_packet(ReliablePacket::MovementInput {
+            directions: movement.directions,
+            rotations: movement.rotations,
+            position: movement.position,
+        })
+    }
+
+    /// Sends `text` as a chat message, or as a command if it starts with `/`, unless nothing
+    /// is left of it once sanitized.
+    pub fn send_chat(&self, text: &str) -> Result<(), NetworkError> {
+        let Some(text) = sanitize_chat(text) else {
+            return Ok(());
+        };
+        match text.strip_prefix('/') {
+            Some(input) => self.send_reliable_packet(ReliablePacket::RunCommand {
+                input: input.to_string(),
+            }),
+            None => self.send_reliable_packet(ReliablePacket::ChatSend { text }),
+        }
+    }
+
+    /// Asks the server how the command `input`, without the leading `/`, could go on. Returns
+    /// the id the answer will carry.
+    pub fn request_completion(&mut self, input: &str) -> Result<u32, NetworkError> {
+        self.completion_id = self.completion_id.wrapping_add(1);
+        self.send_reliable_packet(ReliablePacket::CompleteCommand {
+            id: self.completion_id,
+            input: input.to_string(),
+        })?;
+        Ok(self.completion_id)
+    }
+
+    /// The latest completion the server answered with.
+    pub fn suggestions(&self) -> Option<&Suggestions> {
+        self.suggestions.as_ref()
+    }
+
+    /// The last `MAX_CHAT_HISTORY` chat messages, oldest first.
+    pub fn chat(&self) -> impl ExactSizeIterator<Item = &ChatMessage> {
+        self.chat.iter()
+    }
+
+    pub fn receive_packets(&mut self) {
+        while let Ok(event) = self.connection.tcp_receiver.try_recv() {
+            match event {
+                TcpEvent::PacketReceived { packet } => self.handle_packet(packet),
+                // the stream also ends after the server said why it disconnected us
+                TcpEvent::Disconnected { reason } if self.disconnect_reason.is_none() => {
+                    info!("disconnected: {reason}");
+                    self.disconnect_reason = Some(reason);
+                }
+                TcpEvent::Disconnected { .. } => {}
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: ReliablePacket) {
+        match packet {
+            ReliablePacket::Disconnect { reason } => {
+                info!("disconnected: {reason}");
+                self.disconnect_reason = Some(reason.to_string());
+            }
+            ReliablePacket::ChatBroadcast { message } => {
+                info!("{message}");
+                if self.chat.len() == MAX_CHAT_HISTORY {
+                    self.chat.pop_front();
+                }
+                self.chat.push_back(message);
+            }
+            ReliablePacket::CommandSuggestions {
+                id,
+                start,
+                suggestions,
+            } => {
+                self.suggestions = Some(Suggestions {
+                    id,
+                    start: start as usize,
+                    suggestions,
+                });
+            }
+            ReliablePacket::Teleport { position } => {
+                self.player.movement.position = position;
+            }
+            packet => warn!("server sent an unexpected packet: {packet:?}"),
+        }
+    }
+}
@@ -1,33 +1,76 @@
-use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
 use imgui::Ui;
 use wgpu::RenderPass;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
 use winit::event_loop::{ControlFlow, EventLoop};
 
+use crate::input::Binding;
 use crate::state::ClientState;
 use crate::ui_renderer::UiRenderer;
 
+const MOUSE_BUTTONS: usize = 16;
+
+/// Pixels of a touchpad scroll counted as one line of a mouse wheel.
+const PIXELS_PER_LINE: f64 = 20.0;
+
 #[derive(Copy, Clone, PartialEq)]
 enum KeyState {
     Pressed,
     Released,
 }
 
+fn mouse_button_index(button: MouseButton) -> Option<usize> {
+    let index = match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Other(n) => 3 + n as usize,
+    };
+    (index < MOUSE_BUTTONS).then_some(index)
+}
+
 pub struct FrameContext {
     is_fixed: bool,
     delta_time: Duration,
     fixed_delta_time: Duration,
     key_states: [(KeyState, u64); 163],
+    mouse_button_states: [(KeyState, u64); MOUSE_BUTTONS],
+    mouse_delta: [f64; 2],
+    scroll_delta: f32,
+    text_input: String,
+    last_pressed: Option<(Binding, u64)>,
     frame: u64,
 }
 
 impl FrameContext {
+    pub fn new() -> Self {
+        Self {
+            is_fixed: true,
+            delta_time: Duration::new(0, 0),
+            fixed_delta_time: Duration::new(0, 0),
+            key_states: [(KeyState::Released, 0); 163],
+            mouse_button_states: [(KeyState::Released, 0); MOUSE_BUTTONS],
+            mouse_delta: [0.0; 2],
+            scroll_delta: 0.0,
+            text_input: String::new(),
+            last_pressed: None,
+            frame: 1,
+        }
+    }
+
     pub fn is_fixed(&self) -> bool {
         self.is_fixed
     }
 
+    /// Time since the previous frame.
+    pub fn delta_time(&self) -> Duration {
+        self.delta_time
+    }
+
     pub fn just_pressed(&self, keycode: VirtualKeyCode) -> bool {
         self.key_states[keycode as usize] == (KeyState::Pressed, self.frame)
     }
@@ -36,12 +79,85 @@ impl FrameContext {
         self.key_states[keycode as usize].0 == KeyState::Pressed
     }
 
-    pub fn just_released(&self, keycode: VirtualKeyCode) -> bool {
-        self.key_states[keycode as usize] == (KeyState::Released, self.frame)
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        mouse_button_index(button)
+            .map(|i| self.mouse_button_states[i].0 == KeyState::Pressed)
+            .unwrap_or(false)
+    }
+
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        mouse_button_index(button)
+            .map(|i| self.mouse_button_states[i] == (KeyState::Pressed, self.frame))
+            .unwrap_or(false)
+    }
+
+    /// Raw mouse motion accumulated since the previous frame.
+    pub fn mouse_delta(&self) -> [f64; 2] {
+        self.mouse_delta
+    }
+
+    /// Scroll accumulated since the previous frame, in lines. Positive values scroll up.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Characters typed since the previous frame, for the UI's text fields.
+    pub fn text_input(&self) -> &str {
+        &self.text_input
+    }
+
+    /// The key, mouse button or scroll direction first pressed this frame, if any.
+    pub fn just_pressed_binding(&self) -> Option<Binding> {
+        self.last_pressed
+            .filter(|(_, frame)| *frame == self.frame)
+            .map(|(binding, _)| binding)
     }
 
-    pub fn released(&self, keycode: VirtualKeyCode) -> bool {
-        self.key_states[keycode as usize].0 == KeyState::Released
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => self.handle_keyboard_event(*input),
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.handle_mouse_input(*state, *button)
+            }
+            WindowEvent::MouseWheel { delta, .. } => self.handle_mouse_wheel(*delta),
+            WindowEvent::ReceivedCharacter(c) => {
+                if !c.is_control() {
+                    self.text_input.push(*c);
+                }
+            }
+            WindowEvent::Focused(false) => self.release_all(),
+            _ => {}
+        }
+    }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta[0] += delta.0;
+            self.mouse_delta[1] += delta.1;
+        }
+    }
+
+    /// Clears everything accumulated during the frame and moves on to the next one. Presses and
+    /// releases handled after this call count as happening in the new frame.
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = [0.0; 2];
+        self.scroll_delta = 0.0;
+        self.text_input.clear();
+        self.frame += 1;
+    }
+
+    /// Releases every key and button, as the window stops receiving their release events once
+    /// it loses focus.
+    fn release_all(&mut self) {
+        for state in self
+            .key_states
+            .iter_mut()
+            .chain(self.mouse_button_states.iter_mut())
+        {
+            if state.0 == KeyState::Pressed {
+                *state = (KeyState::Released, self.frame);
+            }
+        }
     }
 
     fn handle_keyboard_event(
@@ -58,6 +174,7 @@ impl FrameContext {
                     let state = &mut self.key_states[virtual_keycode as usize];
                     if state.0 == KeyState::Released {
                         *state = (KeyState::Pressed, self.frame);
+                        self.record_press(Binding::Key(virtual_keycode));
                     }
                 }
                 ElementState::Released => {
@@ -69,6 +186,50 @@ impl FrameContext {
             }
         }
     }
+
+    fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        let Some(index) = mouse_button_index(button) else {
+            return;
+        };
+
+        let current = &mut self.mouse_button_states[index];
+        match state {
+            ElementState::Pressed if current.0 == KeyState::Released => {
+                *current = (KeyState::Pressed, self.frame);
+                self.record_press(Binding::Mouse(button));
+            }
+            ElementState::Released if current.0 == KeyState::Pressed => {
+                *current = (KeyState::Released, self.frame);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => (position.y / PIXELS_PER_LINE) as f32,
+        };
+        self.scroll_delta += lines;
+
+        if lines > 0.0 {
+            self.record_press(Binding::ScrollUp);
+        } else if lines < 0.0 {
+            self.record_press(Binding::ScrollDown);
+        }
+    }
+
+    fn record_press(&mut self, binding: Binding) {
+        if self.just_pressed_binding().is_none() {
+            self.last_pressed = Some((binding, self.frame));
+        }
+    }
+}
+
+impl Default for FrameContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub fn client_game_loop(
@@ -76,17 +237,11 @@ pub fn client_game_loop(
     state: ClientState,
     on_update: fn(&mut FrameContext, &mut ClientState),
     on_ui: fn(&mut FrameContext, &mut ClientState, &mut Ui),
-    on_render: fn(&mut FrameContext, &mut ClientState, &mut RenderPass),
+    on_render: for<'a> fn(&mut FrameContext, &'a ClientState, &mut RenderPass<'a>),
     interval: Duration,
-) {
+) -> ! {
     let mut state = state;
-    let mut ctx = FrameContext {
-        is_fixed: true,
-        delta_time: Duration::new(0, 0),
-        fixed_delta_time: Duration::new(0, 0),
-        key_states: [(KeyState::Released, 0); 163],
-        frame: 1,
-    };
+    let mut ctx = FrameContext::new();
 
     let mut ui_renderer = UiRenderer::new(&state.window, &mut state.renderer);
 
@@ -106,55 +261,218 @@ pub fn client_game_loop(
                         WindowEvent::CloseRequested => {
                             *control_flow = ControlFlow::Exit;
                         }
-                        WindowEvent::KeyboardInput { input, .. } => {
-                            ctx.handle_keyboard_event(input)
-                        }
-                        _ => {}
+                        event => ctx.handle_window_event(&event),
                     }
                 }
             }
 
+            Event::DeviceEvent { event, .. } => ctx.handle_device_event(&event),
+
             Event::MainEventsCleared => {
                 state.window.request_redraw();
             }
 
-            Event::RedrawRequested(window_id) => {
-                if window_id == state.window.id() {
-                    let now = Instant::now();
-
-                    ctx.delta_time = last_tick.elapsed();
-                    last_tick = now;
+            Event::RedrawRequested(window_id) if window_id == state.window.id() => {
+                let now = Instant::now();
 
-                    if last_fixed_tick.elapsed() > interval {
-                        ctx.is_fixed = true;
-                        ctx.fixed_delta_time = last_fixed_tick.elapsed();
-                        last_fixed_tick = now;
-                    } else {
-                        ctx.is_fixed = false;
-                    }
+                ctx.delta_time = last_tick.elapsed();
+                last_tick = now;
 
-                    on_update(&mut ctx, &mut state);
+                if last_fixed_tick.elapsed() > interval {
+                    ctx.is_fixed = true;
+                    ctx.fixed_delta_time = last_fixed_tick.elapsed();
+                    last_fixed_tick = now;
+                } else {
+                    ctx.is_fixed = false;
+                }
 
-                    let output = state.renderer.get_output();
-                    let view = state.renderer.create_texture_view(&output);
-                    let mut command_encoder = state.renderer.create_command_encoder();
+                on_update(&mut ctx, &mut state);
 
-                    {
-                        let mut render_pass = state
-                            .renderer
-                            .create_render_pass(&mut command_encoder, &view);
+                let output = state.renderer.get_output();
+                let view = state.renderer.create_texture_view(&output);
+                let mut command_encoder = state.renderer.create_command_encoder();
 
-                        ui_renderer.render(&mut ctx, &mut state, &mut render_pass, on_ui);
+                {
+                    let mut render_pass = state
+                        .renderer
+                        .create_render_pass(&mut command_encoder, &view);
 
-                        on_render(&mut ctx, &mut state, &mut render_pass);
-                    }
+                    on_render(&mut ctx, &state, &mut render_pass);
+                }
 
-                    state.renderer.present(output, command_encoder);
+                {
+                    let mut ui_render_pass = state
+                        .renderer
+                        .create_ui_render_pass(&mut command_encoder, &view);
 
-                    ctx.frame += 1;
+                    ui_renderer.render(&mut ctx, &mut state, &mut ui_render_pass, on_ui);
                 }
+
+                state.renderer.present(output, command_encoder);
+
+                ctx.end_frame();
             }
             _ => {}
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalPosition;
+    use winit::event::{DeviceId, ModifiersState};
+
+    use super::*;
+
+    // SAFETY: the id is only carried along in the events, never handed to the platform.
+    const DEVICE: DeviceId = unsafe { DeviceId::dummy() };
+
+    #[allow(deprecated)]
+    fn key(keycode: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: DEVICE,
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(keycode),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn mouse(button: MouseButton, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::MouseInput {
+            device_id: DEVICE,
+            state,
+            button,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn wheel(delta: MouseScrollDelta) -> WindowEvent<'static> {
+        WindowEvent::MouseWheel {
+            device_id: DEVICE,
+            delta,
+            phase: winit::event::TouchPhase::Moved,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[test]
+    fn keys_are_just_pressed_for_one_frame() {
+        let mut ctx = FrameContext::new();
+        ctx.handle_window_event(&key(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(ctx.just_pressed(VirtualKeyCode::W));
+        assert!(ctx.pressed(VirtualKeyCode::W));
+        assert!(!ctx.pressed(VirtualKeyCode::S));
+
+        // key repeat sends more presses, which must not count as new ones
+        ctx.end_frame();
+        ctx.handle_window_event(&key(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(!ctx.just_pressed(VirtualKeyCode::W));
+        assert!(ctx.pressed(VirtualKeyCode::W));
+
+        ctx.end_frame();
+        ctx.handle_window_event(&key(VirtualKeyCode::W, ElementState::Released));
+        assert!(!ctx.pressed(VirtualKeyCode::W));
+
+        // pressed again in the next frame, it is a new press
+        ctx.end_frame();
+        ctx.handle_window_event(&key(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(ctx.just_pressed(VirtualKeyCode::W));
+    }
+
+    #[test]
+    fn mouse_buttons_are_tracked() {
+        let mut ctx = FrameContext::new();
+        ctx.handle_window_event(&mouse(MouseButton::Left, ElementState::Pressed));
+        assert!(ctx.mouse_just_pressed(MouseButton::Left));
+        assert!(!ctx.mouse_pressed(MouseButton::Right));
+        assert_eq!(
+            ctx.just_pressed_binding(),
+            Some(Binding::Mouse(MouseButton::Left))
+        );
+
+        ctx.end_frame();
+        assert!(!ctx.mouse_just_pressed(MouseButton::Left));
+        assert!(ctx.mouse_pressed(MouseButton::Left));
+        assert_eq!(ctx.just_pressed_binding(), None);
+
+        ctx.handle_window_event(&mouse(MouseButton::Left, ElementState::Released));
+        assert!(!ctx.mouse_pressed(MouseButton::Left));
+
+        // buttons past those tracked are ignored rather than out of bounds
+        ctx.handle_window_event(&mouse(MouseButton::Other(100), ElementState::Pressed));
+        assert!(!ctx.mouse_pressed(MouseButton::Other(100)));
+    }
+
+    #[test]
+    fn scrolling_accumulates_until_the_frame_ends() {
+        let mut ctx = FrameContext::new();
+        ctx.handle_window_event(&wheel(MouseScrollDelta::LineDelta(0.0, 1.0)));
+        ctx.handle_window_event(&wheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(
+            0.0,
+            PIXELS_PER_LINE * 2.0,
+        ))));
+        assert_eq!(ctx.scroll_delta(), 3.0);
+        assert_eq!(ctx.just_pressed_binding(), Some(Binding::ScrollUp));
+
+        ctx.end_frame();
+        assert_eq!(ctx.scroll_delta(), 0.0);
+        ctx.handle_window_event(&wheel(MouseScrollDelta::LineDelta(0.0, -1.0)));
+        assert_eq!(ctx.just_pressed_binding(), Some(Binding::ScrollDown));
+    }
+
+    #[test]
+    fn the_first_press_of_a_frame_is_the_binding() {
+        let mut ctx = FrameContext::new();
+        ctx.handle_window_event(&key(VirtualKeyCode::E, ElementState::Pressed));
+        ctx.handle_window_event(&mouse(MouseButton::Right, ElementState::Pressed));
+        assert_eq!(
+            ctx.just_pressed_binding(),
+            Some(Binding::Key(VirtualKeyCode::E))
+        );
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut ctx = FrameContext::new();
+        ctx.handle_window_event(&key(VirtualKeyCode::LShift, ElementState::Pressed));
+        ctx.handle_window_event(&mouse(MouseButton::Left, ElementState::Pressed));
+        ctx.end_frame();
+
+        ctx.handle_window_event(&WindowEvent::Focused(false));
+        assert!(!ctx.pressed(VirtualKeyCode::LShift));
+        assert!(!ctx.mouse_pressed(MouseButton::Left));
+
+        // the releases never arrive, but pressing again once focused counts
+        ctx.handle_window_event(&key(VirtualKeyCode::LShift, ElementState::Pressed));
+        assert!(ctx.just_pressed(VirtualKeyCode::LShift));
+    }
+
+    #[test]
+    fn typed_characters_are_kept_until_the_frame_ends() {
+        let mut ctx = FrameContext::new();
+        for c in ['h', 'é', '\u{8}', '!', '\r', '\u{7f}', '字'] {
+            ctx.handle_window_event(&WindowEvent::ReceivedCharacter(c));
+        }
+        // backspace, enter and delete are keys for the UI, not text
+        assert_eq!(ctx.text_input(), "hé!字");
+
+        ctx.end_frame();
+        assert_eq!(ctx.text_input(), "");
+    }
+
+    #[test]
+    fn mouse_motion_accumulates_until_the_frame_ends() {
+        let mut ctx = FrameContext::new();
+        ctx.handle_device_event(&DeviceEvent::MouseMotion { delta: (1.0, 2.0) });
+        ctx.handle_device_event(&DeviceEvent::MouseMotion { delta: (3.0, -1.0) });
+        assert_eq!(ctx.mouse_delta(), [4.0, 1.0]);
+        ctx.end_frame();
+        assert_eq!(ctx.mouse_delta(), [0.0, 0.0]);
+    }
+}
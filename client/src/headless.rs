@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use shared::protocol::Credentials;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::game::{GameState, PlayerMovement};
+use crate::known_hosts::KnownHosts;
+use crate::networking::{connect, ConnectError};
+
+/// Drives a headless client's input, one movement per tick. Only the directions and rotations
+/// are used, the client moves the player from them as the windowed one does.
+pub trait InputScript {
+    fn movement(&mut self, tick: u64, game: &GameState) -> PlayerMovement;
+}
+
+impl<F> InputScript for F
+where
+    F: FnMut(u64, &GameState) -> PlayerMovement,
+{
+    fn movement(&mut self, tick: u64, game: &GameState) -> PlayerMovement {
+        self(tick, game)
+    }
+}
+
+/// Replays a list of movements, each held for a number of ticks, then stands still or starts
+/// over.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedMovement {
+    steps: Vec<(PlayerMovement, u64)>,
+    repeat: bool,
+}
+
+impl ScriptedMovement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, movement: PlayerMovement, ticks: u64) -> Self {
+        self.steps.push((movement, ticks));
+        self
+    }
+
+    pub fn repeat(mut self) -> Self {
+        self.repeat = true;
+        self
+    }
+}
+
+impl InputScript for ScriptedMovement {
+    fn movement(&mut self, tick: u64, _game: &GameState) -> PlayerMovement {
+        let total: u64 = self.steps.iter().map(|(_, ticks)| ticks).sum();
+        if total == 0 {
+            return PlayerMovement::default();
+        }
+
+        let mut tick = if self.repeat { tick % total } else { tick };
+        for (movement, ticks) in &self.steps {
+            if tick < *ticks {
+                return *movement;
+            }
+            tick -= ticks;
+        }
+        PlayerMovement::default()
+    }
+}
+
+/// A client without window or renderer, running the same update logic as the windowed one.
+pub struct HeadlessClient<S> {
+    game: GameState,
+    script: S,
+    tick: u64,
+    /// The first tick moves the player nowhere, however long after connecting it comes.
+    ticked_at: Option<Instant>,
+}
+
+impl<S: InputScript> HeadlessClient<S> {
+    pub async fn connect(
+        address: SocketAddr,
+        username: &str,
+        script: S,
+    ) -> Result<Self, ConnectError> {
+        let known_hosts = Arc::new(Mutex::new(KnownHosts::in_memory()));
+        let connection = connect(
+            address,
+            "localhost",
+            username,
+            Credentials::None,
+            known_hosts,
+        )
+        .await?;
+        Ok(Self {
+            game: GameState::new(connection),
+            script,
+            tick: 0,
+            ticked_at: None,
+        })
+    }
+
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    pub fn game_mut(&mut self) -> &mut GameState {
+        &mut self.game
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn tick(&mut self) {
+        self.game.receive_packets();
+        if !self.game.is_connected() {
+            return;
+        }
+
+        let now = Instant::now();
+        let dt = self
+            .ticked_at
+            .map_or(0.0, |ticked_at| now.duration_since(ticked_at).as_secs_f32());
+        self.ticked_at = Some(now);
+
+        let input = self.script.movement(self.tick, &self.game);
+        let movement = &mut self.game.player.movement;
+        movement.directions = input.directions;
+        movement.rotations = input.rotations;
+        movement.predict(dt);
+        if self.game.send_movement().is_err() {
+            return;
+        }
+        self.tick += 1;
+    }
+
+    /// Ticks `ticks` times, `period` apart, stopping early if the connection is lost.
+    pub async fn run(&mut self, ticks: u64, period: Duration) {
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        for _ in 0..ticks {
+            interval.tick().await;
+            self.tick();
+            if !self.game.is_connected() {
+                return;
+            }
+        }
+    }
+
+    pub fn disconnect(self) {
+        self.game.connection.close();
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use imgui::{Condition, StyleColor, Ui};
+use serde::{Deserialize, Serialize};
+use shared::toml_file::{self, TomlFileError};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::game_loop::FrameContext;
+use crate::state::ClientState;
+
+pub const BINDINGS_PATH: &str = "bindings.toml";
+
+const CONFLICT_COLOR: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sneak,
+    Attack,
+    Use,
+    Inventory,
+    HotbarNext,
+    HotbarPrevious,
+    ToggleCursor,
+    OpenChat,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Sneak,
+        Action::Attack,
+        Action::Use,
+        Action::Inventory,
+        Action::HotbarNext,
+        Action::HotbarPrevious,
+        Action::ToggleCursor,
+        Action::OpenChat,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Jump => "Jump",
+            Action::Sneak => "Sneak",
+            Action::Attack => "Attack",
+            Action::Use => "Use",
+            Action::Inventory => "Inventory",
+            Action::HotbarNext => "Next hotbar slot",
+            Action::HotbarPrevious => "Previous hotbar slot",
+            Action::ToggleCursor => "Toggle cursor",
+            Action::OpenChat => "Open chat",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    ScrollUp,
+    ScrollDown,
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{key:?}"),
+            Binding::Mouse(MouseButton::Other(n)) => write!(f, "Mouse {n}"),
+            Binding::Mouse(button) => write!(f, "{button:?} Mouse"),
+            Binding::ScrollUp => write!(f, "Scroll Up"),
+            Binding::ScrollDown => write!(f, "Scroll Down"),
+        }
+    }
+}
+
+impl Binding {
+    pub fn pressed(self, ctx: &FrameContext) -> bool {
+        match self {
+            Binding::Key(key) => ctx.pressed(key),
+            Binding::Mouse(button) => ctx.mouse_pressed(button),
+            Binding::ScrollUp => ctx.scroll_delta() > 0.0,
+            Binding::ScrollDown => ctx.scroll_delta() < 0.0,
+        }
+    }
+
+    pub fn just_pressed(self, ctx: &FrameContext) -> bool {
+        match self {
+            Binding::Key(key) => ctx.just_pressed(key),
+            Binding::Mouse(button) => ctx.mouse_just_pressed(button),
+            Binding::ScrollUp | Binding::ScrollDown => self.pressed(ctx),
+        }
+    }
+}
+
+/// The bindings of every action. An action may have several bindings and is active when any of
+/// them is.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub fn load(path: &Path) -> Result<Self, TomlFileError> {
+        let mut map: InputMap = toml_file::load(path)?;
+
+        // actions added after the file was written keep their defaults.
+        for (action, bindings) in InputMap::default().bindings {
+            map.bindings.entry(action).or_insert(bindings);
+        }
+        Ok(map)
+    }
+
+    /// Loads the bindings at `path`, falling back to the defaults if the file is missing or
+    /// invalid. An invalid file is moved aside, so saving the defaults does not overwrite it.
+    pub fn load_or_default(path: &Path) -> Self {
+        toml_file::load_or_default(path, Self::load)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TomlFileError> {
+        toml_file::save(path, self)
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|other| *other != binding);
+        }
+    }
+
+    /// Every binding shared by more than one action, with the actions sharing it.
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<Action>)> {
+        let mut conflicts: Vec<(Binding, Vec<Action>)> = Vec::new();
+        for (action, bindings) in &self.bindings {
+            for binding in bindings {
+                match conflicts.iter_mut().find(|(other, _)| other == binding) {
+                    Some((_, actions)) => actions.push(*action),
+                    None => conflicts.push((*binding, vec![*action])),
+                }
+            }
+        }
+        conflicts.retain(|(_, actions)| actions.len() > 1);
+        conflicts
+    }
+
+    pub fn is_conflicting(&self, binding: Binding) -> bool {
+        self.bindings
+            .values()
+            .filter(|bindings| bindings.contains(&binding))
+            .count()
+            > 1
+    }
+
+    pub fn pressed(&self, ctx: &FrameContext, action: Action) -> bool {
+        self.bindings(action).iter().any(|b| b.pressed(ctx))
+    }
+
+    pub fn just_pressed(&self, ctx: &FrameContext, action: Action) -> bool {
+        self.bindings(action).iter().any(|b| b.just_pressed(ctx))
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = [
+            (Action::MoveForward, Binding::Key(VirtualKeyCode::W)),
+            (Action::MoveBackward, Binding::Key(VirtualKeyCode::S)),
+            (Action::MoveLeft, Binding::Key(VirtualKeyCode::A)),
+            (Action::MoveRight, Binding::Key(VirtualKeyCode::D)),
+            (Action::Jump, Binding::Key(VirtualKeyCode::Space)),
+            (Action::Sneak, Binding::Key(VirtualKeyCode::LShift)),
+            (Action::Attack, Binding::Mouse(MouseButton::Left)),
+            (Action::Use, Binding::Mouse(MouseButton::Right)),
+            (Action::Inventory, Binding::Key(VirtualKeyCode::E)),
+            (Action::HotbarNext, Binding::ScrollDown),
+            (Action::HotbarPrevious, Binding::ScrollUp),
+            (Action::ToggleCursor, Binding::Key(VirtualKeyCode::Escape)),
+            (Action::OpenChat, Binding::Key(VirtualKeyCode::T)),
+        ];
+
+        let mut map = Self {
+            bindings: BTreeMap::new(),
+        };
+        for (action, binding) in bindings {
+            map.bind(action, binding);
+        }
+        map
+    }
+}
+
+/// State of the keybinding editor window.
+#[derive(Default)]
+pub struct BindingEditor {
+    capturing: Option<Action>,
+    status: Option<String>,
+}
+
+impl BindingEditor {
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+}
+
+pub fn keybinding_editor(ctx: &mut FrameContext, state: &mut ClientState, ui: &mut Ui) {
+    let ClientState {
+        input_map,
+        binding_editor: editor,
+        ..
+    } = state;
+
+    if let Some(action) = editor.capturing {
+        if let Some(binding) = ctx.just_pressed_binding() {
+            input_map.bind(action, binding);
+            editor.capturing = None;
+        }
+    }
+
+    ui.window("Controls")
+        .size([360.0, 420.0], Condition::FirstUseEver)
+        .build(|| {
+            for action in Action::ALL {
+                let _id = ui.push_id_usize(action as usize);
+                ui.text(action.label());
+
+                let mut removed = None;
+                for binding in input_map.bindings(action) {
+                    ui.same_line();
+                    let label = binding.to_string();
+                    let _id = ui.push_id(&label);
+                    let _color = input_map
+                        .is_conflicting(*binding)
+                        .then(|| ui.push_style_color(StyleColor::Text, CONFLICT_COLOR));
+                    if ui.small_button(&label) {
+                        removed = Some(*binding);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Click to remove");
+                    }
+                }
+                if let Some(binding) = removed {
+                    input_map.unbind(action, binding);
+                }
+
+                ui.same_line();
+                let label = if editor.capturing == Some(action) {
+                    "press a key..."
+                } else {
+                    "+"
+                };
+                if ui.small_button(label) {
+                    editor.capturing = Some(action);
+                }
+            }
+
+            ui.separator();
+            for (binding, actions) in input_map.conflicts() {
+                let actions: Vec<_> = actions.iter().map(|a| a.label()).collect();
+                ui.text_colored(
+                    CONFLICT_COLOR,
+                    format!("{binding} is bound to {}", actions.join(", ")),
+                );
+            }
+
+            if ui.button("Save") {
+                editor.status = Some(match input_map.save(Path::new(BINDINGS_PATH)) {
+                    Ok(()) => format!("saved to {BINDINGS_PATH}"),
+                    Err(err) => format!("failed to save: {err}"),
+                });
+            }
+            ui.same_line();
+            if ui.button("Reset to defaults") {
+                *input_map = InputMap::default();
+                editor.capturing = None;
+            }
+            if let Some(status) = &editor.status {
+                ui.text_disabled(status);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use shared::toml_file::broken_path;
+
+    use super::*;
+
+    #[test]
+    fn bindings_shared_by_actions_conflict() {
+        let mut map = InputMap::default();
+        assert!(map.conflicts().is_empty());
+        assert!(!map.is_conflicting(Binding::Key(VirtualKeyCode::W)));
+
+        map.bind(Action::Jump, Binding::Key(VirtualKeyCode::W));
+        map.bind(Action::Sneak, Binding::Key(VirtualKeyCode::W));
+        map.bind(Action::Use, Binding::Mouse(MouseButton::Left));
+        assert_eq!(
+            map.conflicts(),
+            [
+                (
+                    Binding::Key(VirtualKeyCode::W),
+                    vec![Action::MoveForward, Action::Jump, Action::Sneak]
+                ),
+                (
+                    Binding::Mouse(MouseButton::Left),
+                    vec![Action::Attack, Action::Use]
+                ),
+            ]
+        );
+        assert!(map.is_conflicting(Binding::Key(VirtualKeyCode::W)));
+        assert!(map.is_conflicting(Binding::Mouse(MouseButton::Left)));
+        assert!(!map.is_conflicting(Binding::Key(VirtualKeyCode::Space)));
+
+        // binding twice to the same action is no conflict
+        map.unbind(Action::Jump, Binding::Key(VirtualKeyCode::W));
+        map.unbind(Action::Sneak, Binding::Key(VirtualKeyCode::W));
+        map.bind(Action::MoveForward, Binding::Key(VirtualKeyCode::W));
+        assert_eq!(map.bindings(Action::MoveForward).len(), 1);
+        assert!(!map.is_conflicting(Binding::Key(VirtualKeyCode::W)));
+        assert_eq!(map.conflicts().len(), 1);
+    }
+
+    #[test]
+    fn actions_missing_from_the_file_keep_their_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(BINDINGS_PATH);
+        let saved = InputMap {
+            bindings: BTreeMap::from([
+                (Action::MoveForward, vec![Binding::Key(VirtualKeyCode::Up)]),
+                (Action::Jump, Vec::new()),
+            ]),
+        };
+        saved.save(&path).unwrap();
+
+        let map = InputMap::load(&path).unwrap();
+        assert_eq!(
+            map.bindings(Action::MoveForward),
+            [Binding::Key(VirtualKeyCode::Up)]
+        );
+        assert!(map.bindings(Action::Jump).is_empty());
+        for action in Action::ALL {
+            if ![Action::MoveForward, Action::Jump].contains(&action) {
+                assert_eq!(
+                    map.bindings(action),
+                    InputMap::default().bindings(action),
+                    "{action:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_files_are_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(BINDINGS_PATH);
+        fs::write(&path, "MoveForward = 3").unwrap();
+
+        let map = InputMap::load_or_default(&path);
+        assert_eq!(map, InputMap::default());
+        map.save(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(broken_path(&path)).unwrap(),
+            "MoveForward = 3"
+        );
+    }
+}
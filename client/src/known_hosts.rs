@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use ring::digest::{digest, SHA256};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+pub const KNOWN_HOSTS_PATH: &str = "known_hosts.toml";
+
+/// SHA-256 of a certificate, shown as colon separated hex.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    pub fn of(certificate: &Certificate) -> Self {
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(digest(&SHA256, &certificate.0).as_ref());
+        Self(bytes)
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex: String = s.chars().filter(|c| *c != ':').collect();
+        if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(format!("invalid fingerprint \"{s}\""));
+        }
+
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("invalid fingerprint \"{s}\""))?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl TryFrom<String> for Fingerprint {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Fingerprint> for String {
+    fn from(fingerprint: Fingerprint) -> Self {
+        fingerprint.to_string()
+    }
+}
+
+/// A server presenting a different certificate than the one pinned for its address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertificateMismatch {
+    pub host: String,
+    pub expected: Fingerprint,
+    pub actual: Fingerprint,
+}
+
+impl Display for CertificateMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the certificate of {} changed since the last connection (expected {}, got {}). \
+             Someone may be impersonating the server; forget the old certificate only if its \
+             owner confirms it was replaced",
+            self.host, self.expected, self.actual
+        )
+    }
+}
+
+/// The certificate fingerprint trusted for each server address, pinned the first time the
+/// client connects to it.
+#[derive(Debug, Default)]
+pub struct KnownHosts {
+    path: Option<PathBuf>,
+    hosts: BTreeMap<String, Fingerprint>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct KnownHostsFile {
+    #[serde(default)]
+    hosts: BTreeMap<String, Fingerprint>,
+}
+
+impl KnownHosts {
+    /// Known hosts that are forgotten when dropped.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads the known hosts at `path`, starting with none if the file is missing. Newly
+    /// trusted hosts are saved back there.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let hosts = match fs::read_to_string(path) {
+            Ok(content) => {
+                toml::from_str::<KnownHostsFile>(&content)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+                    .hosts
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            hosts,
+        })
+    }
+
+    pub fn get(&self, host: &str) -> Option<Fingerprint> {
+        self.hosts.get(host).copied()
+    }
+
+    pub fn trust(&mut self, host: &str, fingerprint: Fingerprint) {
+        self.hosts.insert(host.to_string(), fingerprint);
+        self.save();
+    }
+
+    pub fn forget(&mut self, host: &str) {
+        if self.hosts.remove(host).is_some() {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let file = KnownHostsFile {
+            hosts: self.hosts.clone(),
+        };
+        let result = toml::to_string_pretty(&file)
+            .map_err(io::Error::other)
+            .and_then(|content| fs::write(path, content));
+        if let Err(err) = result {
+            warn!("failed to save {}: {err}", path.display());
+        }
+    }
+}
+
+/// Verifies the server's certificate against the fingerprint pinned for `host`, pinning it if
+/// there is none yet.
+pub(crate) struct TrustOnFirstUse {
+    host: String,
+    known_hosts: Arc<Mutex<KnownHosts>>,
+    mismatch: Mutex<Option<CertificateMismatch>>,
+}
+
+impl TrustOnFirstUse {
+    pub(crate) fn new(host: String, known_hosts: Arc<Mutex<KnownHosts>>) -> Arc<Self> {
+        Arc::new(Self {
+            host,
+            known_hosts,
+            mismatch: Mutex::new(None),
+        })
+    }
+
+    /// The reason the certificate was refused, if it was.
+    pub(crate) fn take_mismatch(&self) -> Option<CertificateMismatch> {
+        self.mismatch.lock().unwrap().take()
+    }
+}
+
+impl ServerCertVerifier for TrustOnFirstUse {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let actual = Fingerprint::of(end_entity);
+        let mut known_hosts = self.known_hosts.lock().unwrap();
+
+        match known_hosts.get(&self.host) {
+            Some(expected) if expected == actual => Ok(ServerCertVerified::assertion()),
+            Some(expected) => {
+                let mismatch = CertificateMismatch {
+                    host: self.host.clone(),
+                    expected,
+                    actual,
+                };
+                warn!("{mismatch}");
+                *self.mismatch.lock().unwrap() = Some(mismatch);
+                Err(rustls::Error::General(format!(
+                    "the certificate of {} changed",
+                    self.host
+                )))
+            }
+            None => {
+                info!("trusting {} with certificate {actual}", self.host);
+                known_hosts.trust(&self.host, actual);
+                Ok(ServerCertVerified::assertion())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn verify(
+        verifier: &TrustOnFirstUse,
+        certificate: &Certificate,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        verifier.verify_server_cert(
+            certificate,
+            &[],
+            &ServerName::try_from("localhost").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn fingerprints_round_trip_as_text() {
+        let fingerprint = Fingerprint::of(&Certificate(b"certificate".to_vec()));
+        let text = fingerprint.to_string();
+        assert_eq!(text.len(), 32 * 3 - 1);
+        assert_eq!(text.parse(), Ok(fingerprint));
+        assert_eq!(text.to_uppercase().parse(), Ok(fingerprint));
+        assert_eq!(text.replace(':', "").parse(), Ok(fingerprint));
+    }
+
+    #[test]
+    fn malformed_fingerprints_are_refused() {
+        let valid = "ab".repeat(32);
+        for invalid in [
+            String::new(),
+            "ab".repeat(31),
+            "ab".repeat(33),
+            format!("zz{}", &valid[2..]),
+            format!("+1{}", &valid[2..]),
+            // 64 bytes, but not 64 characters
+            format!("é{}", &valid[2..]),
+            format!("a{}", "é".repeat(31)) + "b",
+        ] {
+            assert!(invalid.parse::<Fingerprint>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn the_first_certificate_is_pinned_and_only_it_accepted() {
+        let known_hosts = Arc::new(Mutex::new(KnownHosts::in_memory()));
+        let original = Certificate(b"original".to_vec());
+        let impostor = Certificate(b"impostor".to_vec());
+
+        let verifier = TrustOnFirstUse::new("example.com:8080".into(), known_hosts.clone());
+        assert!(verify(&verifier, &original).is_ok());
+        assert_eq!(
+            known_hosts.lock().unwrap().get("example.com:8080"),
+            Some(Fingerprint::of(&original))
+        );
+        assert_eq!(verifier.take_mismatch(), None);
+
+        let verifier = TrustOnFirstUse::new("example.com:8080".into(), known_hosts.clone());
+        assert!(verify(&verifier, &original).is_ok());
+        assert_eq!(verifier.take_mismatch(), None);
+
+        let verifier = TrustOnFirstUse::new("example.com:8080".into(), known_hosts.clone());
+        assert!(verify(&verifier, &impostor).is_err());
+        assert_eq!(
+            verifier.take_mismatch(),
+            Some(CertificateMismatch {
+                host: "example.com:8080".into(),
+                expected: Fingerprint::of(&original),
+                actual: Fingerprint::of(&impostor),
+            })
+        );
+        assert_eq!(
+            known_hosts.lock().unwrap().get("example.com:8080"),
+            Some(Fingerprint::of(&original))
+        );
+
+        // other addresses are pinned separately
+        let verifier = TrustOnFirstUse::new("example.com:9090".into(), known_hosts);
+        assert!(verify(&verifier, &impostor).is_ok());
+    }
+
+    #[test]
+    fn trusted_hosts_are_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(KNOWN_HOSTS_PATH);
+        let first = Fingerprint::of(&Certificate(b"first".to_vec()));
+        let second = Fingerprint::of(&Certificate(b"second".to_vec()));
+
+        let mut known_hosts = KnownHosts::load(&path).unwrap();
+        assert_eq!(known_hosts.get("first:8080"), None);
+        known_hosts.trust("first:8080", first);
+        known_hosts.trust("second:8080", second);
+        known_hosts.forget("second:8080");
+
+        let known_hosts = KnownHosts::load(&path).unwrap();
+        assert_eq!(known_hosts.get("first:8080"), Some(first));
+        assert_eq!(known_hosts.get("second:8080"), None);
+
+        fs::write(&path, "[hosts]\n\"first:8080\" = \"nope\"\n").unwrap();
+        assert_eq!(
+            KnownHosts::load(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::debug;
+
+use shared::lan::{LanAnnouncement, LAN_DISCOVERY_GROUP, LAN_DISCOVERY_PORT};
+use shared::protocol::{ProtocolHello, VersionMismatch};
+
+use crate::networking::ServerAddress;
+
+/// How long a server stays listed after its last announcement.
+const LAN_SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A server announcing itself on the local network.
+#[derive(Clone, Debug)]
+pub struct LanServer {
+    pub address: ServerAddress,
+    pub version: u32,
+    /// Only known when the server speaks our protocol version.
+    pub announcement: Option<LanAnnouncement>,
+    last_seen: Instant,
+}
+
+impl LanServer {
+    pub fn compatibility(&self) -> Result<(), VersionMismatch> {
+        VersionMismatch::check(ProtocolHello::CURRENT.version, self.version)
+    }
+}
+
+/// Listens for server announcements, keeping the servers heard from recently.
+pub struct LanDiscovery {
+    socket: UdpSocket,
+    servers: BTreeMap<SocketAddr, LanServer>,
+}
+
+impl LanDiscovery {
+    /// Joins the discovery group on the default interface and on loopback, where servers bound
+    /// to the loopback address announce themselves. Other clients may listen at the same time.
+    pub fn bind() -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, LAN_DISCOVERY_PORT)).into())?;
+        socket.join_multicast_v4(&LAN_DISCOVERY_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        if let Err(err) = socket.join_multicast_v4(&LAN_DISCOVERY_GROUP, &Ipv4Addr::LOCALHOST) {
+            debug!("not listening for LAN servers on loopback: {err}");
+        }
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket: socket.into(),
+            servers: BTreeMap::new(),
+        })
+    }
+
+    /// Reads the announcements received since the last call and forgets the servers that went
+    /// quiet.
+    pub fn poll(&mut self) {
+        let now = Instant::now();
+        let mut buf = [0; 1024];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    debug!("failed to receive a LAN announcement: {err}");
+                    break;
+                }
+            };
+            let Some((hello, announcement)) = LanAnnouncement::from_datagram(&buf[..len]) else {
+                continue;
+            };
+
+            // Servers of other versions do not tell their port, the sender's is the best guess
+            // to tell them apart.
+            let port = announcement.as_ref().map_or(from.port(), |a| a.port);
+            let address = SocketAddr::new(from.ip(), port);
+            self.servers.insert(
+                address,
+                LanServer {
+                    address: ServerAddress::new(address.ip().to_string(), port),
+                    version: hello.version,
+                    announcement,
+                    last_seen: now,
+                },
+            );
+        }
+
+        self.servers
+            .retain(|_, server| now.duration_since(server.last_seen) < LAN_SERVER_TIMEOUT);
+    }
+
+    pub fn servers(&self) -> impl Iterator<Item = &LanServer> {
+        self.servers.values()
+    }
+}
@@ -0,0 +1,10 @@
+pub mod atlas;
+pub mod camera;
+pub mod culling;
+pub mod game;
+pub mod headless;
+pub mod known_hosts;
+pub mod lan;
+pub mod meshing;
+pub mod networking;
+pub mod world;
@@ -1,59 +1,194 @@
-#![feature(variant_count)]
-
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 use std::time::Duration;
 
+use cgmath::Point3;
+use clap::Parser;
+use client::atlas::AtlasBuilder;
+use client::camera::{Camera, CameraController};
+use client::known_hosts::{KnownHosts, KNOWN_HOSTS_PATH};
+use client::lan::LanDiscovery;
+use client::meshing::pool::MeshPool;
+use client::meshing::MeshingMode;
+use client::networking::ServerAddress;
 use imgui::{Condition, Ui};
+use shared::block::BlockRegistry;
+use tracing::warn;
 use wgpu::RenderPass;
+use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, WindowBuilder};
 
+use crate::chat::{chat_window, update_chat, Chat};
+use crate::config::{ClientConfig, CONFIG_PATH};
 use crate::game_loop::{client_game_loop, FrameContext};
-use crate::player::{send_player_movement_packet, update_player_movement, Player};
+use crate::input::{keybinding_editor, BindingEditor, InputMap, BINDINGS_PATH};
+use crate::player::{send_player_movement_packet, update_cursor_grab, update_player_movement};
 use crate::renderer::Renderer;
+use crate::server_browser::{
+    server_browser, update_connection, ServerBrowser, ServerList, SERVERS_PATH,
+};
 use crate::state::ClientState;
+use crate::world_renderer::{update_world_rendering, WorldRenderer};
 
+mod chat;
+mod config;
 mod game_loop;
-mod networking;
+mod input;
 mod player;
 mod renderer;
+mod server_browser;
 mod state;
 mod ui_renderer;
+mod world_renderer;
+
+const BLOCK_TEXTURES_DIRECTORY: &str = "assets/textures/block";
+const ATLAS_PADDING: u32 = 4;
+
+/// Command-line overrides for the values in the config file.
+#[derive(Parser, Debug)]
+#[command(name = "client")]
+struct Args {
+    /// Config file to read.
+    #[arg(long, default_value = CONFIG_PATH)]
+    config: PathBuf,
+    /// Server to join right away instead of showing the server browser, as host[:port].
+    #[arg(long)]
+    server: Option<ServerAddress>,
+    #[arg(long)]
+    username: Option<String>,
+    #[arg(long)]
+    width: Option<u32>,
+    #[arg(long)]
+    height: Option<u32>,
+    #[arg(long)]
+    fullscreen: Option<bool>,
+    #[arg(long)]
+    vsync: Option<bool>,
+    /// In sections, around the camera.
+    #[arg(long)]
+    render_distance: Option<i32>,
+}
+
+/// Environment variables overriding the credentials in the config file. They are not taken as
+/// flags, which anyone on the machine could read from the process list.
+const PASSWORD_VAR: &str = "RBMP_PASSWORD";
+const TOKEN_VAR: &str = "RBMP_TOKEN";
+
+impl Args {
+    fn apply(&self, config: &mut ClientConfig) {
+        if let Some(username) = &self.username {
+            config.username = username.clone();
+        }
+        if let Some(width) = self.width {
+            config.width = width;
+        }
+        if let Some(height) = self.height {
+            config.height = height;
+        }
+        if let Some(fullscreen) = self.fullscreen {
+            config.fullscreen = fullscreen;
+        }
+        if let Some(vsync) = self.vsync {
+            config.vsync = vsync;
+        }
+        if let Some(render_distance) = self.render_distance {
+            config.render_distance = render_distance;
+        }
+        if let Ok(password) = env::var(PASSWORD_VAR) {
+            config.password = Some(password);
+        }
+        if let Ok(token) = env::var(TOKEN_VAR) {
+            config.token = Some(token);
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let args = Args::parse();
     shared::tracing::init();
 
-    networking::init().await;
-
-    loop {}
-
-    // let event_loop = EventLoop::new();
-    // let window = WindowBuilder::new().build(&event_loop).unwrap();
-    //
-    // let renderer = Renderer::new(&window);
-    //
-    // let state = ClientState {
-    //     renderer,
-    //     player: Player::new(id),
-    //     window,
-    //     tcp_receiver,
-    //     udp_receiver,
-    //     packet_action_sender,
-    // };
-    //
-    // client_game_loop(
-    //     event_loop,
-    //     state,
-    //     update,
-    //     ui,
-    //     render,
-    //     Duration::from_millis(1000 / 30),
-    // );
+    let mut config = ClientConfig::load_or_default(&args.config);
+    args.apply(&mut config);
+    if let Err(reason) = config.validate() {
+        eprintln!("invalid config: {reason}");
+        return ExitCode::FAILURE;
+    }
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("rbmp")
+        .with_inner_size(PhysicalSize::new(config.width, config.height))
+        .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None)))
+        .build(&event_loop)
+        .unwrap();
+
+    let renderer = Renderer::new(&window, config.vsync);
+
+    let registry = Arc::new(BlockRegistry::default());
+    let atlas = AtlasBuilder::new(ATLAS_PADDING)
+        .add_block_textures(&registry, Path::new(BLOCK_TEXTURES_DIRECTORY))
+        .build()
+        .expect("block textures do not fit in the atlas");
+
+    let world_renderer = WorldRenderer::new(&renderer, &atlas, config.render_distance);
+    let mesh_pool = MeshPool::new(2, MeshingMode::Greedy, registry, Arc::new(atlas));
+
+    let known_hosts = match KnownHosts::load(Path::new(KNOWN_HOSTS_PATH)) {
+        Ok(known_hosts) => known_hosts,
+        Err(err) => {
+            eprintln!("failed to load {KNOWN_HOSTS_PATH}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let lan = LanDiscovery::bind()
+        .map_err(|err| warn!("not discovering LAN servers: {err}"))
+        .ok();
+    let mut server_browser = ServerBrowser::new(
+        ServerList::load_or_default(Path::new(SERVERS_PATH)),
+        known_hosts,
+        lan,
+    );
+    if let Some(address) = args.server {
+        server_browser.connect(address, config.username.clone(), config.credentials());
+    }
+
+    let state = ClientState {
+        camera: Camera::new(Point3::new(0.0, 80.0, 0.0), renderer.aspect()),
+        camera_controller: CameraController::default(),
+        cursor_grabbed: false,
+        input_map: InputMap::load_or_default(Path::new(BINDINGS_PATH)),
+        binding_editor: BindingEditor::default(),
+        mesh_pool,
+        world_renderer,
+        renderer,
+        window,
+        config,
+        server_browser,
+        chat: Chat::default(),
+        game: None,
+    };
+
+    client_game_loop(
+        event_loop,
+        state,
+        update,
+        ui,
+        render,
+        Duration::from_millis(1000 / 30),
+    )
 }
 
 fn update(ctx: &mut FrameContext, state: &mut ClientState) {
+    update_connection(ctx, state);
+    update_cursor_grab(ctx, state);
+    update_chat(ctx, state);
     update_player_movement(ctx, state);
     send_player_movement_packet(ctx, state);
+    update_world_rendering(ctx, state);
 }
 
 fn ui(ctx: &mut FrameContext, state: &mut ClientState, ui: &mut Ui) {
@@ -63,6 +198,15 @@ fn ui(ctx: &mut FrameContext, state: &mut ClientState, ui: &mut Ui) {
         .build(|| {
             ui.text("velho calvo");
         });
+
+    server_browser(ctx, state, ui);
+    chat_window(ctx, state, ui);
+
+    if !state.cursor_grabbed {
+        keybinding_editor(ctx, state, ui);
+    }
 }
 
-fn render(ctx: &mut FrameContext, state: &mut ClientState, ui: &mut RenderPass) {}
+fn render<'a>(_ctx: &mut FrameContext, state: &'a ClientState, render_pass: &mut RenderPass<'a>) {
+    state.world_renderer.draw(render_pass);
+}
@@ -0,0 +1,212 @@
+use shared::block::BlockFace;
+use shared::chunk::{SECTION_AREA, SECTION_SIZE};
+
+use crate::meshing::{face_axes, push_quad, FaceInfo, Mesh, MeshContext, TextureLookup};
+
+/// Merges coplanar visible faces sharing the same block, texture and light into larger quads.
+pub fn mesh_section<T: TextureLookup>(ctx: &MeshContext<T>) -> Mesh {
+    let mut mesh = Mesh::default();
+    let mut mask: [Option<FaceInfo>; SECTION_AREA] = [None; SECTION_AREA];
+
+    for face in BlockFace::ALL {
+        let (n, u, v, _) = face_axes(face);
+
+        for layer in 0..SECTION_SIZE {
+            for j in 0..SECTION_SIZE {
+                for i in 0..SECTION_SIZE {
+                    let mut pos = [0; 3];
+                    pos[n] = layer;
+                    pos[u] = i;
+                    pos[v] = j;
+                    mask[j * SECTION_SIZE + i] = ctx.visible_face(pos, face);
+                }
+            }
+
+            for j in 0..SECTION_SIZE {
+                let mut i = 0;
+                while i < SECTION_SIZE {
+                    let Some(info) = mask[j * SECTION_SIZE + i] else {
+                        i += 1;
+                        continue;
+                    };
+
+                    let mut width = 1;
+                    while i + width < SECTION_SIZE
+                        && mask[j * SECTION_SIZE + i + width] == Some(info)
+                    {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    'grow: while j + height < SECTION_SIZE {
+                        let row = (j + height) * SECTION_SIZE + i;
+                        for k in 0..width {
+                            if mask[row + k] != Some(info) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+
+                    for dj in 0..height {
+                        let row = (j + dj) * SECTION_SIZE + i;
+                        mask[row..row + width].fill(None);
+                    }
+
+                    let mut pos = [0; 3];
+                    pos[n] = layer;
+                    pos[u] = i;
+                    pos[v] = j;
+                    push_quad(&mut mesh, face, pos, width, height, info);
+
+                    i += width;
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use shared::block::{BlockRegistry, AIR};
+    use shared::chunk::{ChunkSection, MAX_LIGHT};
+
+    use super::*;
+    use crate::meshing::{naive, FullTexture, SectionNeighbours, UvRect};
+
+    /// Sparse enough for plenty of visible faces, with translucent blocks and varying light so
+    /// not every face can be merged.
+    fn random_section(rng: &mut StdRng, registry: &BlockRegistry) -> ChunkSection {
+        let blocks: Vec<_> = registry.blocks().map(|(id, _)| id).collect();
+        let density = rng.gen_range(0.1..0.9);
+        let mut section = ChunkSection::new();
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    if rng.gen_bool(density) {
+                        section.set(x, y, z, blocks[rng.gen_range(0..blocks.len())]);
+                    }
+                    section.set_light(x, y, z, rng.gen_range(MAX_LIGHT - 2..=MAX_LIGHT));
+                }
+            }
+        }
+        section
+    }
+
+    /// Gives every texture its own tile, so faces of different textures cannot be told apart
+    /// by their tile alone.
+    struct DistinctTextures;
+
+    impl TextureLookup for DistinctTextures {
+        fn uv_rect(&self, texture: &str) -> UvRect {
+            let mut hasher = DefaultHasher::new();
+            texture.hash(&mut hasher);
+            UvRect {
+                u: (hasher.finish() % 1024) as f32,
+                ..UvRect::FULL
+            }
+        }
+    }
+
+    /// A unit block face covered by a quad: its direction, the block it lies on, and the bits
+    /// of its tile and light.
+    type CoveredFace = (usize, [i32; 3], [u32; 4], u32);
+
+    /// Rasterises the quads of `mesh` into the unit faces they cover, asserting none is covered
+    /// twice.
+    fn covered_faces(mesh: &Mesh) -> HashSet<CoveredFace> {
+        let mut covered = HashSet::new();
+        for corners in mesh.vertices.chunks(4) {
+            let face = BlockFace::ALL
+                .into_iter()
+                .position(|face| face.normal().map(|c| c as f32) == corners[0].normal)
+                .unwrap();
+            let (n, u, v, _) = face_axes(BlockFace::ALL[face]);
+            let range = |axis: usize| {
+                let (min, max) = corners.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+                    (min.min(c.position[axis]), max.max(c.position[axis]))
+                });
+                min as i32..max as i32
+            };
+
+            // faces pointing the positive way lie on the far side of their block
+            let layer = corners[0].position[n] as i32 - (corners[0].normal[n] > 0.0) as i32;
+            for i in range(u) {
+                for j in range(v) {
+                    let mut block = [0; 3];
+                    block[n] = layer;
+                    block[u] = i;
+                    block[v] = j;
+                    let face = (
+                        face,
+                        block,
+                        corners[0].tile.map(f32::to_bits),
+                        corners[0].light.to_bits(),
+                    );
+                    assert!(covered.insert(face), "{face:?} is covered twice");
+                }
+            }
+        }
+        covered
+    }
+
+    #[test]
+    fn greedy_covers_the_same_faces_as_naive() {
+        let registry = BlockRegistry::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let section = random_section(&mut rng, &registry);
+            let east = random_section(&mut rng, &registry);
+            let mut neighbours = SectionNeighbours::default();
+            neighbours.set(BlockFace::East, &east);
+            let ctx = MeshContext {
+                section: &section,
+                neighbours,
+                registry: &registry,
+                textures: &DistinctTextures,
+            };
+
+            let naive = naive::mesh_section(&ctx);
+            let greedy = mesh_section(&ctx);
+            let (covered, expected) = (covered_faces(&greedy), covered_faces(&naive));
+            let missing: Vec<_> = expected.difference(&covered).take(3).collect();
+            let extra: Vec<_> = covered.difference(&expected).take(3).collect();
+            assert!(
+                missing.is_empty() && extra.is_empty(),
+                "missing {missing:?}, extra {extra:?}"
+            );
+            assert!(greedy.quad_count() <= naive.quad_count());
+        }
+    }
+
+    #[test]
+    fn solid_sections_merge_into_one_quad_per_face() {
+        let registry = BlockRegistry::default();
+        let section = ChunkSection::filled(registry.id("stone").unwrap());
+        let mesh = mesh_section(&MeshContext {
+            section: &section,
+            neighbours: SectionNeighbours::default(),
+            registry: &registry,
+            textures: &FullTexture,
+        });
+        assert_eq!(mesh.quad_count(), 6);
+        assert_eq!(covered_faces(&mesh).len(), 6 * SECTION_SIZE * SECTION_SIZE);
+
+        let empty = ChunkSection::filled(AIR);
+        let ctx = MeshContext {
+            section: &empty,
+            neighbours: SectionNeighbours::default(),
+            registry: &registry,
+            textures: &FullTexture,
+        };
+        assert!(mesh_section(&ctx).is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use shared::block::{BlockFace, BlockId, BlockRegistry, AIR};
+use shared::chunk::{ChunkSection, MAX_LIGHT, SECTION_SIZE};
+
+pub mod greedy;
+pub mod naive;
+pub mod pool;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// One quad per visible block face.
+    #[default]
+    Naive,
+    /// Coplanar faces with the same block, texture and light merged into larger quads.
+    Greedy,
+}
+
+pub fn mesh_section<T: TextureLookup>(mode: MeshingMode, ctx: &MeshContext<T>) -> Mesh {
+    match mode {
+        MeshingMode::Naive => naive::mesh_section(ctx),
+        MeshingMode::Greedy => greedy::mesh_section(ctx),
+    }
+}
+
+/// A single vertex of a chunk mesh.
+///
+/// `uv` is expressed in block units across the quad, so a quad spanning several blocks repeats
+/// its texture; `tile` is the `[u, v, width, height]` rect of that texture in the atlas.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub tile: [f32; 4],
+    pub light: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    pub u: f32,
+    pub v: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl UvRect {
+    pub const FULL: UvRect = UvRect {
+        u: 0.0,
+        v: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+}
+
+pub trait TextureLookup {
+    fn uv_rect(&self, texture: &str) -> UvRect;
+}
+
+/// Maps every texture onto the whole `[0, 1]` range, used when no atlas is available.
+pub struct FullTexture;
+
+impl TextureLookup for FullTexture {
+    fn uv_rect(&self, _texture: &str) -> UvRect {
+        UvRect::FULL
+    }
+}
+
+/// The sections bordering the one being meshed, indexed by [`BlockFace`].
+///
+/// Missing neighbours are treated as empty, so faces on that border are kept.
+#[derive(Copy, Clone, Default)]
+pub struct SectionNeighbours<'a> {
+    pub sections: [Option<&'a ChunkSection>; 6],
+}
+
+impl<'a> SectionNeighbours<'a> {
+    pub fn get(&self, face: BlockFace) -> Option<&'a ChunkSection> {
+        self.sections[face as usize]
+    }
+
+    pub fn set(&mut self, face: BlockFace, section: &'a ChunkSection) {
+        self.sections[face as usize] = Some(section);
+    }
+}
+
+pub struct MeshContext<'a, T: TextureLookup> {
+    pub section: &'a ChunkSection,
+    pub neighbours: SectionNeighbours<'a>,
+    pub registry: &'a BlockRegistry,
+    pub textures: &'a T,
+}
+
+impl<'a, T: TextureLookup> MeshContext<'a, T> {
+    /// Returns the block and light at section-local coordinates, which may step one block
+    /// outside the section into a neighbour.
+    fn sample(&self, x: i32, y: i32, z: i32) -> (BlockId, u8) {
+        let size = SECTION_SIZE as i32;
+        let face = if x < 0 {
+            Some(BlockFace::West)
+        } else if x >= size {
+            Some(BlockFace::East)
+        } else if y < 0 {
+            Some(BlockFace::Down)
+        } else if y >= size {
+            Some(BlockFace::Up)
+        } else if z < 0 {
+            Some(BlockFace::North)
+        } else if z >= size {
+            Some(BlockFace::South)
+        } else {
+            None
+        };
+
+        let section = match face {
+            None => self.section,
+            Some(face) => match self.neighbours.get(face) {
+                Some(section) => section,
+                None => return (AIR, MAX_LIGHT),
+            },
+        };
+
+        let (x, y, z) = (
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        );
+        (section.get(x, y, z), section.light(x, y, z))
+    }
+
+    /// Returns the visible face at `pos` facing `face`, or `None` when it is culled.
+    pub(crate) fn visible_face(&self, pos: [usize; 3], face: BlockFace) -> Option<FaceInfo> {
+        let block = self.section.get(pos[0], pos[1], pos[2]);
+        if block == AIR {
+            return None;
+        }
+
+        let [dx, dy, dz] = face.normal();
+        let (neighbour, light) =
+            self.sample(pos[0] as i32 + dx, pos[1] as i32 + dy, pos[2] as i32 + dz);
+
+        if self.registry.is_opaque(neighbour) {
+            return None;
+        }
+        if neighbour == block && !self.registry.is_opaque(block) {
+            return None;
+        }
+
+        let texture = self
+            .registry
+            .get(block)
+            .map(|block| block.texture(face))
+            .unwrap_or("");
+
+        Some(FaceInfo {
+            block,
+            tile: self.textures.uv_rect(texture),
+            light,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct FaceInfo {
+    pub block: BlockId,
+    pub tile: UvRect,
+    pub light: u8,
+}
+
+/// Returns the `(normal, u, v)` axes of a face and whether its corners have to be emitted in
+/// reverse to stay counter-clockwise when seen from outside the block.
+pub(crate) fn face_axes(face: BlockFace) -> (usize, usize, usize, bool) {
+    match face {
+        BlockFace::East => (0, 2, 1, true),
+        BlockFace::West => (0, 2, 1, false),
+        BlockFace::Up => (1, 0, 2, true),
+        BlockFace::Down => (1, 0, 2, false),
+        BlockFace::South => (2, 0, 1, false),
+        BlockFace::North => (2, 0, 1, true),
+    }
+}
+
+/// Pushes a quad covering `width` x `height` blocks of `face`, starting at block `pos`.
+pub(crate) fn push_quad(
+    mesh: &mut Mesh,
+    face: BlockFace,
+    pos: [usize; 3],
+    width: usize,
+    height: usize,
+    info: FaceInfo,
+) {
+    let (n, u, v, flip) = face_axes(face);
+    let normal = face.normal();
+
+    let mut origin = [pos[0] as f32, pos[1] as f32, pos[2] as f32];
+    if normal[n] > 0 {
+        origin[n] += 1.0;
+    }
+
+    let (w, h) = (width as f32, height as f32);
+    let corners = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)];
+
+    let base = mesh.vertices.len() as u32;
+    for (du, dv) in corners {
+        let mut position = origin;
+        position[u] += du;
+        position[v] += dv;
+
+        let tex_u = if flip { w - du } else { du };
+        let tex_v = if n == 1 { dv } else { h - dv };
+
+        mesh.vertices.push(Vertex {
+            position,
+            normal: normal.map(|c| c as f32),
+            uv: [tex_u, tex_v],
+            tile: [info.tile.u, info.tile.v, info.tile.width, info.tile.height],
+            light: info.light as f32 / MAX_LIGHT as f32,
+        });
+    }
+
+    if flip {
+        mesh.indices
+            .extend_from_slice(&[base, base + 2, base + 1, base, base + 3, base + 2]);
+    } else {
+        mesh.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
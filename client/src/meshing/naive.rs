@@ -0,0 +1,185 @@
+use shared::block::BlockFace;
+use shared::chunk::SECTION_SIZE;
+
+use crate::meshing::{push_quad, Mesh, MeshContext, TextureLookup};
+
+/// Emits one quad per visible block face.
+pub fn mesh_section<T: TextureLookup>(ctx: &MeshContext<T>) -> Mesh {
+    let mut mesh = Mesh::default();
+
+    for y in 0..SECTION_SIZE {
+        for z in 0..SECTION_SIZE {
+            for x in 0..SECTION_SIZE {
+                for face in BlockFace::ALL {
+                    if let Some(info) = ctx.visible_face([x, y, z], face) {
+                        push_quad(&mut mesh, face, [x, y, z], 1, 1, info);
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::block::BlockRegistry;
+    use shared::chunk::{ChunkSection, MAX_LIGHT};
+
+    use super::*;
+    use crate::meshing::{FullTexture, SectionNeighbours, Vertex};
+
+    /// The face direction and the two opposite corners of each quad, sorted.
+    fn quads(mesh: &Mesh) -> Vec<([i32; 3], [f32; 3], [f32; 3])> {
+        let mut quads: Vec<_> = mesh
+            .vertices
+            .chunks(4)
+            .map(|corners| {
+                let mut min = corners[0].position;
+                let mut max = corners[0].position;
+                for corner in corners {
+                    for axis in 0..3 {
+                        min[axis] = min[axis].min(corner.position[axis]);
+                        max[axis] = max[axis].max(corner.position[axis]);
+                    }
+                }
+                (corners[0].normal.map(|c| c as i32), min, max)
+            })
+            .collect();
+        quads.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        quads
+    }
+
+    /// Asserts every triangle is counter-clockwise seen from the side its normal points to.
+    fn assert_facing_out(mesh: &Mesh) {
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position);
+            let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let cross = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            let normal = mesh.vertices[triangle[0] as usize].normal;
+            let facing: f32 = (0..3).map(|axis| cross[axis] * normal[axis]).sum();
+            assert!(facing > 0.0, "triangle {triangle:?} faces inwards");
+        }
+    }
+
+    fn mesh(section: &ChunkSection, neighbours: SectionNeighbours) -> Mesh {
+        let registry = BlockRegistry::default();
+        let mesh = mesh_section(&MeshContext {
+            section,
+            neighbours,
+            registry: &registry,
+            textures: &FullTexture,
+        });
+        assert_eq!(mesh.vertices.len(), mesh.quad_count() * 4);
+        assert_facing_out(&mesh);
+        mesh
+    }
+
+    fn stone() -> u16 {
+        BlockRegistry::default().id("stone").unwrap()
+    }
+
+    #[test]
+    fn single_block() {
+        let mut section = ChunkSection::new();
+        section.set(1, 2, 3, stone());
+        section.set_light(2, 2, 3, 6);
+
+        let mesh = mesh(&section, SectionNeighbours::default());
+        assert_eq!(
+            quads(&mesh),
+            vec![
+                ([-1, 0, 0], [1.0, 2.0, 3.0], [1.0, 3.0, 4.0]),
+                ([0, -1, 0], [1.0, 2.0, 3.0], [2.0, 2.0, 4.0]),
+                ([0, 0, -1], [1.0, 2.0, 3.0], [2.0, 3.0, 3.0]),
+                ([0, 0, 1], [1.0, 2.0, 4.0], [2.0, 3.0, 4.0]),
+                ([0, 1, 0], [1.0, 3.0, 3.0], [2.0, 3.0, 4.0]),
+                ([1, 0, 0], [2.0, 2.0, 3.0], [2.0, 3.0, 4.0]),
+            ]
+        );
+
+        // the east face comes first, lit by the block in front of it
+        let east = |position, uv| Vertex {
+            position,
+            normal: [1.0, 0.0, 0.0],
+            uv,
+            tile: [0.0, 0.0, 1.0, 1.0],
+            light: 6.0 / MAX_LIGHT as f32,
+        };
+        assert_eq!(
+            mesh.vertices[..4],
+            [
+                east([2.0, 2.0, 3.0], [1.0, 1.0]),
+                east([2.0, 2.0, 4.0], [0.0, 1.0]),
+                east([2.0, 3.0, 4.0], [0.0, 0.0]),
+                east([2.0, 3.0, 3.0], [1.0, 0.0]),
+            ]
+        );
+        assert_eq!(mesh.indices[..6], [0, 2, 1, 0, 3, 2]);
+    }
+
+    #[test]
+    fn shared_faces_are_culled() {
+        let mut section = ChunkSection::new();
+        section.set(1, 2, 3, stone());
+        section.set(2, 2, 3, stone());
+
+        let quads = quads(&mesh(&section, SectionNeighbours::default()));
+        assert_eq!(quads.len(), 10);
+        assert!(quads
+            .iter()
+            .all(|(normal, min, _)| normal[0] == 0 || min[0] != 2.0));
+    }
+
+    #[test]
+    fn translucent_blocks_cull_their_own_kind_only() {
+        let registry = BlockRegistry::default();
+        let glass = registry.id("glass").unwrap();
+        let mut section = ChunkSection::new();
+        section.set(1, 2, 3, glass);
+        section.set(2, 2, 3, glass);
+        assert_eq!(
+            mesh(&section, SectionNeighbours::default()).quad_count(),
+            10
+        );
+
+        // the stone face behind the glass is seen through it, the glass face against the stone
+        // is not
+        section.set(2, 2, 3, stone());
+        assert_eq!(
+            mesh(&section, SectionNeighbours::default()).quad_count(),
+            11
+        );
+    }
+
+    #[test]
+    fn faces_on_the_border_depend_on_the_neighbour() {
+        let mut section = ChunkSection::new();
+        section.set(SECTION_SIZE - 1, 0, 0, stone());
+
+        // missing neighbours count as air
+        let quads_alone = quads(&mesh(&section, SectionNeighbours::default()));
+        assert_eq!(quads_alone.len(), 6);
+        assert!(quads_alone.contains(&([1, 0, 0], [16.0, 0.0, 0.0], [16.0, 1.0, 1.0])));
+
+        let mut east = ChunkSection::new();
+        let mut neighbours = SectionNeighbours::default();
+        neighbours.set(BlockFace::East, &east);
+        assert_eq!(quads(&mesh(&section, neighbours)), quads_alone);
+
+        east.set(0, 0, 0, stone());
+        let mut neighbours = SectionNeighbours::default();
+        neighbours.set(BlockFace::East, &east);
+        let quads_covered = quads(&mesh(&section, neighbours));
+        assert_eq!(quads_covered.len(), 5);
+        assert!(quads_covered
+            .iter()
+            .all(|(normal, ..)| *normal != [1, 0, 0]));
+    }
+}
@@ -0,0 +1,429 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cgmath::{InnerSpace, Vector3};
+use shared::block::{BlockFace, BlockRegistry};
+use shared::chunk::{ChunkSection, SectionPos, SECTION_SIZE};
+
+use crate::culling::SectionVisibility;
+use crate::meshing::{
+    mesh_section, Mesh, MeshContext, MeshingMode, SectionNeighbours, TextureLookup,
+};
+use crate::world::ClientWorld;
+
+/// A snapshot of a section and its neighbours, owned so it can be meshed on another thread.
+pub struct MeshJob {
+    pub pos: SectionPos,
+    pub section: Arc<ChunkSection>,
+    pub neighbours: [Option<Arc<ChunkSection>>; 6],
+}
+
+impl MeshJob {
+    pub fn from_world(world: &ClientWorld, pos: SectionPos) -> Option<Self> {
+        let section = world.section(pos)?.clone();
+        let neighbours = BlockFace::ALL.map(|face| world.section(pos.offset(face)).cloned());
+        Some(Self {
+            pos,
+            section,
+            neighbours,
+        })
+    }
+}
+
+pub struct MeshResult {
+    pub pos: SectionPos,
+    pub mesh: Mesh,
+    pub visibility: SectionVisibility,
+}
+
+struct QueuedJob {
+    job: MeshJob,
+    version: u64,
+    priority: f32,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    // `BinaryHeap` is a max-heap, the lowest priority value has to come out first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.total_cmp(&self.priority)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MeshView {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl MeshView {
+    /// Distance to the section centre, doubled for sections directly behind the camera.
+    pub fn priority(&self, pos: SectionPos) -> f32 {
+        let half = SECTION_SIZE as f32 / 2.0;
+        let [x, y, z] = pos.origin();
+        let centre = Vector3::new(x as f32 + half, y as f32 + half, z as f32 + half);
+
+        let offset = centre - self.position;
+        let distance = offset.magnitude();
+        if distance <= f32::EPSILON {
+            return 0.0;
+        }
+
+        let facing = offset.dot(self.direction) / distance;
+        distance * (1.5 - 0.5 * facing)
+    }
+}
+
+impl Default for MeshView {
+    fn default() -> Self {
+        Self {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        }
+    }
+}
+
+struct Queue {
+    jobs: BinaryHeap<QueuedJob>,
+    view: MeshView,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+/// Meshes sections on worker threads, closest and most in view first.
+pub struct MeshPool {
+    shared: Arc<Shared>,
+    results: Receiver<(MeshResult, u64)>,
+    versions: HashMap<SectionPos, u64>,
+    next_version: u64,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl MeshPool {
+    pub fn new<T>(
+        threads: usize,
+        mode: MeshingMode,
+        registry: Arc<BlockRegistry>,
+        textures: Arc<T>,
+    ) -> Self
+    where
+        T: TextureLookup + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: BinaryHeap::new(),
+                view: MeshView::default(),
+                shutdown: false,
+            }),
+            available: Condvar::new(),
+        });
+        let (sender, results) = channel();
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let shared = shared.clone();
+                let sender = sender.clone();
+                let registry = registry.clone();
+                let textures = textures.clone();
+                std::thread::Builder::new()
+                    .name(format!("mesh-worker-{i}"))
+                    .spawn(move || worker(shared, sender, mode, registry, textures))
+                    .expect("failed to spawn mesh worker")
+            })
+            .collect();
+
+        Self {
+            shared,
+            results,
+            versions: HashMap::new(),
+            next_version: 0,
+            workers,
+        }
+    }
+
+    /// Updates the camera used for prioritisation and re-sorts the jobs still queued.
+    pub fn set_view(&self, view: MeshView) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.view = view;
+        let jobs = std::mem::take(&mut queue.jobs);
+        queue.jobs = jobs
+            .into_iter()
+            .map(|mut queued| {
+                queued.priority = view.priority(queued.job.pos);
+                queued
+            })
+            .collect();
+    }
+
+    /// Queues a job, superseding any result still in flight for the same section.
+    pub fn submit(&mut self, job: MeshJob) {
+        self.next_version += 1;
+        let version = self.next_version;
+        self.versions.insert(job.pos, version);
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        let priority = queue.view.priority(job.pos);
+        queue.jobs.push(QueuedJob {
+            job,
+            version,
+            priority,
+        });
+        drop(queue);
+
+        self.shared.available.notify_one();
+    }
+
+    /// Queues every dirty section of `world`, returning how many jobs were submitted.
+    pub fn submit_dirty(&mut self, world: &mut ClientWorld) -> usize {
+        let mut submitted = 0;
+        for pos in world.take_dirty() {
+            if let Some(job) = MeshJob::from_world(world, pos) {
+                self.submit(job);
+                submitted += 1;
+            }
+        }
+        submitted
+    }
+
+    /// Forgets a section so that results still being produced for it are dropped.
+    pub fn cancel(&mut self, pos: SectionPos) {
+        self.versions.remove(&pos);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.versions.len()
+    }
+
+    /// Hands finished meshes to `upload` until `budget` is spent. At least one mesh is uploaded
+    /// per call when available, so a tight budget cannot starve the queue.
+    pub fn upload(&mut self, budget: Duration, mut upload: impl FnMut(MeshResult)) -> usize {
+        let start = Instant::now();
+        let mut uploaded = 0;
+
+        while uploaded == 0 || start.elapsed() < budget {
+            let Ok((result, version)) = self.results.try_recv() else {
+                break;
+            };
+            if self.versions.get(&result.pos) != Some(&version) {
+                continue;
+            }
+            self.versions.remove(&result.pos);
+
+            upload(result);
+            uploaded += 1;
+        }
+
+        uploaded
+    }
+}
+
+impl Drop for MeshPool {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker<T: TextureLookup>(
+    shared: Arc<Shared>,
+    sender: Sender<(MeshResult, u64)>,
+    mode: MeshingMode,
+    registry: Arc<BlockRegistry>,
+    textures: Arc<T>,
+) {
+    loop {
+        let queued = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                if let Some(queued) = queue.jobs.pop() {
+                    break queued;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+
+        let job = queued.job;
+        let mut neighbours = SectionNeighbours::default();
+        for face in BlockFace::ALL {
+            if let Some(section) = &job.neighbours[face as usize] {
+                neighbours.set(face, section);
+            }
+        }
+
+        let mesh = mesh_section(
+            mode,
+            &MeshContext {
+                section: &job.section,
+                neighbours,
+                registry: &registry,
+                textures: textures.as_ref(),
+            },
+        );
+
+        let result = MeshResult {
+            pos: job.pos,
+            mesh,
+            visibility: SectionVisibility::compute(&job.section, &registry),
+        };
+        if sender.send((result, queued.version)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pool without workers, plus the sender its results would come from.
+    fn idle_pool() -> (MeshPool, Sender<(MeshResult, u64)>) {
+        let (sender, results) = channel();
+        let pool = MeshPool {
+            shared: Arc::new(Shared {
+                queue: Mutex::new(Queue {
+                    jobs: BinaryHeap::new(),
+                    view: MeshView::default(),
+                    shutdown: false,
+                }),
+                available: Condvar::new(),
+            }),
+            results,
+            versions: HashMap::new(),
+            next_version: 0,
+            workers: Vec::new(),
+        };
+        (pool, sender)
+    }
+
+    fn job(x: i32, y: i32, z: i32) -> MeshJob {
+        MeshJob {
+            pos: SectionPos::new(x, y, z),
+            section: Arc::new(ChunkSection::new()),
+            neighbours: Default::default(),
+        }
+    }
+
+    fn result(pos: SectionPos) -> MeshResult {
+        MeshResult {
+            pos,
+            mesh: Mesh::default(),
+            visibility: SectionVisibility::ALL,
+        }
+    }
+
+    fn queued_order(pool: &MeshPool) -> Vec<SectionPos> {
+        let mut queue = pool.shared.queue.lock().unwrap();
+        std::iter::from_fn(|| queue.jobs.pop().map(|queued| queued.job.pos)).collect()
+    }
+
+    #[test]
+    fn sections_in_view_come_before_those_behind() {
+        let view = MeshView::default();
+        let ahead = SectionPos::new(0, 0, -3);
+        let behind = SectionPos::new(0, 0, 2);
+        let aside = SectionPos::new(2, 0, -1);
+        assert!(view.priority(ahead) < view.priority(aside));
+        assert!(view.priority(aside) < view.priority(behind));
+        // mirrored across the view direction
+        assert_eq!(
+            view.priority(SectionPos::new(0, 0, 0)),
+            view.priority(SectionPos::new(-1, -1, 0))
+        );
+    }
+
+    #[test]
+    fn jobs_are_meshed_closest_first_and_resorted_when_the_view_moves() {
+        let (mut pool, _sender) = idle_pool();
+        let positions = [
+            SectionPos::new(0, 0, 4),
+            SectionPos::new(0, 0, -1),
+            SectionPos::new(0, 0, 2),
+            SectionPos::new(0, 0, -3),
+        ];
+        for pos in positions {
+            pool.submit(job(pos.x, pos.y, pos.z));
+        }
+        assert_eq!(
+            queued_order(&pool),
+            [positions[1], positions[3], positions[2], positions[0]]
+        );
+
+        for pos in positions {
+            pool.submit(job(pos.x, pos.y, pos.z));
+        }
+        let half = SECTION_SIZE as f32 / 2.0;
+        pool.set_view(MeshView {
+            position: Vector3::new(half, half, 4.0 * SECTION_SIZE as f32 + half),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+        });
+        assert_eq!(
+            queued_order(&pool),
+            [positions[0], positions[2], positions[1], positions[3]]
+        );
+    }
+
+    #[test]
+    fn only_the_latest_job_of_a_section_is_uploaded() {
+        let (mut pool, sender) = idle_pool();
+        let pos = SectionPos::new(0, 0, 0);
+        pool.submit(job(0, 0, 0));
+        pool.submit(job(0, 0, 0));
+        assert_eq!(pool.pending(), 1);
+
+        sender.send((result(pos), 1)).unwrap();
+        assert_eq!(pool.upload(Duration::MAX, |_| panic!("stale mesh")), 0);
+        sender.send((result(pos), 2)).unwrap();
+        let mut uploaded = Vec::new();
+        assert_eq!(
+            pool.upload(Duration::MAX, |result| uploaded.push(result.pos)),
+            1
+        );
+        assert_eq!(uploaded, [pos]);
+        assert_eq!(pool.pending(), 0);
+    }
+
+    #[test]
+    fn cancelled_sections_are_not_uploaded() {
+        let (mut pool, sender) = idle_pool();
+        let kept = SectionPos::new(1, 0, 0);
+        let cancelled = SectionPos::new(0, 0, 0);
+        pool.submit(job(0, 0, 0));
+        pool.submit(job(1, 0, 0));
+        pool.cancel(cancelled);
+        assert_eq!(pool.pending(), 1);
+
+        sender.send((result(cancelled), 1)).unwrap();
+        sender.send((result(kept), 2)).unwrap();
+        let mut uploaded = Vec::new();
+        pool.upload(Duration::MAX, |result| uploaded.push(result.pos));
+        assert_eq!(uploaded, [kept]);
+        assert_eq!(pool.pending(), 0);
+    }
+}
@@ -1,71 +1,446 @@
-use std::hash::Hasher;
-use std::io::Write;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use tracing::info;
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{info, warn};
 
-use quinn::{ClientConfig, Endpoint};
-use shared::packet_ext::{PacketStreamReadExt, PacketStreamWriteExt};
-use shared::protocol::{PacketAction, ReliablePacket, UnreliablePacket};
-use std::net::{TcpStream, UdpSocket};
-use std::sync::Arc;
+use shared::error::NetworkError;
+use shared::packet_ext::{decode_packet, AsyncPacketReadExt, AsyncPacketWriteExt};
+use shared::protocol::{
+    Credentials, DisconnectReason, PacketAction, ProtocolHello, ReliablePacket, ServerStatus,
+    UnreliablePacket, VersionMismatch, GAME_ALPN, STATUS_ALPN,
+};
+
+use crate::known_hosts::{CertificateMismatch, KnownHosts, TrustOnFirstUse};
 
 pub enum TcpEvent {
     PacketReceived { packet: ReliablePacket },
+    Disconnected { reason: String },
 }
 
 pub enum UdpEvent {
     PacketReceived { packet: UnreliablePacket },
 }
 
-pub async fn init() {
-    let endpoint = create_client();
-    let connection = endpoint
-        .connect(shared::TCP_ADDRESS.parse().unwrap(), "localhost")
-        .unwrap()
-        .await
-        .unwrap();
+/// A host name or IP address and a port, as typed by the player. The port defaults to
+/// `shared::DEFAULT_PORT` when omitted.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: u16,
+}
 
-    let (mut handshake_send, mut handshake_recv) = connection.open_bi().await.unwrap();
+impl ServerAddress {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+        }
+    }
 
-    handshake_send.write("hello".as_bytes()).await.unwrap();
+    /// Looks the host up, preferring the first address returned.
+    pub async fn resolve(&self) -> std::io::Result<SocketAddr> {
+        tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} has no address", self.host),
+                )
+            })
+    }
 
-    loop {}
+    /// The name checked against the server's certificate.
+    pub fn server_name(&self) -> &str {
+        &self.host
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = String;
 
-    // (id, tcp_receiver, udp_receiver, packet_action_sender)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(address) = s.parse::<SocketAddr>() {
+            return Ok(Self::new(address.ip().to_string(), address.port()));
+        }
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self::new(ip.to_string(), shared::DEFAULT_PORT));
+        }
+
+        let (host, port) = match s.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port \"{port}\""))?;
+                (host, port)
+            }
+            None => (s, shared::DEFAULT_PORT),
+        };
+        if host.is_empty() || host.contains(|c: char| c.is_whitespace() || c == ':') {
+            return Err(format!("invalid host \"{host}\""));
+        }
+        Ok(Self::new(host, port))
+    }
 }
 
-fn create_client() -> Endpoint {
-    let client_config = rustls::ClientConfig::builder()
-        .with_safe_defaults()
-        .with_custom_certificate_verifier(SkipServerVerification::new())
-        .with_no_client_auth();
+impl TryFrom<String> for ServerAddress {
+    type Error = String;
 
-    let client_config = ClientConfig::new(Arc::new(client_config));
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
 
-    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
-    endpoint.set_default_client_config(client_config);
-    endpoint
+impl From<ServerAddress> for String {
+    fn from(address: ServerAddress) -> Self {
+        address.to_string()
+    }
 }
 
-struct SkipServerVerification;
+impl Display for ServerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
 
-impl SkipServerVerification {
-    fn new() -> Arc<Self> {
-        Arc::new(Self)
+#[derive(Debug)]
+pub enum ConnectError {
+    Resolve(std::io::Error),
+    Endpoint(std::io::Error),
+    Connect(quinn::ConnectError),
+    Connection(quinn::ConnectionError),
+    Handshake(NetworkError),
+    UnexpectedPacket(ReliablePacket),
+    NotRbmp,
+    Outdated(VersionMismatch),
+    Rejected(DisconnectReason),
+    CertificateChanged(CertificateMismatch),
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectError::Resolve(err) => write!(f, "failed to resolve the address: {err}"),
+            ConnectError::Endpoint(err) => write!(f, "failed to create the endpoint: {err}"),
+            ConnectError::Connect(err) => write!(f, "failed to connect: {err}"),
+            ConnectError::Connection(err) => write!(f, "connection failed: {err}"),
+            ConnectError::Handshake(err) => write!(f, "handshake failed: {err}"),
+            ConnectError::UnexpectedPacket(packet) => {
+                write!(f, "server answered the handshake with {packet:?}")
+            }
+            ConnectError::NotRbmp => write!(f, "the server does not speak the rbmp protocol"),
+            ConnectError::Outdated(mismatch) => write!(f, "{mismatch}"),
+            ConnectError::Rejected(reason) => write!(f, "{reason}"),
+            ConnectError::CertificateChanged(mismatch) => write!(f, "{mismatch}"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// What went over the wire so far, counted in UDP payload bytes.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Traffic {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub round_trip_time: Duration,
+}
+
+/// An established, handshaken connection to a server.
+pub struct ServerConnection {
+    pub player_id: u32,
+    pub tcp_receiver: Receiver<TcpEvent>,
+    pub udp_receiver: Receiver<UdpEvent>,
+    pub packet_action_sender: UnboundedSender<PacketAction>,
+    connection: Connection,
+    endpoint: Endpoint,
+}
+
+impl ServerConnection {
+    pub fn remote_address(&self) -> SocketAddr {
+        self.connection.remote_address()
+    }
+
+    pub fn traffic(&self) -> Traffic {
+        let stats = self.connection.stats();
+        Traffic {
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            round_trip_time: self.connection.rtt(),
+        }
+    }
+
+    pub fn close(&self) {
+        self.connection.close(0u8.into(), b"disconnected");
+        self.endpoint.close(0u8.into(), b"disconnected");
     }
 }
 
-impl rustls::client::ServerCertVerifier for SkipServerVerification {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::Certificate,
-        _intermediates: &[rustls::Certificate],
-        _server_name: &rustls::ServerName,
-        _scts: &mut dyn Iterator<Item = &[u8]>,
-        _ocsp_response: &[u8],
-        _now: std::time::SystemTime,
-    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::ServerCertVerified::assertion())
+/// A server's answer to a status query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerPing {
+    pub version: u32,
+    /// Only decodable when the server speaks our protocol version.
+    pub status: Option<ServerStatus>,
+    /// Between sending our `ProtocolHello` and receiving the server's.
+    pub latency: Duration,
+}
+
+impl ServerPing {
+    pub fn compatibility(&self) -> Result<(), VersionMismatch> {
+        VersionMismatch::check(ProtocolHello::CURRENT.version, self.version)
     }
 }
+
+/// Resolves `address` and asks it for its status without logging in. The certificate is checked
+/// against `known_hosts` like when connecting.
+pub async fn ping(
+    address: &ServerAddress,
+    known_hosts: Arc<Mutex<KnownHosts>>,
+) -> Result<ServerPing, ConnectError> {
+    let resolved = address.resolve().await.map_err(ConnectError::Resolve)?;
+    let (endpoint, connection) =
+        open_connection(resolved, address.server_name(), STATUS_ALPN, known_hosts).await?;
+
+    let result = async {
+        let (mut send, mut recv) = connection
+            .open_bi()
+            .await
+            .map_err(ConnectError::Connection)?;
+
+        let sent_at = Instant::now();
+        send.send_packet(&ProtocolHello::CURRENT)
+            .await
+            .map_err(ConnectError::Handshake)?;
+        let hello = recv
+            .recv_packet::<ProtocolHello>()
+            .await
+            .map_err(ConnectError::Handshake)?;
+        let latency = sent_at.elapsed();
+        if !hello.is_rbmp() {
+            return Err(ConnectError::NotRbmp);
+        }
+
+        let status = if hello.version == ProtocolHello::CURRENT.version {
+            let status = recv.recv_packet::<ServerStatus>().await;
+            Some(status.map_err(ConnectError::Handshake)?)
+        } else {
+            None
+        };
+        Ok(ServerPing {
+            version: hello.version,
+            status,
+            latency,
+        })
+    }
+    .await;
+
+    connection.close(0u8.into(), b"done");
+    endpoint.close(0u8.into(), b"done");
+    result
+}
+
+/// Resolves `address` and connects to it, see `connect`.
+pub async fn connect_to(
+    address: &ServerAddress,
+    username: &str,
+    credentials: Credentials,
+    known_hosts: Arc<Mutex<KnownHosts>>,
+) -> Result<ServerConnection, ConnectError> {
+    let resolved = address.resolve().await.map_err(ConnectError::Resolve)?;
+    connect(
+        resolved,
+        address.server_name(),
+        username,
+        credentials,
+        known_hosts,
+    )
+    .await
+}
+
+/// Connects to `address`, performs the handshake and spawns the tasks moving packets between
+/// the connection and the returned channels. Must be called from within a tokio runtime.
+///
+/// The server's certificate is pinned in `known_hosts` under `server_name` and the port the
+/// first time, and must match the pinned one afterwards.
+pub async fn connect(
+    address: SocketAddr,
+    server_name: &str,
+    username: &str,
+    credentials: Credentials,
+    known_hosts: Arc<Mutex<KnownHosts>>,
+) -> Result<ServerConnection, ConnectError> {
+    let (endpoint, connection) =
+        open_connection(address, server_name, GAME_ALPN, known_hosts).await?;
+
+    let (mut send, mut recv) = connection
+        .open_bi()
+        .await
+        .map_err(ConnectError::Connection)?;
+
+    send.send_packet(&ProtocolHello::CURRENT)
+        .await
+        .map_err(ConnectError::Handshake)?;
+    let hello = recv
+        .recv_packet::<ProtocolHello>()
+        .await
+        .map_err(ConnectError::Handshake)?;
+    if !hello.is_rbmp() {
+        return Err(ConnectError::NotRbmp);
+    }
+    VersionMismatch::check(ProtocolHello::CURRENT.version, hello.version)
+        .map_err(ConnectError::Outdated)?;
+
+    send.send_reliable(&ReliablePacket::Handshake {
+        username: username.to_string(),
+        credentials,
+    })
+    .await
+    .map_err(ConnectError::Handshake)?;
+
+    let player_id = match recv.recv_reliable().await {
+        Ok(ReliablePacket::HandshakeRes { player_id }) => player_id,
+        Ok(ReliablePacket::Disconnect { reason }) => return Err(ConnectError::Rejected(reason)),
+        Ok(packet) => return Err(ConnectError::UnexpectedPacket(packet)),
+        Err(err) => return Err(ConnectError::Handshake(err)),
+    };
+
+    info!("connected to {address} as {username}, player_id: {player_id}");
+
+    let (tcp_sender, tcp_receiver) = channel();
+    let (udp_sender, udp_receiver) = channel();
+    let (packet_action_sender, packet_action_receiver) = unbounded_channel();
+
+    tokio::spawn(read_packets(recv, tcp_sender));
+    tokio::spawn(read_datagrams(connection.clone(), udp_sender));
+    tokio::spawn(write_packets(
+        connection.clone(),
+        send,
+        packet_action_receiver,
+    ));
+
+    Ok(ServerConnection {
+        player_id,
+        tcp_receiver,
+        udp_receiver,
+        packet_action_sender,
+        connection,
+        endpoint,
+    })
+}
+
+/// Establishes a QUIC connection negotiating `alpn`, pinning the server's certificate in
+/// `known_hosts` under `server_name` and the port the first time.
+async fn open_connection(
+    address: SocketAddr,
+    server_name: &str,
+    alpn: &[u8],
+    known_hosts: Arc<Mutex<KnownHosts>>,
+) -> Result<(Endpoint, Connection), ConnectError> {
+    let host = ServerAddress::new(server_name, address.port()).to_string();
+    let verifier = TrustOnFirstUse::new(host, known_hosts);
+    let endpoint =
+        create_client(address, verifier.clone(), alpn).map_err(ConnectError::Endpoint)?;
+    let connection = endpoint
+        .connect(address, server_name)
+        .map_err(ConnectError::Connect)?
+        .await
+        .map_err(|err| match verifier.take_mismatch() {
+            Some(mismatch) => ConnectError::CertificateChanged(mismatch),
+            None => ConnectError::Connection(err),
+        })?;
+    Ok((endpoint, connection))
+}
+
+async fn read_packets(mut recv: RecvStream, sender: Sender<TcpEvent>) {
+    loop {
+        match recv.recv_reliable().await {
+            Ok(packet) => {
+                if sender.send(TcpEvent::PacketReceived { packet }).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                let _ = sender.send(TcpEvent::Disconnected {
+                    reason: err.to_string(),
+                });
+                return;
+            }
+        }
+    }
+}
+
+async fn read_datagrams(connection: Connection, sender: Sender<UdpEvent>) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        match decode_packet(&datagram) {
+            Ok(packet) => {
+                if sender.send(UdpEvent::PacketReceived { packet }).is_err() {
+                    return;
+                }
+            }
+            Err(err) => warn!("server sent an invalid datagram: {err}"),
+        }
+    }
+}
+
+async fn write_packets(
+    connection: Connection,
+    mut send: SendStream,
+    mut receiver: UnboundedReceiver<PacketAction>,
+) {
+    while let Some(action) = receiver.recv().await {
+        let result = match action {
+            PacketAction::Reliable(packet) => send.send_reliable(&packet).await,
+            PacketAction::Unreliable(packet) => packet.to_buf().and_then(|buf| {
+                connection
+                    .send_datagram(buf.into())
+                    .map_err(|err| NetworkError::ConnectionLost(std::io::Error::other(err)))
+            }),
+            PacketAction::Disconnect(reason) => {
+                connection.close(0u8.into(), reason.to_string().as_bytes());
+                return;
+            }
+        };
+
+        if let Err(err) = result {
+            warn!("failed to send to the server: {err}");
+            return;
+        }
+    }
+}
+
+fn create_client(
+    address: SocketAddr,
+    verifier: Arc<TrustOnFirstUse>,
+    alpn: &[u8],
+) -> std::io::Result<Endpoint> {
+    let mut client_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    client_config.alpn_protocols = vec![alpn.to_vec()];
+
+    let client_config = ClientConfig::new(Arc::new(client_config));
+
+    let bind_address = if address.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let mut endpoint = Endpoint::client(bind_address.parse().unwrap())?;
+    endpoint.set_default_client_config(client_config);
+    Ok(endpoint)
+}
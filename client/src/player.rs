@@ -1,48 +1,85 @@
 use crate::game_loop::FrameContext;
+use crate::input::Action;
 use crate::state::ClientState;
-use shared::protocol::{ReliablePacket, UnreliablePacket};
-use winit::event::VirtualKeyCode;
+use tracing::{debug, warn};
+use winit::window::CursorGrabMode;
 
-pub struct Player {
-    id: u32,
-    movement: PlayerMovement,
-}
+pub fn update_player_movement(ctx: &mut FrameContext, state: &mut ClientState) {
+    let Some(game) = &mut state.game else {
+        return;
+    };
+
+    let movement = &mut game.player.movement;
+    // while the chat is open the keys are being typed into it
+    let typing = state.chat.is_open();
+    let input_map = &state.input_map;
+    let pressed = |action| !typing && input_map.pressed(ctx, action);
 
-pub struct PlayerMovement {
-    directions: [bool; 6],
-    rotations: [f32; 2],
+    movement.directions[0] = pressed(Action::MoveLeft);
+    movement.directions[1] = pressed(Action::MoveForward);
+    movement.directions[2] = pressed(Action::MoveRight);
+    movement.directions[3] = pressed(Action::MoveBackward);
+    movement.directions[4] = pressed(Action::Sneak);
+    movement.directions[5] = pressed(Action::Jump);
+
+    if state.cursor_grabbed {
+        let [dx, dy] = ctx.mouse_delta();
+        state.camera_controller.rotate(dx, dy);
+    }
+    state.camera_controller.apply(&mut state.camera);
+    movement.rotations[0] = state.camera_controller.yaw.0;
+    movement.rotations[1] = state.camera_controller.pitch.0;
+
+    // the server sends the player back with a teleport if it disagrees
+    movement.predict(ctx.delta_time().as_secs_f32());
+    state.camera.position = movement.position.into();
 }
 
-impl Player {
-    pub fn new(id: u32) -> Self {
-        Self {
-            id,
-            movement: PlayerMovement {
-                directions: [false; 6],
-                rotations: [0.0; 2],
-            },
-        }
+pub fn update_cursor_grab(ctx: &mut FrameContext, state: &mut ClientState) {
+    if state.game.is_none()
+        || state.chat.is_open()
+        || state.binding_editor.is_capturing()
+        || !state.input_map.just_pressed(ctx, Action::ToggleCursor)
+    {
+        return;
     }
+
+    set_cursor_grab(state, !state.cursor_grabbed);
 }
 
-pub fn update_player_movement(ctx: &mut FrameContext, state: &mut ClientState) {
-    let movement = &mut state.player.movement;
+pub fn set_cursor_grab(state: &mut ClientState, grab: bool) {
+    if state.cursor_grabbed == grab {
+        return;
+    }
 
-    movement.directions[0] = ctx.pressed(VirtualKeyCode::A);
-    movement.directions[1] = ctx.pressed(VirtualKeyCode::W);
-    movement.directions[2] = ctx.pressed(VirtualKeyCode::D);
-    movement.directions[3] = ctx.pressed(VirtualKeyCode::S);
-    movement.directions[4] = ctx.pressed(VirtualKeyCode::LShift);
-    movement.directions[5] = ctx.pressed(VirtualKeyCode::Space);
+    let mode = if grab {
+        CursorGrabMode::Locked
+    } else {
+        CursorGrabMode::None
+    };
 
-    movement.rotations[0] = 0.0;
-    movement.rotations[1] = 0.0;
+    let result = state.window.set_cursor_grab(mode).or_else(|_| {
+        if grab {
+            state.window.set_cursor_grab(CursorGrabMode::Confined)
+        } else {
+            Ok(())
+        }
+    });
+
+    match result {
+        Ok(()) => {
+            state.cursor_grabbed = grab;
+            state.window.set_cursor_visible(!grab);
+        }
+        Err(err) => warn!("failed to change cursor grab: {err}"),
+    }
 }
 
-pub fn send_player_movement_packet(_ctx: &mut FrameContext, state: &mut ClientState) {
-    let movement = &state.player.movement;
-    state.send_reliable_packet(ReliablePacket::MovementInput {
-        directions: movement.directions.clone(),
-        rotations: movement.rotations.clone(),
-    });
+pub fn send_player_movement_packet(ctx: &mut FrameContext, state: &mut ClientState) {
+    if let (true, Some(game)) = (ctx.is_fixed(), &state.game) {
+        // the connection is gone, the game learns why from its next packets
+        if let Err(err) = game.send_movement() {
+            debug!("failed to send the movement: {err}");
+        }
+    }
 }
@@ -1,17 +1,21 @@
 use std::iter::once;
 
 use wgpu::{
-    Backends, CommandEncoder, CommandEncoderDescriptor, Device, DeviceDescriptor, Instance,
-    InstanceDescriptor, LoadOp, Operations, Queue, RenderPass, RenderPassColorAttachment,
-    RenderPassDescriptor, RequestAdapterOptions, Surface, SurfaceConfiguration, SurfaceTexture,
-    TextureUsages, TextureView, TextureViewDescriptor,
+    Backends, CommandEncoder, CommandEncoderDescriptor, Device, DeviceDescriptor, Extent3d,
+    Instance, InstanceDescriptor, LoadOp, Operations, PresentMode, Queue, RenderPass,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RequestAdapterOptions, Surface, SurfaceConfiguration, SurfaceTexture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
 };
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
 pub struct Renderer {
     surface_configuration: SurfaceConfiguration,
     surface: Surface,
+    depth_view: TextureView,
 
     device: Device,
     queue: Queue,
@@ -20,7 +24,7 @@ pub struct Renderer {
 }
 
 impl Renderer {
-    pub fn new(window: &Window) -> Self {
+    pub fn new(window: &Window, vsync: bool) -> Self {
         let size = window.inner_size();
 
         let instance = Instance::new(InstanceDescriptor {
@@ -61,16 +65,23 @@ impl Renderer {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_capabilities.present_modes[0],
+            present_mode: if vsync {
+                PresentMode::AutoVsync
+            } else {
+                PresentMode::AutoNoVsync
+            },
             alpha_mode: surface_capabilities.alpha_modes[0],
             view_formats: vec![],
         };
 
         surface.configure(&device, &surface_configuration);
 
+        let depth_view = create_depth_view(&device, &surface_configuration);
+
         Self {
             surface_configuration,
             surface,
+            depth_view,
             device,
             queue,
             size,
@@ -84,9 +95,14 @@ impl Renderer {
             self.surface_configuration.height = new_size.height;
             self.surface
                 .configure(&self.device, &self.surface_configuration);
+            self.depth_view = create_depth_view(&self.device, &self.surface_configuration);
         }
     }
 
+    pub fn aspect(&self) -> f32 {
+        self.size.width as f32 / self.size.height.max(1) as f32
+    }
+
     pub fn get_output(&self) -> SurfaceTexture {
         self.surface.get_current_texture().unwrap()
     }
@@ -105,7 +121,7 @@ impl Renderer {
     }
 
     pub fn create_render_pass<'a, 'b: 'a>(
-        &self,
+        &'a self,
         encoder: &'b mut CommandEncoder,
         view: &'b TextureView,
     ) -> RenderPass<'a> {
@@ -124,6 +140,33 @@ impl Renderer {
                     store: true,
                 },
             })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    /// Draws over whatever the world pass left in `view`, without depth testing.
+    pub fn create_ui_render_pass<'a, 'b: 'a>(
+        &self,
+        encoder: &'b mut CommandEncoder,
+        view: &'b TextureView,
+    ) -> RenderPass<'a> {
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("UI Render Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            })],
             depth_stencil_attachment: None,
         })
     }
@@ -133,10 +176,6 @@ impl Renderer {
         output.present();
     }
 
-    pub fn surface(&self) -> &Surface {
-        &self.surface
-    }
-
     pub fn device(&self) -> &Device {
         &self.device
     }
@@ -149,3 +188,21 @@ impl Renderer {
         &self.surface_configuration
     }
 }
+
+fn create_depth_view(device: &Device, configuration: &SurfaceConfiguration) -> TextureView {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("Depth Texture"),
+        size: Extent3d {
+            width: configuration.width.max(1),
+            height: configuration.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&TextureViewDescriptor::default())
+}
@@ -0,0 +1,335 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use client::game::GameState;
+use client::known_hosts::{CertificateMismatch, KnownHosts};
+use client::lan::LanDiscovery;
+use client::networking::{connect_to, ConnectError, ServerAddress, ServerConnection};
+use imgui::{Condition, Ui};
+use serde::{Deserialize, Serialize};
+use shared::protocol::Credentials;
+use shared::toml_file::{self, TomlFileError};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::game_loop::FrameContext;
+use crate::player::set_cursor_grab;
+use crate::state::ClientState;
+
+pub const SERVERS_PATH: &str = "servers.toml";
+
+const MISMATCH_COLOR: [f32; 4] = [1.0, 0.35, 0.35, 1.0];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedServer {
+    pub name: String,
+    pub address: ServerAddress,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerList {
+    #[serde(default)]
+    pub servers: Vec<SavedServer>,
+}
+
+impl ServerList {
+    pub fn load(path: &Path) -> Result<Self, TomlFileError> {
+        toml_file::load(path)
+    }
+
+    /// Loads the server list at `path`, starting with an empty one if the file is missing or
+    /// invalid. An invalid file is moved aside, so saving the new list does not overwrite it.
+    pub fn load_or_default(path: &Path) -> Self {
+        toml_file::load_or_default(path, Self::load)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TomlFileError> {
+        toml_file::save(path, self)
+    }
+}
+
+struct PendingConnection {
+    address: ServerAddress,
+    receiver: oneshot::Receiver<Result<ServerConnection, ConnectError>>,
+}
+
+/// The saved servers, the ones discovered on the LAN and the connection being established, if
+/// any. Shown whenever the client is not in a game.
+pub struct ServerBrowser {
+    list: ServerList,
+    selected: Option<usize>,
+    name_input: String,
+    address_input: String,
+    status: Option<String>,
+    mismatch: Option<CertificateMismatch>,
+    pending: Option<PendingConnection>,
+    known_hosts: Arc<Mutex<KnownHosts>>,
+    lan: Option<LanDiscovery>,
+}
+
+impl ServerBrowser {
+    pub fn new(list: ServerList, known_hosts: KnownHosts, lan: Option<LanDiscovery>) -> Self {
+        Self {
+            list,
+            selected: None,
+            name_input: String::new(),
+            address_input: String::new(),
+            status: None,
+            mismatch: None,
+            pending: None,
+            known_hosts: Arc::new(Mutex::new(known_hosts)),
+            lan,
+        }
+    }
+
+    pub fn is_connecting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Starts connecting to `address` in the background. The result is picked up by
+    /// `update_connection`.
+    pub fn connect(&mut self, address: ServerAddress, username: String, credentials: Credentials) {
+        info!("connecting to {address} as {username}");
+        let (sender, receiver) = oneshot::channel();
+        {
+            let address = address.clone();
+            let known_hosts = self.known_hosts.clone();
+            tokio::spawn(async move {
+                let result = connect_to(&address, &username, credentials, known_hosts).await;
+                let _ = sender.send(result);
+            });
+        }
+        self.mismatch = None;
+        self.status = Some(format!("connecting to {address}..."));
+        self.pending = Some(PendingConnection { address, receiver });
+    }
+
+    fn save(&mut self) {
+        if let Err(err) = self.list.save(Path::new(SERVERS_PATH)) {
+            self.status = Some(format!("failed to save the server list: {err}"));
+        }
+    }
+}
+
+/// Enters the game once a pending connection is established and returns to the browser when
+/// the connection is lost.
+pub fn update_connection(_ctx: &mut FrameContext, state: &mut ClientState) {
+    let browser = &mut state.server_browser;
+    if let Some(lan) = &mut browser.lan {
+        lan.poll();
+    }
+    if let Some(pending) = &mut browser.pending {
+        match pending.receiver.try_recv() {
+            Ok(Ok(connection)) => {
+                browser.status = None;
+                browser.pending = None;
+                state.game = Some(GameState::new(connection));
+            }
+            Ok(Err(ConnectError::CertificateChanged(mismatch))) => {
+                browser.status = None;
+                browser.mismatch = Some(mismatch);
+                browser.pending = None;
+            }
+            Ok(Err(err)) => {
+                warn!("failed to connect to {}: {err}", pending.address);
+                browser.status = Some(format!("failed to connect to {}: {err}", pending.address));
+                browser.pending = None;
+            }
+            Err(oneshot::error::TryRecvError::Empty) => {}
+            Err(oneshot::error::TryRecvError::Closed) => {
+                browser.status = Some(format!("failed to connect to {}", pending.address));
+                browser.pending = None;
+            }
+        }
+    }
+
+    let Some(game) = &mut state.game else {
+        return;
+    };
+    game.receive_packets();
+    if let Some(reason) = game.disconnect_reason() {
+        state.server_browser.status = Some(format!("disconnected: {reason}"));
+        leave_game(state);
+    }
+}
+
+/// Closes the connection, if any, and forgets everything about the world.
+pub fn leave_game(state: &mut ClientState) {
+    let Some(game) = state.game.take() else {
+        return;
+    };
+
+    game.connection.close();
+    for (pos, _) in game.world.sections() {
+        state.mesh_pool.cancel(*pos);
+    }
+    state.world_renderer.clear();
+    set_cursor_grab(state, false);
+}
+
+pub fn server_browser(_ctx: &mut FrameContext, state: &mut ClientState, ui: &mut Ui) {
+    if state.game.is_some() {
+        if state.cursor_grabbed {
+            return;
+        }
+
+        let mut disconnect = false;
+        ui.window("Server")
+            .size([260.0, 80.0], Condition::FirstUseEver)
+            .build(|| {
+                if let Some(game) = &state.game {
+                    ui.text(format!("connected to {}", game.connection.remote_address()));
+                }
+                disconnect = ui.button("Disconnect");
+            });
+        if disconnect {
+            leave_game(state);
+        }
+        return;
+    }
+
+    let username = state.config.username.clone();
+    let credentials = state.config.credentials();
+    let browser = &mut state.server_browser;
+    ui.window("Servers")
+        .size([420.0, 360.0], Condition::FirstUseEver)
+        .build(|| {
+            let connecting = browser.is_connecting();
+            ui.text(format!("playing as {username}"));
+            ui.separator();
+
+            let mut join = None;
+            for (index, server) in browser.list.servers.iter().enumerate() {
+                let _id = ui.push_id_usize(index);
+                let label = format!("{}  ({})", server.name, server.address);
+                if ui
+                    .selectable_config(&label)
+                    .selected(browser.selected == Some(index))
+                    .allow_double_click(true)
+                    .build()
+                {
+                    browser.selected = Some(index);
+                    if ui.is_mouse_double_clicked(imgui::MouseButton::Left) {
+                        join = Some(server.address.clone());
+                    }
+                }
+            }
+            if browser.list.servers.is_empty() {
+                ui.text_disabled("no saved servers");
+            }
+
+            if let Some(lan) = &browser.lan {
+                ui.separator();
+                ui.text("LAN games");
+                let mut discovered = 0;
+                for server in lan.servers() {
+                    discovered += 1;
+                    let _id = ui.push_id(server.address.to_string());
+                    let (label, compatible) = match (&server.announcement, server.compatibility()) {
+                        (Some(announcement), Ok(())) => (
+                            format!(
+                                "{}  ({})  {}/{}",
+                                announcement.motd,
+                                server.address,
+                                announcement.online,
+                                announcement.max_players
+                            ),
+                            true,
+                        ),
+                        (_, Err(mismatch)) => (format!("{}  {mismatch}", server.address), false),
+                        (None, Ok(())) => (server.address.to_string(), false),
+                    };
+                    ui.disabled(!compatible, || {
+                        if ui
+                            .selectable_config(&label)
+                            .allow_double_click(true)
+                            .build()
+                        {
+                            browser.selected = None;
+                            browser.name_input = server
+                                .announcement
+                                .as_ref()
+                                .map_or_else(String::new, |a| a.motd.clone());
+                            browser.address_input = server.address.to_string();
+                            if ui.is_mouse_double_clicked(imgui::MouseButton::Left) {
+                                join = Some(server.address.clone());
+                            }
+                        }
+                    });
+                }
+                if discovered == 0 {
+                    ui.text_disabled("searching the local network...");
+                }
+            }
+
+            ui.separator();
+            let selected = browser
+                .selected
+                .filter(|index| *index < browser.list.servers.len());
+            ui.disabled(connecting || selected.is_none(), || {
+                if ui.button("Join") {
+                    join = selected.map(|index| browser.list.servers[index].address.clone());
+                }
+                ui.same_line();
+                if ui.button("Remove") {
+                    if let Some(index) = selected {
+                        browser.list.servers.remove(index);
+                        browser.selected = None;
+                        browser.save();
+                    }
+                }
+            });
+
+            ui.separator();
+            ui.input_text("Name", &mut browser.name_input).build();
+            ui.input_text("Address", &mut browser.address_input)
+                .hint("host:port")
+                .build();
+            let address = browser.address_input.parse::<ServerAddress>();
+            ui.disabled(address.is_err(), || {
+                if ui.button("Add") {
+                    if let Ok(address) = &address {
+                        let name = match browser.name_input.trim() {
+                            "" => address.to_string(),
+                            name => name.to_string(),
+                        };
+                        browser.list.servers.push(SavedServer {
+                            name,
+                            address: address.clone(),
+                        });
+                        browser.name_input.clear();
+                        browser.address_input.clear();
+                        browser.save();
+                    }
+                }
+                ui.same_line();
+                ui.disabled(connecting, || {
+                    if ui.button("Direct connect") {
+                        join = address.clone().ok();
+                    }
+                });
+            });
+            if let (Err(err), false) = (&address, browser.address_input.is_empty()) {
+                ui.text_disabled(err);
+            }
+
+            if let Some(address) = join {
+                browser.connect(address, username.clone(), credentials.clone());
+            }
+            if let Some(status) = &browser.status {
+                ui.text_wrapped(status);
+            }
+            if let Some(mismatch) = &browser.mismatch {
+                ui.text_colored(MISMATCH_COLOR, "WARNING: the server's identity changed");
+                ui.text_wrapped(mismatch.to_string());
+                if ui.button("Forget the old certificate") {
+                    browser.known_hosts.lock().unwrap().forget(&mismatch.host);
+                    browser.status = Some(format!(
+                        "forgot the certificate of {}, it will be trusted on the next connection",
+                        mismatch.host
+                    ));
+                    browser.mismatch = None;
+                }
+            }
+        });
+}
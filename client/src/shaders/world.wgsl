@@ -0,0 +1,59 @@
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tile: vec4<f32>,
+    @location(4) light: f32,
+};
+
+struct InstanceInput {
+    @location(5) origin: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) tile: vec4<f32>,
+    @location(3) light: f32,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_projection * vec4<f32>(vertex.position + instance.origin, 1.0);
+    out.normal = vertex.normal;
+    out.uv = vertex.uv;
+    out.tile = vertex.tile;
+    out.light = vertex.light;
+    return out;
+}
+
+const SUN_DIRECTION: vec3<f32> = vec3<f32>(0.3, 1.0, 0.5);
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // `uv` spans whole blocks across merged quads, wrap it back into the tile.
+    let uv = in.tile.xy + fract(in.uv) * in.tile.zw;
+    let color = textureSample(atlas, atlas_sampler, uv);
+    if color.a < 0.5 {
+        discard;
+    }
+
+    let shade = 0.6 + 0.4 * max(dot(in.normal, normalize(SUN_DIRECTION)), 0.0);
+    let brightness = shade * max(in.light, 0.05);
+    return vec4<f32>(color.rgb * brightness, 1.0);
+}
@@ -1,30 +1,28 @@
-use crate::networking::{TcpEvent, UdpEvent};
-use crate::player::Player;
+use crate::chat::Chat;
+use crate::config::ClientConfig;
+use crate::input::{BindingEditor, InputMap};
 use crate::renderer::Renderer;
-use shared::protocol::{PacketAction, ReliablePacket, UnreliablePacket};
-use std::sync::mpsc::{Receiver, Sender};
+use crate::server_browser::ServerBrowser;
+use crate::world_renderer::WorldRenderer;
+use client::camera::{Camera, CameraController};
+use client::game::GameState;
+use client::meshing::pool::MeshPool;
 use winit::window::Window;
 
 pub struct ClientState {
+    pub config: ClientConfig,
     pub renderer: Renderer,
-    pub player: Player,
     pub window: Window,
 
-    pub tcp_receiver: Receiver<TcpEvent>,
-    pub udp_receiver: Receiver<UdpEvent>,
-    pub packet_action_sender: Sender<PacketAction>,
-}
-
-impl ClientState {
-    pub fn send_packet_action(&self, packet_action: PacketAction) {
-        self.packet_action_sender.send(packet_action).unwrap();
-    }
-
-    pub fn send_reliable_packet(&self, reliable_packet: ReliablePacket) {
-        self.send_packet_action(PacketAction::Reliable(reliable_packet))
-    }
+    pub camera: Camera,
+    pub camera_controller: CameraController,
+    pub cursor_grabbed: bool,
+    pub input_map: InputMap,
+    pub binding_editor: BindingEditor,
+    pub mesh_pool: MeshPool,
+    pub world_renderer: WorldRenderer,
 
-    pub fn send_unreliable_packet(&self, unreliable_packet: UnreliablePacket) {
-        self.send_packet_action(PacketAction::Unreliable(unreliable_packet))
-    }
+    pub server_browser: ServerBrowser,
+    pub chat: Chat,
+    pub game: Option<GameState>,
 }
@@ -1,12 +1,11 @@
 use crate::game_loop::FrameContext;
 use crate::renderer::Renderer;
 use crate::state::ClientState;
-use imgui::StyleVar::SelectableTextAlign;
 use imgui::{Context as ImguiContext, FontConfig, FontSource, MouseCursor, Ui};
 use imgui_wgpu::{Renderer as ImguiRenderer, RendererConfig};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
-use wgpu::{Queue, RenderPass};
-use winit::event::Event;
+use wgpu::RenderPass;
+use winit::event::{Event, WindowEvent};
 use winit::window::Window;
 
 pub struct UiRenderer {
@@ -93,6 +92,10 @@ impl UiRenderer {
         render_pass: &mut RenderPass<'a>,
         ui_fn: fn(&mut FrameContext, &mut ClientState, &mut Ui),
     ) {
+        let io = self.imgui.io_mut();
+        for c in ctx.text_input().chars() {
+            io.add_input_character(c);
+        }
         self.platform
             .prepare_frame(self.imgui.io_mut(), &state.window)
             .expect("failed to prepare imgui frame");
@@ -104,7 +107,7 @@ impl UiRenderer {
         let mouse_cursor_option = ui.mouse_cursor();
         if self.last_cursor != Some(mouse_cursor_option) {
             self.last_cursor = Some(mouse_cursor_option);
-            self.platform.prepare_render(&ui, &state.window);
+            self.platform.prepare_render(ui, &state.window);
         }
 
         self.imgui_renderer
@@ -117,7 +120,16 @@ impl UiRenderer {
             .expect("failed to render imgui");
     }
 
+    /// Passes `event` on to imgui, except for typed characters which it gets from
+    /// `FrameContext::text_input` once per frame.
     pub fn handle_event(&mut self, window: &Window, event: &Event<()>) {
+        if let Event::WindowEvent {
+            event: WindowEvent::ReceivedCharacter(_),
+            ..
+        } = event
+        {
+            return;
+        }
         self.platform
             .handle_event(self.imgui.io_mut(), window, event);
     }
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use shared::block::{BlockFace, BlockId, AIR};
+use shared::chunk::{ChunkSection, SectionPos, SECTION_SIZE};
+
+/// The sections the client knows about, plus the sets of sections whose mesh is out of date or
+/// has to be dropped.
+#[derive(Default)]
+pub struct ClientWorld {
+    sections: HashMap<SectionPos, Arc<ChunkSection>>,
+    dirty: HashSet<SectionPos>,
+    removed: HashSet<SectionPos>,
+}
+
+impl ClientWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn section(&self, pos: SectionPos) -> Option<&Arc<ChunkSection>> {
+        self.sections.get(&pos)
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = (&SectionPos, &Arc<ChunkSection>)> {
+        self.sections.iter()
+    }
+
+    /// Inserts a section, invalidating it and every neighbour since all of its borders changed.
+    pub fn insert_section(&mut self, pos: SectionPos, section: ChunkSection) {
+        self.sections.insert(pos, Arc::new(section));
+        self.removed.remove(&pos);
+        self.dirty.insert(pos);
+        for face in BlockFace::ALL {
+            self.mark_dirty(pos.offset(face));
+        }
+    }
+
+    /// Removes a section, invalidating its neighbours and queueing its mesh to be dropped.
+    pub fn remove_section(&mut self, pos: SectionPos) -> Option<Arc<ChunkSection>> {
+        let section = self.sections.remove(&pos)?;
+        self.dirty.remove(&pos);
+        self.removed.insert(pos);
+        for face in BlockFace::ALL {
+            self.mark_dirty(pos.offset(face));
+        }
+        Some(section)
+    }
+
+    pub fn block(&self, x: i32, y: i32, z: i32) -> BlockId {
+        let pos = SectionPos::of_block(x, y, z);
+        let [lx, ly, lz] = local(x, y, z);
+        self.sections
+            .get(&pos)
+            .map(|section| section.get(lx, ly, lz))
+            .unwrap_or(AIR)
+    }
+
+    /// Sets a block, invalidating its section and only the neighbours sharing the border the
+    /// block sits on.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: BlockId) -> bool {
+        let pos = SectionPos::of_block(x, y, z);
+        let [lx, ly, lz] = local(x, y, z);
+
+        let Some(section) = self.sections.get_mut(&pos) else {
+            return false;
+        };
+        if section.get(lx, ly, lz) == block {
+            return false;
+        }
+        Arc::make_mut(section).set(lx, ly, lz, block);
+
+        self.dirty.insert(pos);
+        let last = SECTION_SIZE - 1;
+        for (coord, low, high) in [
+            (lx, BlockFace::West, BlockFace::East),
+            (ly, BlockFace::Down, BlockFace::Up),
+            (lz, BlockFace::North, BlockFace::South),
+        ] {
+            if coord == 0 {
+                self.mark_dirty(pos.offset(low));
+            } else if coord == last {
+                self.mark_dirty(pos.offset(high));
+            }
+        }
+        true
+    }
+
+    pub fn is_dirty(&self, pos: SectionPos) -> bool {
+        self.dirty.contains(&pos)
+    }
+
+    pub fn take_dirty(&mut self) -> Vec<SectionPos> {
+        self.dirty.drain().collect()
+    }
+
+    pub fn take_removed(&mut self) -> Vec<SectionPos> {
+        self.removed.drain().collect()
+    }
+
+    fn mark_dirty(&mut self, pos: SectionPos) {
+        if self.sections.contains_key(&pos) {
+            self.dirty.insert(pos);
+        }
+    }
+}
+
+fn local(x: i32, y: i32, z: i32) -> [usize; 3] {
+    let size = SECTION_SIZE as i32;
+    [
+        x.rem_euclid(size) as usize,
+        y.rem_euclid(size) as usize,
+        z.rem_euclid(size) as usize,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: SectionPos = SectionPos { x: 0, y: 0, z: 0 };
+
+    /// The origin section and its six neighbours, with nothing dirty.
+    fn world() -> ClientWorld {
+        let mut world = ClientWorld::new();
+        world.insert_section(ORIGIN, ChunkSection::new());
+        for face in BlockFace::ALL {
+            world.insert_section(ORIGIN.offset(face), ChunkSection::new());
+        }
+        world.take_dirty();
+        world
+    }
+
+    fn dirty(world: &mut ClientWorld) -> HashSet<SectionPos> {
+        world.take_dirty().into_iter().collect()
+    }
+
+    fn sections(faces: &[BlockFace]) -> HashSet<SectionPos> {
+        faces
+            .iter()
+            .map(|face| ORIGIN.offset(*face))
+            .chain([ORIGIN])
+            .collect()
+    }
+
+    #[test]
+    fn inner_blocks_only_invalidate_their_section() {
+        let mut world = world();
+        assert!(world.set_block(5, 1, 14, 1));
+        assert_eq!(dirty(&mut world), sections(&[]));
+    }
+
+    #[test]
+    fn border_blocks_invalidate_the_neighbours_sharing_the_border() {
+        let last = SECTION_SIZE as i32 - 1;
+        let mut world = world();
+
+        assert!(world.set_block(0, 5, 5, 1));
+        assert_eq!(dirty(&mut world), sections(&[BlockFace::West]));
+        assert!(world.set_block(last, 5, 5, 1));
+        assert_eq!(dirty(&mut world), sections(&[BlockFace::East]));
+        assert!(world.set_block(5, 0, last, 1));
+        assert_eq!(
+            dirty(&mut world),
+            sections(&[BlockFace::Down, BlockFace::South])
+        );
+        assert!(world.set_block(0, last, 0, 1));
+        assert_eq!(
+            dirty(&mut world),
+            sections(&[BlockFace::West, BlockFace::Up, BlockFace::North])
+        );
+    }
+
+    #[test]
+    fn unchanged_or_unloaded_blocks_invalidate_nothing() {
+        let mut world = world();
+        assert!(!world.set_block(0, 0, 0, AIR));
+        assert!(!world.set_block(100, 0, 0, 1));
+        assert!(world.take_dirty().is_empty());
+        assert_eq!(world.block(100, 0, 0), AIR);
+    }
+
+    #[test]
+    fn removed_sections_are_dropped_and_invalidate_their_neighbours() {
+        let mut world = world();
+        let east = ORIGIN.offset(BlockFace::East);
+        world.set_block(SECTION_SIZE as i32, 0, 0, 1);
+
+        assert!(world.remove_section(east).is_some());
+        assert!(world.remove_section(east).is_none());
+        assert_eq!(world.take_removed(), vec![east]);
+        assert_eq!(dirty(&mut world), HashSet::from([ORIGIN]));
+        assert_eq!(world.block(SECTION_SIZE as i32, 0, 0), AIR);
+
+        // loading it again keeps the old mesh until the new one replaces it
+        world.insert_section(east, ChunkSection::new());
+        assert!(world.remove_section(east).is_some());
+        world.insert_section(east, ChunkSection::new());
+        assert!(world.take_removed().is_empty());
+        assert!(world.is_dirty(east));
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::time::Duration;
+
+use cgmath::EuclideanSpace;
+use client::atlas::TextureAtlas;
+use client::camera::{Camera, CameraUniform};
+use client::culling::{visible_sections, Frustum, SectionVisibility};
+use client::meshing::pool::{MeshResult, MeshView};
+use client::meshing::Vertex;
+use client::world::ClientWorld;
+use shared::chunk::SectionPos;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    vertex_attr_array, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    Buffer, BufferAddress, BufferBindingType, BufferUsages, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, Device, Extent3d, Face, FilterMode, FragmentState,
+    FrontFace, ImageCopyTexture, ImageDataLayout, IndexFormat, MultisampleState,
+    PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline,
+    RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension, VertexAttribute,
+    VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::game_loop::FrameContext;
+use crate::renderer::{Renderer, DEPTH_FORMAT};
+use crate::state::ClientState;
+
+const UPLOAD_BUDGET: Duration = Duration::from_millis(4);
+
+const VERTEX_ATTRIBUTES: [VertexAttribute; 5] = vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4, 4 => Float32];
+const INSTANCE_ATTRIBUTES: [VertexAttribute; 1] = vertex_attr_array![5 => Float32x3];
+
+struct ChunkBuffers {
+    vertices: Buffer,
+    indices: Buffer,
+    origin: Buffer,
+    index_count: u32,
+}
+
+pub struct WorldRenderer {
+    pipeline: RenderPipeline,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup,
+    atlas_bind_group: BindGroup,
+    chunks: HashMap<SectionPos, ChunkBuffers>,
+    visibility: HashMap<SectionPos, SectionVisibility>,
+    visible: Vec<SectionPos>,
+    render_distance: i32,
+}
+
+impl WorldRenderer {
+    pub fn new(renderer: &Renderer, atlas: &TextureAtlas, render_distance: i32) -> Self {
+        let device = renderer.device();
+
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::bytes_of(&CameraUniform::default()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Camera Bind Group Layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        let atlas_image = atlas.image();
+        let atlas_size = Extent3d {
+            width: atlas_image.width,
+            height: atlas_image.height,
+            depth_or_array_layers: 1,
+        };
+        let atlas_texture = device.create_texture(&TextureDescriptor {
+            label: Some("Atlas Texture"),
+            size: atlas_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        renderer.queue().write_texture(
+            ImageCopyTexture {
+                texture: &atlas_texture,
+                mip_level: 0,
+                origin: Default::default(),
+                aspect: TextureAspect::All,
+            },
+            &atlas_image.pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * atlas_image.width),
+                rows_per_image: Some(atlas_image.height),
+            },
+            atlas_size,
+        );
+        let atlas_view = atlas_texture.create_view(&TextureViewDescriptor::default());
+        let atlas_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Atlas Sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let atlas_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Atlas Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let atlas_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Atlas Bind Group"),
+            layout: &atlas_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&atlas_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&atlas_sampler),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("World Shader"),
+            source: ShaderSource::Wgsl(include_str!("shaders/world.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("World Pipeline Layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &atlas_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("World Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    VertexBufferLayout {
+                        array_stride: size_of::<Vertex>() as BufferAddress,
+                        step_mode: VertexStepMode::Vertex,
+                        attributes: &VERTEX_ATTRIBUTES,
+                    },
+                    VertexBufferLayout {
+                        array_stride: size_of::<[f32; 3]>() as BufferAddress,
+                        step_mode: VertexStepMode::Instance,
+                        attributes: &INSTANCE_ATTRIBUTES,
+                    },
+                ],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: renderer.surface_configuration().format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            camera_buffer,
+            camera_bind_group,
+            atlas_bind_group,
+            chunks: HashMap::new(),
+            visibility: HashMap::new(),
+            visible: Vec::new(),
+            render_distance,
+        }
+    }
+
+    pub fn update_camera(&self, queue: &Queue, camera: &Camera) {
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::bytes_of(&CameraUniform::from_camera(camera)),
+        );
+    }
+
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        MeshResult {
+            pos,
+            mesh,
+            visibility,
+        }: MeshResult,
+    ) {
+        self.visibility.insert(pos, visibility);
+
+        if mesh.is_empty() {
+            self.chunks.remove(&pos);
+            return;
+        }
+
+        let [x, y, z] = pos.origin();
+        let buffers = ChunkBuffers {
+            vertices: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Chunk Vertex Buffer"),
+                contents: bytemuck::cast_slice(&mesh.vertices),
+                usage: BufferUsages::VERTEX,
+            }),
+            indices: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Chunk Index Buffer"),
+                contents: bytemuck::cast_slice(&mesh.indices),
+                usage: BufferUsages::INDEX,
+            }),
+            origin: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Chunk Origin Buffer"),
+                contents: bytemuck::cast_slice(&[x as f32, y as f32, z as f32]),
+                usage: BufferUsages::VERTEX,
+            }),
+            index_count: mesh.indices.len() as u32,
+        };
+        self.chunks.insert(pos, buffers);
+    }
+
+    pub fn remove(&mut self, pos: SectionPos) {
+        self.chunks.remove(&pos);
+        self.visibility.remove(&pos);
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.visibility.clear();
+        self.visible.clear();
+    }
+
+    /// Picks the sections to draw this frame. Loaded sections that have not been meshed yet are
+    /// assumed to be see-through.
+    pub fn cull(&mut self, camera: &Camera, world: &ClientWorld) {
+        let frustum = Frustum::from_matrix(camera.view_projection_matrix());
+        self.visible = visible_sections(camera.position, &frustum, self.render_distance, |pos| {
+            world.section(pos).map(|_| {
+                self.visibility
+                    .get(&pos)
+                    .copied()
+                    .unwrap_or(SectionVisibility::ALL)
+            })
+        });
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+
+        for chunk in self.visible.iter().filter_map(|pos| self.chunks.get(pos)) {
+            render_pass.set_vertex_buffer(0, chunk.vertices.slice(..));
+            render_pass.set_vertex_buffer(1, chunk.origin.slice(..));
+            render_pass.set_index_buffer(chunk.indices.slice(..), IndexFormat::Uint32);
+            render_pass.draw_indexed(0..chunk.index_count, 0, 0..1);
+        }
+    }
+}
+
+pub fn update_world_rendering(_ctx: &mut FrameContext, state: &mut ClientState) {
+    state.camera.aspect = state.renderer.aspect();
+
+    state.mesh_pool.set_view(MeshView {
+        position: state.camera.position.to_vec(),
+        direction: state.camera.forward(),
+    });
+    if let Some(game) = &mut state.game {
+        for pos in game.world.take_removed() {
+            state.mesh_pool.cancel(pos);
+            state.world_renderer.remove(pos);
+        }
+        state.mesh_pool.submit_dirty(&mut game.world);
+    }
+
+    let device = state.renderer.device();
+    let world_renderer = &mut state.world_renderer;
+    state.mesh_pool.upload(UPLOAD_BUDGET, |result| {
+        world_renderer.upload(device, result)
+    });
+
+    state
+        .world_renderer
+        .update_camera(state.renderer.queue(), &state.camera);
+    match &state.game {
+        Some(game) => state.world_renderer.cull(&state.camera, &game.world),
+        None => state.world_renderer.visible.clear(),
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use shared::protocol::{validate_username, DisconnectReason};
+use shared::toml_file::{self, TomlFileError};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ban {
+    pub reason: String,
+    /// Who banned, a player or the console.
+    pub by: String,
+    /// Unix time, in seconds, the ban is lifted at. Bans without one are permanent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires.is_none_or(|expires| now < expires)
+    }
+
+    pub fn disconnect_reason(&self) -> DisconnectReason {
+        DisconnectReason::Banned {
+            reason: self.reason.clone(),
+            expires: self.expires,
+        }
+    }
+}
+
+/// Only players listed may join while enabled.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Whitelist {
+    pub enabled: bool,
+    pub players: BTreeSet<String>,
+}
+
+/// Who may not join, read from `access.toml`. Checked during every handshake, so changes apply
+/// to the next player joining.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessList {
+    pub whitelist: Whitelist,
+    pub banned_players: BTreeMap<String, Ban>,
+    pub banned_ips: BTreeMap<IpAddr, Ban>,
+}
+
+impl AccessList {
+    /// Loads the access list at `path`, taking IPv4 addresses mapped to IPv6 as IPv4.
+    pub fn load(path: &Path) -> Result<Self, TomlFileError> {
+        let mut access: Self = toml_file::load(path)?;
+        access
+            .canonicalize_ips()
+            .and_then(|()| access.validate())
+            .map_err(|reason| TomlFileError::Invalid(path.to_path_buf(), reason))?;
+        Ok(access)
+    }
+
+    /// Loads the access list at `path`, writing an empty one there first if the file is
+    /// missing.
+    pub fn load_or_create(path: &Path) -> Result<Self, TomlFileError> {
+        toml_file::load_or_create(path, Self::load)
+    }
+
+    /// Writes the access list to `path`, leaving out the bans that expired.
+    pub fn save(&self, path: &Path) -> Result<(), TomlFileError> {
+        let now = unix_time();
+        let mut access = self.clone();
+        access.banned_players.retain(|_, ban| ban.is_active(now));
+        access.banned_ips.retain(|_, ban| ban.is_active(now));
+        toml_file::save(path, &access)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let usernames = self
+            .whitelist
+            .players
+            .iter()
+            .chain(self.banned_players.keys());
+        for username in usernames {
+            if let Err(err) = validate_username(username) {
+                return Err(format!("{username}: {err}"));
+            }
+        }
+        if let Some(ip) = self.banned_ips.keys().find(|ip| ip.to_canonical() != **ip) {
+            return Err(format!("{ip} is banned as {}", ip.to_canonical()));
+        }
+        Ok(())
+    }
+
+    /// Keys the IP bans by the addresses `ip_ban` looks up, refusing addresses banned twice.
+    fn canonicalize_ips(&mut self) -> Result<(), String> {
+        let mut banned_ips = BTreeMap::new();
+        for (ip, ban) in std::mem::take(&mut self.banned_ips) {
+            let ip = ip.to_canonical();
+            if banned_ips.insert(ip, ban).is_some() {
+                return Err(format!("{ip} is banned twice"));
+            }
+        }
+        self.banned_ips = banned_ips;
+        Ok(())
+    }
+
+    /// The ban on `username`, if still active.
+    pub fn player_ban(&self, username: &str) -> Option<&Ban> {
+        let now = unix_time();
+        self.banned_players
+            .get(username)
+            .filter(|ban| ban.is_active(now))
+    }
+
+    /// The ban on `ip`, if still active. IPv4 addresses mapped to IPv6 are taken as IPv4.
+    pub fn ip_ban(&self, ip: IpAddr) -> Option<&Ban> {
+        let now = unix_time();
+        self.banned_ips
+            .get(&ip.to_canonical())
+            .filter(|ban| ban.is_active(now))
+    }
+
+    /// Why `username`, connecting from `ip`, may not join, if they may not.
+    pub fn check(&self, username: &str, ip: IpAddr) -> Result<(), DisconnectReason> {
+        if let Some(ban) = self.ip_ban(ip).or_else(|| self.player_ban(username)) {
+            return Err(ban.disconnect_reason());
+        }
+        if self.whitelist.enabled && !self.whitelist.players.contains(username) {
+            return Err(DisconnectReason::NotWhitelisted);
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    fn ban(reason: &str, expires: Option<u64>) -> Ban {
+        Ban {
+            reason: reason.to_string(),
+            by: "console".to_string(),
+            expires,
+        }
+    }
+
+    #[test]
+    fn bans_expire() {
+        assert!(ban("spam", None).is_active(u64::MAX));
+        assert!(ban("spam", Some(100)).is_active(99));
+        assert!(!ban("spam", Some(100)).is_active(100));
+
+        let mut access = AccessList::default();
+        access
+            .banned_players
+            .insert("alice".into(), ban("spam", Some(unix_time() - 1)));
+        access.banned_ips.insert(IP, ban("spam", Some(1)));
+        assert_eq!(access.check("alice", IP), Ok(()));
+
+        access
+            .banned_players
+            .insert("alice".into(), ban("spam", Some(unix_time() + 60)));
+        assert!(access.check("alice", IP).is_err());
+    }
+
+    #[test]
+    fn ip_bans_come_before_player_bans() {
+        let mut access = AccessList::default();
+        access
+            .banned_players
+            .insert("alice".into(), ban("griefing", None));
+        access.banned_ips.insert(IP, ban("alts", Some(u64::MAX)));
+
+        assert_eq!(
+            access.check("alice", IP),
+            Err(ban("alts", Some(u64::MAX)).disconnect_reason())
+        );
+        assert_eq!(
+            access.check("bob", IP),
+            Err(ban("alts", Some(u64::MAX)).disconnect_reason())
+        );
+        let elsewhere = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(
+            access.check("alice", elsewhere),
+            Err(ban("griefing", None).disconnect_reason())
+        );
+        assert_eq!(access.check("bob", elsewhere), Ok(()));
+
+        let mapped = IpAddr::V6(Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped());
+        assert!(access.check("bob", mapped).is_err());
+    }
+
+    #[test]
+    fn only_whitelisted_players_join_while_enabled() {
+        let mut access = AccessList::default();
+        access.whitelist.players.insert("alice".into());
+        assert_eq!(access.check("bob", IP), Ok(()));
+
+        access.whitelist.enabled = true;
+        assert_eq!(access.check("alice", IP), Ok(()));
+        assert_eq!(
+            access.check("bob", IP),
+            Err(DisconnectReason::NotWhitelisted)
+        );
+
+        // bans still apply to whitelisted players
+        access
+            .banned_players
+            .insert("alice".into(), ban("spam", None));
+        assert!(access.check("alice", IP).is_err());
+    }
+
+    #[test]
+    fn mapped_addresses_are_loaded_as_ipv4() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.toml");
+        let entry =
+            |ip: &str| format!("[banned_ips.\"{ip}\"]\nreason = \"spam\"\nby = \"console\"\n");
+
+        fs::write(&path, entry("::ffff:10.0.0.1")).unwrap();
+        let access = AccessList::load(&path).unwrap();
+        assert_eq!(access.banned_ips.keys().collect::<Vec<_>>(), [&IP]);
+        assert!(access.validate().is_ok());
+
+        fs::write(&path, entry("::ffff:10.0.0.1") + &entry("10.0.0.1")).unwrap();
+        assert!(matches!(
+            AccessList::load(&path),
+            Err(TomlFileError::Invalid(..))
+        ));
+
+        let mut access = AccessList::default();
+        access.banned_ips.insert(
+            IpAddr::V6(Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped()),
+            ban("spam", None),
+        );
+        assert!(access.validate().is_err());
+    }
+
+    #[test]
+    fn expired_bans_are_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.toml");
+        let mut access = AccessList::load_or_create(&path).unwrap();
+        assert_eq!(access, AccessList::default());
+
+        access
+            .banned_players
+            .insert("alice".into(), ban("spam", Some(1)));
+        access
+            .banned_players
+            .insert("bob".into(), ban("spam", None));
+        access.save(&path).unwrap();
+
+        let saved = AccessList::load(&path).unwrap();
+        assert_eq!(saved.banned_players.keys().collect::<Vec<_>>(), ["bob"]);
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::info;
+
+use shared::protocol::{AuthFailure, Credentials};
+
+use crate::tls::{replace_private, write_private};
+
+/// Decides whether a connecting player is who they claim to be. Called once per handshake,
+/// off the networking tasks, so implementations may block.
+pub trait AuthProvider: Send + Sync {
+    fn authenticate(&self, username: &str, credentials: &Credentials) -> Result<(), AuthFailure>;
+}
+
+/// How players are authenticated, as written in `server.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// Anyone may join under any username.
+    #[default]
+    Offline,
+    /// Local accounts with argon2-hashed passwords.
+    PasswordFile { path: PathBuf },
+    /// Tokens signed with a secret shared with the service issuing them.
+    Token { secret_file: PathBuf },
+}
+
+impl AuthConfig {
+    pub fn create_provider(&self) -> io::Result<Box<dyn AuthProvider>> {
+        Ok(match self {
+            AuthConfig::Offline => Box::new(OfflineAuth),
+            AuthConfig::PasswordFile { path } => Box::new(PasswordFileAuth::load(path)?),
+            AuthConfig::Token { secret_file } => {
+                Box::new(TokenAuth::load_or_generate(secret_file)?)
+            }
+        })
+    }
+}
+
+/// Trusts the username, whatever the credentials.
+pub struct OfflineAuth;
+
+impl AuthProvider for OfflineAuth {
+    fn authenticate(&self, _username: &str, _credentials: &Credentials) -> Result<(), AuthFailure> {
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AccountsFile {
+    #[serde(default)]
+    accounts: BTreeMap<String, String>,
+}
+
+/// Accounts read from a TOML file mapping usernames to argon2 password hashes in PHC format.
+pub struct PasswordFileAuth {
+    accounts: BTreeMap<String, String>,
+    /// Checked against for unknown accounts, so they take as long to refuse as wrong passwords.
+    dummy_hash: String,
+}
+
+impl PasswordFileAuth {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut password = [0; 16];
+        OsRng.fill_bytes(&mut password);
+        Ok(Self {
+            accounts: read_accounts(path)?.accounts,
+            dummy_hash: hash_password(&to_hex(&password))?,
+        })
+    }
+
+    /// Creates or replaces the account `username` in the file at `path`.
+    pub fn set_password(path: &Path, username: &str, password: &str) -> io::Result<()> {
+        let mut file = match read_accounts(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => AccountsFile::default(),
+            result => result?,
+        };
+        file.accounts
+            .insert(username.to_string(), hash_password(password)?);
+
+        let content = toml::to_string_pretty(&file).map_err(io::Error::other)?;
+        replace_private(path, content.as_bytes())
+    }
+}
+
+fn read_accounts(path: &Path) -> io::Result<AccountsFile> {
+    let content = fs::read_to_string(path)?;
+    toml::from_str(&content).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {err}", path.display()),
+        )
+    })
+}
+
+fn hash_password(password: &str) -> io::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| io::Error::other(err.to_string()))
+}
+
+impl AuthProvider for PasswordFileAuth {
+    fn authenticate(&self, username: &str, credentials: &Credentials) -> Result<(), AuthFailure> {
+        let Credentials::Password(password) = credentials else {
+            return Err(AuthFailure::MissingCredentials);
+        };
+        let (hash, known) = match self.accounts.get(username) {
+            Some(hash) => (hash, true),
+            None => (&self.dummy_hash, false),
+        };
+        let hash = PasswordHash::new(hash).map_err(|_| AuthFailure::Unavailable)?;
+
+        let verified = Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok();
+        if !(known && verified) {
+            return Err(AuthFailure::InvalidCredentials);
+        }
+        Ok(())
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Tokens of the form `username.expiry.signature`, where the expiry is in seconds since the
+/// Unix epoch and the signature is the hex HMAC-SHA256 of `username.expiry`.
+pub struct TokenAuth {
+    secret: Vec<u8>,
+}
+
+impl TokenAuth {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self { secret }
+    }
+
+    /// Reads the hex secret at `path`, generating a random one first if the file is missing.
+    pub fn load_or_generate(path: &Path) -> io::Result<Self> {
+        let hex = match fs::read_to_string(path) {
+            Ok(hex) => hex,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                info!("generating a token secret in {}", path.display());
+                let mut secret = [0; 32];
+                OsRng.fill_bytes(&mut secret);
+                let hex = to_hex(&secret);
+                write_private(path, hex.as_bytes())?;
+                hex
+            }
+            Err(err) => return Err(err),
+        };
+
+        let secret = from_hex(hex.trim()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a hex secret", path.display()),
+            )
+        })?;
+        Ok(Self::new(secret))
+    }
+
+    /// Issues a token letting `username` join for `ttl`. Stands in for an external login
+    /// service sharing the secret.
+    pub fn issue(&self, username: &str, ttl: Duration) -> String {
+        let expiry = (SystemTime::now() + ttl)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let payload = format!("{username}.{expiry}");
+        format!(
+            "{payload}.{}",
+            to_hex(&self.sign(&payload).finalize().into_bytes())
+        )
+    }
+
+    fn sign(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+impl AuthProvider for TokenAuth {
+    fn authenticate(&self, username: &str, credentials: &Credentials) -> Result<(), AuthFailure> {
+        let Credentials::Token(token) = credentials else {
+            return Err(AuthFailure::MissingCredentials);
+        };
+
+        let (payload, signature) = token.rsplit_once('.').ok_or(AuthFailure::InvalidToken)?;
+        let signature = from_hex(signature).ok_or(AuthFailure::InvalidToken)?;
+        self.sign(payload)
+            .verify_slice(&signature)
+            .map_err(|_| AuthFailure::InvalidToken)?;
+
+        let (token_username, expiry) = payload.rsplit_once('.').ok_or(AuthFailure::InvalidToken)?;
+        let expiry: u64 = expiry.parse().map_err(|_| AuthFailure::InvalidToken)?;
+        if token_username != username {
+            return Err(AuthFailure::UsernameMismatch);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now >= expiry {
+            return Err(AuthFailure::ExpiredToken);
+        }
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_checked() {
+        let auth = TokenAuth::new(b"secret".to_vec());
+        let token = |token: String| Credentials::Token(token);
+
+        let valid = auth.issue("alice", Duration::from_secs(60));
+        assert_eq!(auth.authenticate("alice", &token(valid.clone())), Ok(()));
+        assert_eq!(
+            auth.authenticate("bob", &token(valid.clone())),
+            Err(AuthFailure::UsernameMismatch)
+        );
+
+        let expired = auth.issue("alice", Duration::ZERO);
+        assert_eq!(
+            auth.authenticate("alice", &token(expired)),
+            Err(AuthFailure::ExpiredToken)
+        );
+
+        // a later expiry under the old signature
+        let (payload, signature) = valid.rsplit_once('.').unwrap();
+        let (username, expiry) = payload.rsplit_once('.').unwrap();
+        let extended = format!(
+            "{username}.{}.{signature}",
+            expiry.parse::<u64>().unwrap() + 1
+        );
+        assert_eq!(
+            auth.authenticate("alice", &token(extended)),
+            Err(AuthFailure::InvalidToken)
+        );
+
+        let forged =
+            TokenAuth::new(b"another secret".to_vec()).issue("alice", Duration::from_secs(60));
+        assert_eq!(
+            auth.authenticate("alice", &token(forged)),
+            Err(AuthFailure::InvalidToken)
+        );
+        assert_eq!(
+            auth.authenticate("alice", &token("alice".to_string())),
+            Err(AuthFailure::InvalidToken)
+        );
+
+        assert_eq!(
+            auth.authenticate("alice", &Credentials::None),
+            Err(AuthFailure::MissingCredentials)
+        );
+    }
+
+    #[test]
+    fn passwords_are_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.toml");
+        PasswordFileAuth::set_password(&path, "alice", "hunter2").unwrap();
+        let auth = PasswordFileAuth::load(&path).unwrap();
+        let password = |password: &str| Credentials::Password(password.to_string());
+
+        assert_eq!(auth.authenticate("alice", &password("hunter2")), Ok(()));
+        // a wrong password and an unknown account look the same
+        assert_eq!(
+            auth.authenticate("alice", &password("hunter3")),
+            Err(AuthFailure::InvalidCredentials)
+        );
+        assert_eq!(
+            auth.authenticate("bob", &password("hunter2")),
+            Err(AuthFailure::InvalidCredentials)
+        );
+        assert_eq!(
+            auth.authenticate("alice", &Credentials::None),
+            Err(AuthFailure::MissingCredentials)
+        );
+    }
+
+    #[test]
+    fn password_files_are_replaced_privately() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.toml");
+        PasswordFileAuth::set_password(&path, "alice", "hunter2").unwrap();
+        PasswordFileAuth::set_password(&path, "bob", "swordfish").unwrap();
+        PasswordFileAuth::set_password(&path, "alice", "hunter3").unwrap();
+
+        let auth = PasswordFileAuth::load(&path).unwrap();
+        let password = |password: &str| Credentials::Password(password.to_string());
+        assert_eq!(auth.authenticate("alice", &password("hunter3")), Ok(()));
+        assert_eq!(auth.authenticate("bob", &password("swordfish")), Ok(()));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn generated_secrets_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token_secret");
+        let auth = TokenAuth::load_or_generate(&path).unwrap();
+        let token = auth.issue("alice", Duration::from_secs(60));
+
+        let reloaded = TokenAuth::load_or_generate(&path).unwrap();
+        assert_eq!(
+            reloaded.authenticate("alice", &Credentials::Token(token)),
+            Ok(())
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
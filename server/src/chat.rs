@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::info;
+
+use shared::protocol::{sanitize_chat, ChatMessage, ChatSender, ReliablePacket};
+
+use crate::permissions::{Permissions, CHAT};
+use crate::player::Player;
+
+/// Messages and commands a player may send in a row before being slowed down.
+const CHAT_BURST: f32 = 5.0;
+/// Messages and commands per second a player may keep sending.
+const CHAT_RATE: f32 = 1.0;
+/// Completion requests a player may send in a row, one per keystroke while typing a command.
+const COMPLETION_BURST: f32 = 20.0;
+/// Completion requests per second a player may keep sending.
+const COMPLETION_RATE: f32 = 10.0;
+
+/// A token bucket holding up to `burst` requests, refilled at `rate` per second.
+pub struct RateLimiter {
+    burst: f32,
+    rate: f32,
+    tokens: f32,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(burst: f32, rate: f32) -> Self {
+        Self {
+            burst,
+            rate,
+            tokens: burst,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Limits chat messages and commands.
+    pub fn chat() -> Self {
+        Self::new(CHAT_BURST, CHAT_RATE)
+    }
+
+    /// Limits command completion requests.
+    pub fn completion() -> Self {
+        Self::new(COMPLETION_BURST, COMPLETION_RATE)
+    }
+
+    /// Takes a token if there is one left.
+    pub fn try_send(&mut self) -> bool {
+        self.try_take(1.0)
+    }
+
+    /// Takes `amount` tokens if there are that many left.
+    pub fn try_take(&mut self, amount: f32) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled_at = now;
+
+        if self.tokens < amount {
+            return false;
+        }
+        self.tokens -= amount;
+        true
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Sends `message` to every player.
+pub fn broadcast(players: &HashMap<u32, Player>, message: ChatMessage) {
+    info!("{message}");
+    for player in players.values() {
+        player.send_reliable_packet(ReliablePacket::ChatBroadcast {
+            message: message.clone(),
+        });
+    }
+}
+
+pub fn broadcast_system(players: &HashMap<u32, Player>, text: impl Into<String>) {
+    broadcast(
+        players,
+        ChatMessage {
+            sender: ChatSender::System,
+            text: text.into(),
+            timestamp: now_millis(),
+        },
+    );
+}
+
+/// Tells only `player` something, e.g. why their message was dropped.
+pub fn send_system(player: &Player, text: impl Into<String>) {
+    player.send_reliable_packet(ReliablePacket::ChatBroadcast {
+        message: ChatMessage {
+            sender: ChatSender::System,
+            text: text.into(),
+            timestamp: now_millis(),
+        },
+    });
+}
+
+/// Broadcasts what player `id` typed, unless they may not chat, are sending too fast or it is
+/// empty once sanitized.
+pub fn handle_chat(
+    players: &mut HashMap<u32, Player>,
+    permissions: &Permissions,
+    id: u32,
+    text: &str,
+) {
+    let Some(player) = players.get_mut(&id) else {
+        return;
+    };
+    if !permissions.check(player.username(), CHAT) {
+        send_system(player, "you are not allowed to chat");
+        return;
+    }
+    if !player.chat_limiter.try_send() {
+        send_system(player, "you are sending messages too fast");
+        return;
+    }
+    let Some(text) = sanitize_chat(text) else {
+        return;
+    };
+
+    let message = ChatMessage {
+        sender: ChatSender::Player(player.username().to_string()),
+        text,
+        timestamp: now_millis(),
+    };
+    broadcast(players, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiters_allow_a_burst_then_slow_down() {
+        let mut limiter = RateLimiter::chat();
+        for _ in 0..CHAT_BURST as usize {
+            assert!(limiter.try_send());
+        }
+        assert!(!limiter.try_send());
+
+        // a second later one more message is allowed
+        limiter.refilled_at -= std::time::Duration::from_secs(1);
+        assert!(limiter.try_send());
+        assert!(!limiter.try_send());
+    }
+}
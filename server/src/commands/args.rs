@@ -0,0 +1,533 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use shared::block::BlockId;
+use shared::protocol::validate_username;
+
+use super::CommandError;
+use crate::state::ServerState;
+
+/// What an argument accepts, deciding how it is parsed and completed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArgKind {
+    /// The username of an online player.
+    Player,
+    /// A valid username, whether its player is online or not.
+    Username,
+    /// Three coordinates, each absolute or relative with `~`, e.g. `~ ~10 -5.5`.
+    Position,
+    /// An IP address, or the username of an online player standing for theirs.
+    Address,
+    /// The name of a registered block.
+    Block,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    /// A number followed by `s`, `m`, `h`, `d` or `w`, e.g. `12h`.
+    Duration,
+    /// One of a fixed set of words.
+    Choice(&'static [&'static str]),
+    /// The name of a command.
+    Command,
+    /// Everything left on the line.
+    Text,
+}
+
+impl ArgKind {
+    /// Words taken from the input.
+    fn width(self) -> usize {
+        match self {
+            ArgKind::Position => 3,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+impl Arg {
+    pub const fn required(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub const fn optional(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+        }
+    }
+}
+
+/// A coordinate, relative to some base when typed with `~`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Coordinate {
+    pub relative: bool,
+    pub value: f32,
+}
+
+impl FromStr for Coordinate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (relative, value) = match s.strip_prefix('~') {
+            Some("") => (true, 0.0),
+            Some(offset) => (true, offset.parse().map_err(|_| s.to_string())?),
+            None => (false, s.parse().map_err(|_| s.to_string())?),
+        };
+        if !f32::is_finite(value) {
+            return Err(s.to_string());
+        }
+        Ok(Self { relative, value })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Position(pub [Coordinate; 3]);
+
+impl Position {
+    /// The absolute position, relative coordinates being added to `base`.
+    pub fn resolve(&self, base: [f32; 3]) -> [f32; 3] {
+        let mut position = base;
+        for (axis, coordinate) in self.0.iter().enumerate() {
+            position[axis] = if coordinate.relative {
+                base[axis] + coordinate.value
+            } else {
+                coordinate.value
+            };
+        }
+        position
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Player(u32),
+    Username(String),
+    Address(IpAddr),
+    Position(Position),
+    Block(BlockId),
+    Integer(i64),
+    Duration(Duration),
+    Choice(&'static str),
+    Command(String),
+    Text(String),
+}
+
+/// The arguments a command was given, by name. Missing optional arguments are absent.
+///
+/// The getters expect the argument to be of the kind its command declared, and panic
+/// otherwise.
+#[derive(Debug, Default)]
+pub struct Args {
+    values: Vec<(&'static str, Value)>,
+}
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(other, _)| *other == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn player(&self, name: &str) -> Option<u32> {
+        self.get(name).map(|value| match value {
+            Value::Player(id) => *id,
+            value => panic!("argument {name} is not a player: {value:?}"),
+        })
+    }
+
+    pub fn username(&self, name: &str) -> Option<&str> {
+        self.get(name).map(|value| match value {
+            Value::Username(username) => username.as_str(),
+            value => panic!("argument {name} is not a username: {value:?}"),
+        })
+    }
+
+    pub fn address(&self, name: &str) -> Option<IpAddr> {
+        self.get(name).map(|value| match value {
+            Value::Address(address) => *address,
+            value => panic!("argument {name} is not an address: {value:?}"),
+        })
+    }
+
+    pub fn position(&self, name: &str) -> Option<Position> {
+        self.get(name).map(|value| match value {
+            Value::Position(position) => *position,
+            value => panic!("argument {name} is not a position: {value:?}"),
+        })
+    }
+
+    pub fn block(&self, name: &str) -> Option<BlockId> {
+        self.get(name).map(|value| match value {
+            Value::Block(block) => *block,
+            value => panic!("argument {name} is not a block: {value:?}"),
+        })
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        self.get(name).map(|value| match value {
+            Value::Integer(integer) => *integer,
+            value => panic!("argument {name} is not an integer: {value:?}"),
+        })
+    }
+
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        self.get(name).map(|value| match value {
+            Value::Duration(duration) => *duration,
+            value => panic!("argument {name} is not a duration: {value:?}"),
+        })
+    }
+
+    pub fn choice(&self, name: &str) -> Option<&'static str> {
+        self.get(name).map(|value| match value {
+            Value::Choice(choice) => *choice,
+            value => panic!("argument {name} is not a choice: {value:?}"),
+        })
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.get(name).map(|value| match value {
+            Value::Text(text) | Value::Command(text) => text.as_str(),
+            value => panic!("argument {name} is not text: {value:?}"),
+        })
+    }
+}
+
+/// The words of `input` with the byte they start at.
+pub(super) fn words(input: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in input.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, &input[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &input[s..]));
+    }
+    words
+}
+
+/// Parses `words`, following the command name, as `args`. `input` is the whole line, which
+/// `ArgKind::Text` takes the rest of.
+pub(super) fn parse(
+    state: &ServerState,
+    args: &[Arg],
+    input: &str,
+    words: &[(usize, &str)],
+) -> Result<Args, CommandError> {
+    let mut parsed = Args::default();
+    let mut next = 0;
+    for arg in args {
+        if next >= words.len() {
+            if arg.optional {
+                break;
+            }
+            return Err(CommandError::Usage(format!("missing <{}>", arg.name)));
+        }
+
+        let value = match arg.kind {
+            ArgKind::Text => {
+                let text = input[words[next].0..].trim_end().to_string();
+                next = words.len();
+                Value::Text(text)
+            }
+            ArgKind::Position => {
+                let Some(coordinates) = words.get(next..next + 3) else {
+                    return Err(CommandError::Usage(format!(
+                        "<{}> takes three coordinates",
+                        arg.name
+                    )));
+                };
+                let mut position = [Coordinate {
+                    relative: true,
+                    value: 0.0,
+                }; 3];
+                for (coordinate, (_, word)) in position.iter_mut().zip(coordinates) {
+                    *coordinate = word.parse().map_err(|word| {
+                        CommandError::Invalid(format!("\"{word}\" is not a coordinate"))
+                    })?;
+                }
+                next += 3;
+                Value::Position(Position(position))
+            }
+            kind => {
+                let word = words[next].1;
+                next += 1;
+                parse_word(state, kind, word)?
+            }
+        };
+        parsed.values.push((arg.name, value));
+    }
+
+    if next < words.len() {
+        return Err(CommandError::Usage(format!(
+            "unexpected \"{}\"",
+            input[words[next].0..].trim_end()
+        )));
+    }
+    Ok(parsed)
+}
+
+fn parse_word(state: &ServerState, kind: ArgKind, word: &str) -> Result<Value, CommandError> {
+    let invalid = |reason: String| Err(CommandError::Invalid(reason));
+    match kind {
+        ArgKind::Player => match state.player_by_name(word) {
+            Some(player) => Ok(Value::Player(player.id())),
+            None => invalid(format!("{word} is not online")),
+        },
+        ArgKind::Username => match validate_username(word) {
+            Ok(()) => Ok(Value::Username(word.to_string())),
+            Err(err) => invalid(format!("\"{word}\": {err}")),
+        },
+        ArgKind::Address => match (word.parse::<IpAddr>(), state.player_by_name(word)) {
+            (Ok(address), _) => Ok(Value::Address(address.to_canonical())),
+            (_, Some(player)) => Ok(Value::Address(player.addr().ip().to_canonical())),
+            _ => invalid(format!("{word} is neither an IP address nor online")),
+        },
+        ArgKind::Block => match state.blocks.id(word) {
+            Some(block) => Ok(Value::Block(block)),
+            None => invalid(format!("unknown block \"{word}\"")),
+        },
+        ArgKind::Integer { min, max } => match word.parse::<i64>() {
+            Ok(integer) if (min..=max).contains(&integer) => Ok(Value::Integer(integer)),
+            Ok(_) => invalid(format!("{word} is not between {min} and {max}")),
+            Err(_) => invalid(format!("\"{word}\" is not an integer")),
+        },
+        ArgKind::Duration => match parse_duration(word) {
+            Some(duration) => Ok(Value::Duration(duration)),
+            None => invalid(format!(
+                "\"{word}\" is not a duration such as 30m, 12h or 7d"
+            )),
+        },
+        ArgKind::Choice(choices) => match choices.iter().find(|choice| **choice == word) {
+            Some(choice) => Ok(Value::Choice(choice)),
+            None => invalid(format!("\"{word}\" is not one of {}", choices.join(", "))),
+        },
+        ArgKind::Command => Ok(Value::Command(word.to_string())),
+        ArgKind::Position | ArgKind::Text => unreachable!("parsed by `parse`"),
+    }
+}
+
+fn parse_duration(word: &str) -> Option<Duration> {
+    let unit = match word.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = word[..word.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(count.checked_mul(unit)?)).filter(|duration| !duration.is_zero())
+}
+
+/// The argument the `index`th word after the command name belongs to.
+pub(super) fn arg_at(args: &[Arg], index: usize) -> Option<Arg> {
+    let mut start = 0;
+    for arg in args {
+        if arg.kind == ArgKind::Text || index < start + arg.kind.width() {
+            return Some(*arg);
+        }
+        start += arg.kind.width();
+    }
+    None
+}
+
+/// Values of `kind` starting with `prefix`, except command names which the caller knows.
+pub(super) fn complete(state: &ServerState, kind: ArgKind, prefix: &str) -> Vec<String> {
+    let candidates: Vec<String> = match kind {
+        ArgKind::Player | ArgKind::Username | ArgKind::Address => state
+            .players
+            .values()
+            .map(|player| player.username().to_string())
+            .collect(),
+        ArgKind::Block => state
+            .blocks
+            .blocks()
+            .map(|(_, block)| block.name().to_string())
+            .collect(),
+        ArgKind::Choice(choices) => choices.iter().map(|choice| choice.to_string()).collect(),
+        ArgKind::Position => vec!["~".to_string()],
+        ArgKind::Integer { .. } | ArgKind::Duration | ArgKind::Command | ArgKind::Text => {
+            Vec::new()
+        }
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinate(relative: bool, value: f32) -> Coordinate {
+        Coordinate { relative, value }
+    }
+
+    #[test]
+    fn coordinates_are_absolute_or_relative() {
+        assert_eq!("~".parse(), Ok(coordinate(true, 0.0)));
+        assert_eq!("~10".parse(), Ok(coordinate(true, 10.0)));
+        assert_eq!("~-5.5".parse(), Ok(coordinate(true, -5.5)));
+        assert_eq!("64".parse(), Ok(coordinate(false, 64.0)));
+        assert_eq!("-0.25".parse(), Ok(coordinate(false, -0.25)));
+
+        for word in [
+            "", "x", "~x", "~~", "10~", "inf", "~inf", "-inf", "NaN", "~NaN", "1e39",
+        ] {
+            assert_eq!(word.parse::<Coordinate>(), Err(word.to_string()));
+        }
+    }
+
+    #[test]
+    fn relative_coordinates_resolve_against_the_base() {
+        let position = Position([
+            coordinate(false, 1.0),
+            coordinate(true, 2.0),
+            coordinate(true, 0.0),
+        ]);
+        assert_eq!(position.resolve([10.0, 20.0, 30.0]), [1.0, 22.0, 30.0]);
+    }
+
+    #[test]
+    fn durations_need_a_count_and_a_unit() {
+        let secs = |secs| Some(Duration::from_secs(secs));
+        assert_eq!(parse_duration("30s"), secs(30));
+        assert_eq!(parse_duration("5m"), secs(300));
+        assert_eq!(parse_duration("12h"), secs(12 * 3600));
+        assert_eq!(parse_duration("7d"), secs(7 * 86400));
+        assert_eq!(parse_duration("2w"), secs(14 * 86400));
+
+        for word in [
+            "",
+            "h",
+            "12",
+            "12x",
+            "0s",
+            "-1h",
+            "1.5h",
+            "5ms",
+            "1é",
+            "99999999999999999w",
+        ] {
+            assert_eq!(parse_duration(word), None, "{word}");
+        }
+    }
+
+    const TP: &[Arg] = &[
+        Arg::required("player", ArgKind::Username),
+        Arg::required("position", ArgKind::Position),
+        Arg::optional("count", ArgKind::Integer { min: 1, max: 64 }),
+    ];
+
+    fn parse_line(args: &[Arg], input: &str) -> Result<Args, CommandError> {
+        parse(&ServerState::for_tests(), args, input, &words(input))
+    }
+
+    #[test]
+    fn arguments_are_parsed_by_kind() {
+        let args = parse_line(TP, "alice ~ 64 ~-2 3").unwrap();
+        assert_eq!(args.username("player"), Some("alice"));
+        assert_eq!(
+            args.position("position"),
+            Some(Position([
+                coordinate(true, 0.0),
+                coordinate(false, 64.0),
+                coordinate(true, -2.0)
+            ]))
+        );
+        assert_eq!(args.integer("count"), Some(3));
+
+        // optional arguments may be left out
+        let args = parse_line(TP, "  alice 1 2 3 ").unwrap();
+        assert_eq!(args.integer("count"), None);
+
+        let text = [Arg::required("reason", ArgKind::Text)];
+        let args = parse_line(&text, "griefing  the spawn ").unwrap();
+        assert_eq!(args.text("reason"), Some("griefing  the spawn"));
+    }
+
+    #[test]
+    fn missing_extra_and_bad_words_are_reported() {
+        let usage = |input| match parse_line(TP, input) {
+            Err(CommandError::Usage(usage)) => usage,
+            result => panic!("{input:?} parsed as {result:?}"),
+        };
+        assert_eq!(usage(""), "missing <player>");
+        assert_eq!(usage("alice"), "missing <position>");
+        assert_eq!(usage("alice 1 2"), "<position> takes three coordinates");
+        assert_eq!(usage("alice 1 2 3 4 5 6"), "unexpected \"5 6\"");
+
+        let invalid = |input| matches!(parse_line(TP, input), Err(CommandError::Invalid(_)));
+        assert!(invalid("alice 1 two 3"));
+        assert!(invalid("alice 1 2 3 65"));
+        assert!(invalid("al!ce 1 2 3"));
+    }
+
+    #[test]
+    fn words_belong_to_the_argument_they_fall_in() {
+        let args = [
+            Arg::required("player", ArgKind::Player),
+            Arg::required("position", ArgKind::Position),
+            Arg::optional("reason", ArgKind::Text),
+        ];
+        let kinds: Vec<_> = (0..7)
+            .map(|index| arg_at(&args, index).map(|arg| arg.name))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Some("player"),
+                Some("position"),
+                Some("position"),
+                Some("position"),
+                Some("reason"),
+                Some("reason"),
+                Some("reason"),
+            ]
+        );
+        assert_eq!(arg_at(&TP[..2], 4), None);
+    }
+
+    #[test]
+    fn completions_start_with_the_prefix() {
+        let mut state = ServerState::for_tests();
+        state.add_player("alice");
+        state.add_player("albert");
+        state.add_player("bob");
+
+        let mut players = complete(&state, ArgKind::Player, "al");
+        players.sort();
+        assert_eq!(players, ["albert", "alice"]);
+        assert_eq!(complete(&state, ArgKind::Address, "b"), ["bob"]);
+        assert_eq!(complete(&state, ArgKind::Block, "st"), ["stone"]);
+        assert_eq!(
+            complete(&state, ArgKind::Choice(&["on", "off", "list"]), "o"),
+            ["on", "off"]
+        );
+        assert_eq!(complete(&state, ArgKind::Position, ""), ["~"]);
+        assert!(complete(&state, ArgKind::Integer { min: 0, max: 9 }, "").is_empty());
+        assert!(complete(&state, ArgKind::Block, "zzz").is_empty());
+    }
+}
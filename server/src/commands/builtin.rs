@@ -0,0 +1,398 @@
+use shared::protocol::{DisconnectReason, PacketAction, ReliablePacket};
+
+use super::{
+    can_use, source_name, Arg, ArgKind, Args, Command, CommandContext, CommandError,
+    CommandRegistry, CommandResult,
+};
+use crate::chat;
+use crate::permissions::Permissions;
+use crate::player::GameMode;
+use crate::state::DAY_LENGTH;
+
+const MAX_GIVE: i64 = 6400;
+
+pub(super) fn register(registry: &mut CommandRegistry) {
+    registry.register(Command {
+        name: "help",
+        args: vec![Arg::optional("command", ArgKind::Command)],
+        help: "Lists the commands you can use, or explains one.",
+        permission: "command.help",
+        run: help,
+    });
+    registry.register(Command {
+        name: "list",
+        args: Vec::new(),
+        help: "Lists the players online.",
+        permission: "command.list",
+        run: list,
+    });
+    registry.register(Command {
+        name: "seed",
+        args: Vec::new(),
+        help: "Shows the world seed.",
+        permission: "command.seed",
+        run: seed,
+    });
+    registry.register(Command {
+        name: "tp",
+        args: vec![
+            Arg::required("player", ArgKind::Player),
+            Arg::required("position", ArgKind::Position),
+        ],
+        help: "Teleports a player. Coordinates with ~ are relative to that player.",
+        permission: "command.tp",
+        run: tp,
+    });
+    registry.register(Command {
+        name: "give",
+        args: vec![
+            Arg::required("player", ArgKind::Player),
+            Arg::required("block", ArgKind::Block),
+            Arg::optional(
+                "count",
+                ArgKind::Integer {
+                    min: 1,
+                    max: MAX_GIVE,
+                },
+            ),
+        ],
+        help: "Gives a player blocks.",
+        permission: "command.give",
+        run: give,
+    });
+    registry.register(Command {
+        name: "time",
+        args: vec![
+            Arg::required("action", ArgKind::Choice(&["query", "set", "add"])),
+            Arg::optional(
+                "ticks",
+                ArgKind::Integer {
+                    min: 0,
+                    max: i32::MAX as i64,
+                },
+            ),
+        ],
+        help: "Shows or changes the time of day, in ticks.",
+        permission: "command.time",
+        run: time,
+    });
+    registry.register(Command {
+        name: "gamemode",
+        args: vec![
+            Arg::required("mode", ArgKind::Choice(GameMode::NAMES)),
+            Arg::optional("player", ArgKind::Player),
+        ],
+        help: "Changes the game mode of a player, yourself by default.",
+        permission: "command.gamemode",
+        run: gamemode,
+    });
+    registry.register(Command {
+        name: "stop",
+        args: Vec::new(),
+        help: "Saves the world, disconnects everyone and stops the server.",
+        permission: "command.stop",
+        run: stop,
+    });
+    registry.register(Command {
+        name: "op",
+        args: vec![Arg::required("player", ArgKind::Username)],
+        help: "Lets a player do anything.",
+        permission: "command.op",
+        run: op,
+    });
+    registry.register(Command {
+        name: "deop",
+        args: vec![Arg::required("player", ArgKind::Username)],
+        help: "Takes away what a player could do as an operator.",
+        permission: "command.deop",
+        run: deop,
+    });
+    registry.register(Command {
+        name: "permissions",
+        args: vec![
+            Arg::required("action", ArgKind::Choice(&["reload", "check"])),
+            Arg::optional("player", ArgKind::Username),
+            Arg::optional("node", ArgKind::Text),
+        ],
+        help: "Reloads the permissions file, or tells whether a player has a permission.",
+        permission: "command.permissions",
+        run: permissions,
+    });
+    registry.register(Command {
+        name: "kick",
+        args: vec![
+            Arg::required("player", ArgKind::Player),
+            Arg::optional("reason", ArgKind::Text),
+        ],
+        help: "Disconnects a player.",
+        permission: "command.kick",
+        run: kick,
+    });
+}
+
+fn help(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let commands = ctx.state.commands.clone();
+    if let Some(name) = args.text("command") {
+        let name = name.trim_start_matches('/');
+        let command = commands
+            .get(name)
+            .filter(|command| can_use(ctx.state, ctx.source, command))
+            .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+        return Ok(format!("{}\n{}", command.usage(), command.help));
+    }
+
+    let usages: Vec<String> = commands
+        .commands()
+        .filter(|command| can_use(ctx.state, ctx.source, command))
+        .map(|command| format!("{}: {}", command.usage(), command.help))
+        .collect();
+    Ok(usages.join("\n"))
+}
+
+fn list(ctx: &mut CommandContext, _args: &Args) -> CommandResult {
+    let mut usernames: Vec<&str> = ctx
+        .state
+        .players
+        .values()
+        .map(|player| player.username())
+        .collect();
+    usernames.sort_unstable();
+    Ok(format!(
+        "{}/{} players online: {}",
+        usernames.len(),
+        ctx.state.config.max_players,
+        usernames.join(", ")
+    ))
+}
+
+fn seed(ctx: &mut CommandContext, _args: &Args) -> CommandResult {
+    Ok(format!("seed: {}", ctx.state.seed))
+}
+
+fn tp(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let id = args.player("player").expect("required");
+    let position = args.position("position").expect("required");
+    let player = ctx
+        .state
+        .players
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::Failed("the player left".to_string()))?;
+
+    let [x, y, z] = position.resolve(player.position);
+    player.position = [x, y, z];
+    player.send_reliable_packet(ReliablePacket::Teleport {
+        position: player.position,
+    });
+    Ok(format!(
+        "teleported {} to {x:.1} {y:.1} {z:.1}",
+        player.username()
+    ))
+}
+
+fn give(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let id = args.player("player").expect("required");
+    let block = args.block("block").expect("required");
+    let count = args.integer("count").unwrap_or(1) as u32;
+    let name = ctx
+        .state
+        .blocks
+        .get(block)
+        .map_or("?", |block| block.name());
+    let player = ctx
+        .state
+        .players
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::Failed("the player left".to_string()))?;
+
+    let held = player.inventory.entry(block).or_default();
+    *held = held.saturating_add(count);
+    chat::send_system(player, format!("you received {count} {name}"));
+    Ok(format!("gave {count} {name} to {}", player.username()))
+}
+
+fn time(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let action = args.choice("action").expect("required");
+    let ticks = args.integer("ticks").map(|ticks| ticks as u64);
+    match (action, ticks) {
+        ("query", _) => {}
+        ("set", Some(ticks)) => ctx.state.time = ticks,
+        ("add", Some(ticks)) => ctx.state.time += ticks,
+        _ => return Err(CommandError::Usage(format!("/time {action} needs <ticks>"))),
+    }
+
+    let time = ctx.state.time;
+    Ok(format!(
+        "day {}, time {} of {DAY_LENGTH}",
+        time / DAY_LENGTH,
+        time % DAY_LENGTH
+    ))
+}
+
+fn gamemode(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let mode: GameMode = args
+        .choice("mode")
+        .expect("required")
+        .parse()
+        .map_err(CommandError::Invalid)?;
+    let id = match args.player("player") {
+        Some(id) => id,
+        None => ctx.player_source()?,
+    };
+    let player = ctx
+        .state
+        .players
+        .get_mut(&id)
+        .ok_or_else(|| CommandError::Failed("the player left".to_string()))?;
+
+    player.game_mode = mode;
+    chat::send_system(player, format!("your game mode is now {mode}"));
+    Ok(format!(
+        "set the game mode of {} to {mode}",
+        player.username()
+    ))
+}
+
+fn kick(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let id = args.player("player").expect("required");
+    let reason = match args.text("reason") {
+        Some(reason) => reason.to_string(),
+        None => format!("kicked by {}", source_name(ctx.state, ctx.source)),
+    };
+    let player = ctx
+        .state
+        .players
+        .get(&id)
+        .ok_or_else(|| CommandError::Failed("the player left".to_string()))?;
+
+    player.send_packet_action(PacketAction::Disconnect(DisconnectReason::Kicked(
+        reason.clone(),
+    )));
+    Ok(format!("kicked {}: {reason}", player.username()))
+}
+
+fn stop(ctx: &mut CommandContext, _args: &Args) -> CommandResult {
+    ctx.state.stop();
+    Ok("stopping the server".to_string())
+}
+
+fn op(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let username = args.username("player").expect("required");
+    update_permissions(ctx, |permissions| {
+        if !permissions.ops.insert(username.to_string()) {
+            return Err(CommandError::Failed(format!(
+                "{username} is already an operator"
+            )));
+        }
+        Ok(())
+    })?;
+    if let Some(player) = ctx.state.player_by_name(username) {
+        chat::send_system(player, "you are now an operator");
+    }
+    Ok(format!("made {username} an operator"))
+}
+
+fn deop(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let username = args.username("player").expect("required");
+    update_permissions(ctx, |permissions| {
+        if !permissions.ops.remove(username) {
+            return Err(CommandError::Failed(format!(
+                "{username} is not an operator"
+            )));
+        }
+        Ok(())
+    })?;
+    if let Some(player) = ctx.state.player_by_name(username) {
+        chat::send_system(player, "you are no longer an operator");
+    }
+    Ok(format!("{username} is no longer an operator"))
+}
+
+fn permissions(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    match args.choice("action").expect("required") {
+        "reload" => {
+            let path = &ctx.state.config.permissions;
+            ctx.state.permissions =
+                Permissions::load(path).map_err(|err| CommandError::Failed(err.to_string()))?;
+            Ok(format!("reloaded {}", path.display()))
+        }
+        _ => {
+            let (Some(username), Some(node)) = (args.username("player"), args.text("node")) else {
+                return Err(CommandError::Usage(
+                    "/permissions check needs <player> and <node>".to_string(),
+                ));
+            };
+            let verb = if ctx.state.permissions.check(username, node) {
+                "has"
+            } else {
+                "lacks"
+            };
+            Ok(format!("{username} {verb} {node}"))
+        }
+    }
+}
+
+/// Changes a copy of the permissions with `change` and writes it to their file, only putting it
+/// in place once saved so the permissions in use never differ from those on disk.
+fn update_permissions(
+    ctx: &mut CommandContext,
+    change: impl FnOnce(&mut Permissions) -> Result<(), CommandError>,
+) -> Result<(), CommandError> {
+    let mut updated = ctx.state.permissions.clone();
+    change(&mut updated)?;
+    updated
+        .save(&ctx.state.config.permissions)
+        .map_err(|err| CommandError::Failed(err.to_string()))?;
+    ctx.state.permissions = updated;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandSource;
+    use crate::state::ServerState;
+
+    #[test]
+    fn giving_saturates_instead_of_overflowing() {
+        let mut state = ServerState::for_tests();
+        let id = state.add_player("alice");
+        let stone = state.blocks.id("stone").unwrap();
+        state
+            .players
+            .get_mut(&id)
+            .unwrap()
+            .inventory
+            .insert(stone, u32::MAX - 1);
+
+        let commands = state.commands.clone();
+        let input = format!("give alice stone {MAX_GIVE}");
+        commands
+            .execute(&mut state, CommandSource::Console, &input)
+            .unwrap();
+        assert_eq!(state.players[&id].inventory[&stone], u32::MAX);
+    }
+
+    #[test]
+    fn operators_change_only_once_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = ServerState::for_tests();
+        let commands = state.commands.clone();
+        let run = |state: &mut ServerState, input: &str| {
+            commands.execute(state, CommandSource::Console, input)
+        };
+
+        state.config.permissions = dir.path().join("missing").join("permissions.toml");
+        assert!(run(&mut state, "op alice").is_err());
+        assert!(!state.permissions.is_op("alice"));
+
+        state.config.permissions = dir.path().join("permissions.toml");
+        run(&mut state, "op alice").unwrap();
+        assert!(state.permissions.is_op("alice"));
+        let saved = Permissions::load(&state.config.permissions).unwrap();
+        assert!(saved.is_op("alice"));
+
+        state.config.permissions = dir.path().join("missing").join("permissions.toml");
+        assert!(run(&mut state, "deop alice").is_err());
+        assert!(state.permissions.is_op("alice"));
+    }
+}
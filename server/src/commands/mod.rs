@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use tracing::info;
+
+use crate::chat;
+use crate::state::ServerState;
+
+pub use args::{Arg, ArgKind, Args, Coordinate, Position, Value};
+
+mod args;
+mod builtin;
+mod moderation;
+
+/// Who runs a command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandSource {
+    Console,
+    Player(u32),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    NoPermission,
+    /// The arguments do not match the command's, the usage is shown along.
+    Usage(String),
+    /// An argument has the right shape but is not acceptable, e.g. an offline player.
+    Invalid(String),
+    /// The command could not do what it was asked.
+    Failed(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Unknown(name) => write!(f, "unknown command \"{name}\", try /help"),
+            CommandError::NoPermission => write!(f, "you are not allowed to use this command"),
+            CommandError::Usage(reason) | CommandError::Invalid(reason) => write!(f, "{reason}"),
+            CommandError::Failed(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+pub type CommandResult = Result<String, CommandError>;
+
+pub struct CommandContext<'a> {
+    pub state: &'a mut ServerState,
+    pub source: CommandSource,
+}
+
+impl CommandContext<'_> {
+    /// The player running the command, failing for the console.
+    pub fn player_source(&self) -> Result<u32, CommandError> {
+        match self.source {
+            CommandSource::Player(id) => Ok(id),
+            CommandSource::Console => Err(CommandError::Failed(
+                "only players can do that, name one".to_string(),
+            )),
+        }
+    }
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub args: Vec<Arg>,
+    pub help: &'static str,
+    /// Permission node players need, such as `command.tp`.
+    pub permission: &'static str,
+    pub run: fn(&mut CommandContext, &Args) -> CommandResult,
+}
+
+impl Command {
+    /// `/name <required> [optional]`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in &self.args {
+            if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+}
+
+/// Every command the server knows, by name.
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Command>,
+}
+
+impl Default for CommandRegistry {
+    /// The built-in commands.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        builtin::register(&mut registry);
+        moderation::register(&mut registry);
+        registry
+    }
+}
+
+impl CommandRegistry {
+    pub fn empty() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.values()
+    }
+
+    /// Runs `input`, a command line without the leading `/`, on behalf of `source`.
+    pub fn execute(
+        &self,
+        state: &mut ServerState,
+        source: CommandSource,
+        input: &str,
+    ) -> CommandResult {
+        let words = args::words(input);
+        let Some((_, name)) = words.first() else {
+            return Err(CommandError::Usage(
+                "type /help to list commands".to_string(),
+            ));
+        };
+        let command = self
+            .get(name)
+            .ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+        if !can_use(state, source, command) {
+            return Err(CommandError::NoPermission);
+        }
+
+        info!("{} ran /{input}", source_name(state, source));
+        let args =
+            args::parse(state, &command.args, input, &words[1..]).map_err(|err| match err {
+                CommandError::Usage(reason) => {
+                    CommandError::Usage(format!("{reason}, usage: {}", command.usage()))
+                }
+                err => err,
+            })?;
+        (command.run)(&mut CommandContext { state, source }, &args)
+    }
+
+    /// Ways to complete the last word of `input`, with the character they replace `input`
+    /// from.
+    pub fn complete(
+        &self,
+        state: &ServerState,
+        source: CommandSource,
+        input: &str,
+    ) -> (usize, Vec<String>) {
+        let mut words = args::words(input);
+        if input.is_empty() || input.ends_with(char::is_whitespace) {
+            words.push((input.len(), ""));
+        }
+        let Some(&(start, prefix)) = words.last() else {
+            return (0, Vec::new());
+        };
+        let start_char = input[..start].chars().count();
+
+        let suggestions = if words.len() == 1 {
+            self.command_names(state, source, prefix)
+        } else {
+            match self.get(words[0].1) {
+                Some(command) if can_use(state, source, command) => {
+                    match args::arg_at(&command.args, words.len() - 2) {
+                        Some(Arg {
+                            kind: ArgKind::Command,
+                            ..
+                        }) => self.command_names(state, source, prefix),
+                        Some(arg) => args::complete(state, arg.kind, prefix),
+                        None => Vec::new(),
+                    }
+                }
+                _ => Vec::new(),
+            }
+        };
+        (start_char, suggestions)
+    }
+
+    fn command_names(
+        &self,
+        state: &ServerState,
+        source: CommandSource,
+        prefix: &str,
+    ) -> Vec<String> {
+        self.commands()
+            .filter(|command| command.name.starts_with(prefix) && can_use(state, source, command))
+            .map(|command| command.name.to_string())
+            .collect()
+    }
+}
+
+/// Whether `source` may run `command`, which the console always may.
+pub fn can_use(state: &ServerState, source: CommandSource, command: &Command) -> bool {
+    match source {
+        CommandSource::Console => true,
+        CommandSource::Player(id) => state.has_permission(id, command.permission),
+    }
+}
+
+pub fn source_name(state: &ServerState, source: CommandSource) -> String {
+    match source {
+        CommandSource::Console => "the console".to_string(),
+        CommandSource::Player(id) => state.players.get(&id).map_or_else(
+            || format!("player {id}"),
+            |player| player.username().to_string(),
+        ),
+    }
+}
+
+/// Runs a command typed by player `id` and tells them how it went.
+pub fn run_player_command(state: &mut ServerState, id: u32, input: &str) {
+    let commands = state.commands.clone();
+    let result = commands.execute(state, CommandSource::Player(id), input);
+    let Some(player) = state.players.get(&id) else {
+        return;
+    };
+
+    let output = match result {
+        Ok(output) => output,
+        Err(err) => err.to_string(),
+    };
+    for line in output.lines() {
+        chat::send_system(player, line);
+    }
+}
+
+/// A command line sent by the console, whose output lines are sent back through `output`.
+pub struct ConsoleCommand {
+    input: String,
+    output: Sender<String>,
+}
+
+/// Runs commands on the game loop on behalf of the console.
+#[derive(Clone)]
+pub struct Console {
+    sender: Sender<ConsoleCommand>,
+}
+
+impl Console {
+    pub fn new() -> (Self, Receiver<ConsoleCommand>) {
+        let (sender, receiver) = channel();
+        (Self { sender }, receiver)
+    }
+
+    /// Queues `input` for the next tick. The returned receiver yields the output lines and
+    /// disconnects once the command ran, or right away if the server stopped.
+    pub fn run(&self, input: &str) -> Receiver<String> {
+        let (output, receiver) = channel();
+        let _ = self.sender.send(ConsoleCommand {
+            input: input.trim().trim_start_matches('/').to_string(),
+            output,
+        });
+        receiver
+    }
+}
+
+/// Runs the commands the console queued since the last tick.
+pub fn run_console_commands(state: &mut ServerState) {
+    let commands = state.commands.clone();
+    while let Ok(command) = state.console_receiver.try_recv() {
+        let output = match commands.execute(state, CommandSource::Console, &command.input) {
+            Ok(output) => output,
+            Err(err) => err.to_string(),
+        };
+        for line in output.lines() {
+            let _ = command.output.send(line.to_string());
+        }
+    }
+}
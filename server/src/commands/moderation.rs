@@ -0,0 +1,253 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use shared::protocol::{DisconnectReason, PacketAction};
+
+use super::{
+    source_name, Arg, ArgKind, Args, Command, CommandContext, CommandError, CommandRegistry,
+    CommandResult,
+};
+use crate::access::{unix_time, AccessList, Ban};
+
+pub(super) fn register(registry: &mut CommandRegistry) {
+    registry.register(Command {
+        name: "ban",
+        args: vec![
+            Arg::required("player", ArgKind::Username),
+            Arg::optional("reason", ArgKind::Text),
+        ],
+        help: "Bans a player for good, disconnecting them if online.",
+        permission: "command.ban",
+        run: ban,
+    });
+    registry.register(Command {
+        name: "tempban",
+        args: vec![
+            Arg::required("player", ArgKind::Username),
+            Arg::required("duration", ArgKind::Duration),
+            Arg::optional("reason", ArgKind::Text),
+        ],
+        help: "Bans a player for a while, such as 12h or 7d.",
+        permission: "command.tempban",
+        run: tempban,
+    });
+    registry.register(Command {
+        name: "ban-ip",
+        args: vec![
+            Arg::required("address", ArgKind::Address),
+            Arg::optional("reason", ArgKind::Text),
+        ],
+        help: "Bans an IP address, or that of an online player, disconnecting its players.",
+        permission: "command.ban-ip",
+        run: ban_ip,
+    });
+    registry.register(Command {
+        name: "unban",
+        args: vec![Arg::required("player", ArgKind::Username)],
+        help: "Lifts the ban on a player.",
+        permission: "command.unban",
+        run: unban,
+    });
+    registry.register(Command {
+        name: "unban-ip",
+        args: vec![Arg::required("address", ArgKind::Address)],
+        help: "Lifts the ban on an IP address.",
+        permission: "command.unban-ip",
+        run: unban_ip,
+    });
+    registry.register(Command {
+        name: "banlist",
+        args: Vec::new(),
+        help: "Lists the banned players and IP addresses.",
+        permission: "command.banlist",
+        run: banlist,
+    });
+    registry.register(Command {
+        name: "whitelist",
+        args: vec![
+            Arg::required(
+                "action",
+                ArgKind::Choice(&["on", "off", "add", "remove", "list"]),
+            ),
+            Arg::optional("player", ArgKind::Username),
+        ],
+        help: "Lets only the listed players join while on.",
+        permission: "command.whitelist",
+        run: whitelist,
+    });
+}
+
+fn new_ban(ctx: &CommandContext, args: &Args, duration: Option<Duration>) -> Ban {
+    let by = source_name(ctx.state, ctx.source);
+    Ban {
+        reason: match args.text("reason") {
+            Some(reason) => reason.to_string(),
+            None => "no reason given".to_string(),
+        },
+        by,
+        expires: duration.map(|duration| unix_time().saturating_add(duration.as_secs())),
+    }
+}
+
+/// Changes a copy of the access list with `change` and writes it to its file, only putting it
+/// in place once saved so the list in use never differs from the one on disk.
+fn update_access<T>(
+    ctx: &CommandContext,
+    change: impl FnOnce(&mut AccessList) -> Result<T, CommandError>,
+) -> Result<T, CommandError> {
+    let mut access = ctx.state.access.lock().unwrap();
+    let mut updated = access.clone();
+    let result = change(&mut updated)?;
+    updated
+        .save(&ctx.state.config.access_list)
+        .map_err(|err| CommandError::Failed(err.to_string()))?;
+    *access = updated;
+    Ok(result)
+}
+
+/// Disconnects the online players `matches` picks, returning how many.
+fn disconnect_matching(
+    ctx: &CommandContext,
+    reason: DisconnectReason,
+    matches: impl Fn(&str, IpAddr) -> bool,
+) -> usize {
+    let players = ctx
+        .state
+        .players
+        .values()
+        .filter(|player| matches(player.username(), player.addr().ip().to_canonical()));
+    let mut count = 0;
+    for player in players {
+        player.send_packet_action(PacketAction::Disconnect(reason.clone()));
+        count += 1;
+    }
+    count
+}
+
+fn ban_player(ctx: &mut CommandContext, args: &Args, duration: Option<Duration>) -> CommandResult {
+    let username = args.username("player").expect("required").to_string();
+    let ban = new_ban(ctx, args, duration);
+    let reason = ban.disconnect_reason();
+    update_access(ctx, |access| {
+        access.banned_players.insert(username.clone(), ban);
+        Ok(())
+    })?;
+
+    disconnect_matching(ctx, reason.clone(), |other, _| other == username);
+    Ok(format!("{username} {reason}"))
+}
+
+fn ban(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    ban_player(ctx, args, None)
+}
+
+fn tempban(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    ban_player(ctx, args, args.duration("duration"))
+}
+
+fn ban_ip(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let address = args.address("address").expect("required");
+    let ban = new_ban(ctx, args, None);
+    let reason = ban.disconnect_reason();
+    update_access(ctx, |access| {
+        access.banned_ips.insert(address, ban);
+        Ok(())
+    })?;
+
+    let count = disconnect_matching(ctx, reason.clone(), |_, other| other == address);
+    Ok(format!(
+        "{address} {reason}, disconnected {count} player(s)"
+    ))
+}
+
+fn unban(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let username = args.username("player").expect("required");
+    update_access(ctx, |access| match access.banned_players.remove(username) {
+        Some(_) => Ok(format!("unbanned {username}")),
+        None => Err(CommandError::Failed(format!("{username} is not banned"))),
+    })
+}
+
+fn unban_ip(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let address = args.address("address").expect("required");
+    update_access(ctx, |access| match access.banned_ips.remove(&address) {
+        Some(_) => Ok(format!("unbanned {address}")),
+        None => Err(CommandError::Failed(format!("{address} is not banned"))),
+    })
+}
+
+fn banlist(ctx: &mut CommandContext, _args: &Args) -> CommandResult {
+    let access = ctx.state.access.lock().unwrap();
+    let now = unix_time();
+    let players = access
+        .banned_players
+        .iter()
+        .map(|(username, ban)| (username.clone(), ban));
+    let ips = access
+        .banned_ips
+        .iter()
+        .map(|(ip, ban)| (ip.to_string(), ban));
+
+    let lines: Vec<String> = players
+        .chain(ips)
+        .filter(|(_, ban)| ban.is_active(now))
+        .map(|(banned, ban)| format!("{banned} by {}: {}", ban.by, ban.disconnect_reason()))
+        .collect();
+    if lines.is_empty() {
+        return Ok("nobody is banned".to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+fn whitelist(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let action = args.choice("action").expect("required");
+    let username = args.username("player").map(str::to_string);
+    match (action, username) {
+        ("on" | "off", _) => {
+            let enabled = action == "on";
+            update_access(ctx, |access| {
+                access.whitelist.enabled = enabled;
+                Ok(())
+            })?;
+            Ok(format!("the whitelist is {action}"))
+        }
+        ("add", Some(username)) => update_access(ctx, |access| {
+            if !access.whitelist.players.insert(username.clone()) {
+                return Err(CommandError::Failed(format!(
+                    "{username} is already whitelisted"
+                )));
+            }
+            Ok(format!("whitelisted {username}"))
+        }),
+        ("remove", Some(username)) => update_access(ctx, |access| {
+            if !access.whitelist.players.remove(&username) {
+                return Err(CommandError::Failed(format!(
+                    "{username} is not whitelisted"
+                )));
+            }
+            Ok(format!("removed {username} from the whitelist"))
+        }),
+        ("list", _) => {
+            let access = ctx.state.access.lock().unwrap();
+            let state = if access.whitelist.enabled {
+                "on"
+            } else {
+                "off"
+            };
+            let players: Vec<&str> = access
+                .whitelist
+                .players
+                .iter()
+                .map(String::as_str)
+                .collect();
+            Ok(format!(
+                "the whitelist is {state}, {} player(s): {}",
+                players.len(),
+                players.join(", ")
+            ))
+        }
+        _ => Err(CommandError::Usage(format!(
+            "/whitelist {action} needs <player>"
+        ))),
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+use shared::protocol::MAX_ICON_SIZE;
+use shared::toml_file::{self, TomlFileError};
+
+use crate::auth::AuthConfig;
+
+pub const CONFIG_PATH: &str = "server.toml";
+
+const MAX_TICK_RATE: u32 = 1000;
+const MAX_MOTD_LENGTH: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+}
+
+/// Everything an operator can tune, read from `server.toml` and overridable on the command line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    /// Fixed updates per second.
+    pub tick_rate: u32,
+    pub max_players: u32,
+    pub world_dir: PathBuf,
+    /// PEM certificate chain presented to clients. A self-signed one is generated along with
+    /// its key when neither file exists.
+    pub certificate: PathBuf,
+    /// PEM private key of the certificate.
+    pub private_key: PathBuf,
+    /// World seed. A random one is picked at startup when unset.
+    pub seed: Option<u64>,
+    pub motd: String,
+    /// PNG shown next to the server by clients querying its status.
+    pub icon: Option<PathBuf>,
+    /// Announces the server to clients on the local network.
+    pub lan_discovery: bool,
+    pub log_level: LogLevel,
+    pub auth: AuthConfig,
+    /// Operators, groups and player permissions, created with the defaults if missing.
+    pub permissions: PathBuf,
+    /// Bans and whitelist, created empty if missing.
+    pub access_list: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: shared::DEFAULT_PORT,
+            tick_rate: 60,
+            max_players: 20,
+            world_dir: PathBuf::from("world"),
+            certificate: PathBuf::from("server.crt"),
+            private_key: PathBuf::from("server.key"),
+            seed: None,
+            motd: "A rbmp server".to_string(),
+            icon: None,
+            lan_discovery: true,
+            log_level: LogLevel::Info,
+            auth: AuthConfig::default(),
+            permissions: PathBuf::from("permissions.toml"),
+            access_list: PathBuf::from("access.toml"),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<Self, TomlFileError> {
+        toml_file::load(path)
+    }
+
+    /// Loads the config at `path`, writing the defaults there first if the file is missing.
+    pub fn load_or_create(path: &Path) -> Result<Self, TomlFileError> {
+        toml_file::load_or_create(path, Self::load)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TomlFileError> {
+        toml_file::save(path, self)
+    }
+
+    /// Checks every value is usable, reporting the first one that is not. Not part of `load`,
+    /// as command-line flags may still override them.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("port must be between 1 and 65535".to_string());
+        }
+        if self.tick_rate == 0 || self.tick_rate > MAX_TICK_RATE {
+            return Err(format!(
+                "tick_rate must be between 1 and {MAX_TICK_RATE}, got {}",
+                self.tick_rate
+            ));
+        }
+        if self.max_players == 0 {
+            return Err("max_players must be at least 1".to_string());
+        }
+        if self.world_dir.as_os_str().is_empty() {
+            return Err("world_dir must not be empty".to_string());
+        }
+        if self.world_dir.exists() && !self.world_dir.is_dir() {
+            return Err(format!(
+                "world_dir {} is not a directory",
+                self.world_dir.display()
+            ));
+        }
+        if self.motd.chars().count() > MAX_MOTD_LENGTH {
+            return Err(format!(
+                "motd must be at most {MAX_MOTD_LENGTH} characters long"
+            ));
+        }
+        if self.motd.chars().any(|c| c.is_control()) {
+            return Err("motd must not contain control characters".to_string());
+        }
+        if let Some(icon) = &self.icon {
+            let size = fs::metadata(icon)
+                .map_err(|err| format!("icon {}: {err}", icon.display()))?
+                .len();
+            if size > MAX_ICON_SIZE as u64 {
+                return Err(format!(
+                    "icon {} is larger than {MAX_ICON_SIZE} bytes",
+                    icon.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate
+    }
+}
@@ -1,27 +1,85 @@
 use crate::state::ServerState;
-use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
+/// Timings of the fixed updates, shared with whoever wants to watch the server's load.
+#[derive(Debug, Default)]
+pub struct TickStats {
+    ticks: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+    last_micros: AtomicU64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TickSnapshot {
+    pub ticks: u64,
+    pub average: Duration,
+    pub max: Duration,
+    pub last: Duration,
+}
+
+impl TickStats {
+    pub fn record(&self, duration: Duration) {
+        let micros = duration.as_micros() as u64;
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+        self.last_micros.store(micros, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TickSnapshot {
+        let ticks = self.ticks.load(Ordering::Relaxed);
+        let total = self.total_micros.load(Ordering::Relaxed);
+        TickSnapshot {
+            ticks,
+            average: Duration::from_micros(total.checked_div(ticks).unwrap_or(0)),
+            max: Duration::from_micros(self.max_micros.load(Ordering::Relaxed)),
+            last: Duration::from_micros(self.last_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Returns the timings since the previous call and starts over.
+    pub fn take(&self) -> TickSnapshot {
+        let ticks = self.ticks.swap(0, Ordering::Relaxed);
+        let total = self.total_micros.swap(0, Ordering::Relaxed);
+        TickSnapshot {
+            ticks,
+            average: Duration::from_micros(total.checked_div(ticks).unwrap_or(0)),
+            max: Duration::from_micros(self.max_micros.swap(0, Ordering::Relaxed)),
+            last: Duration::from_micros(self.last_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Runs `update` as often as possible and `fixed_update` every `interval`, sleeping in between,
+/// until `running` is cleared. The time each fixed update takes is recorded in `stats`.
 pub fn server_game_loop(
     state: ServerState,
     update: fn(&mut ServerState, &Duration),
     fixed_update: fn(&mut ServerState, &Duration),
     interval: Duration,
-) -> Infallible {
+    running: &AtomicBool,
+    stats: &TickStats,
+) -> ServerState {
     let mut last_tick = Instant::now();
     let mut last_fixed_tick = Instant::now();
     let mut state = state;
-    loop {
+    while running.load(Ordering::Relaxed) {
         let now = Instant::now();
 
-        update(&mut state, &(last_tick - now));
+        update(&mut state, &(now - last_tick));
         last_tick = now;
 
-        if last_fixed_tick.elapsed() < interval {
+        let since_fixed_tick = now - last_fixed_tick;
+        if since_fixed_tick < interval {
+            std::thread::sleep((interval - since_fixed_tick).min(Duration::from_millis(1)));
             continue;
         }
 
-        fixed_update(&mut state, &(last_fixed_tick - now));
+        fixed_update(&mut state, &since_fixed_tick);
         last_fixed_tick = now;
+        stats.record(now.elapsed());
     }
+    state
 }
@@ -0,0 +1,74 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tracing::{debug, error, warn};
+
+use shared::lan::{LAN_DISCOVERY_GROUP, LAN_DISCOVERY_PORT};
+
+use crate::networking::Lobby;
+
+/// How often the server announces itself.
+pub const BEACON_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Announces the server listening on `address` to the local network until `running` is
+/// cleared.
+pub(crate) fn spawn_beacon(
+    address: SocketAddr,
+    lobby: Arc<Lobby>,
+    running: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let interface = match address.ip() {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(ip) if ip.is_unspecified() => Ipv4Addr::UNSPECIFIED,
+        IpAddr::V6(ip) => {
+            warn!("not announcing the server on the LAN, {ip} is not reachable over IPv4");
+            return Ok(());
+        }
+    };
+    let socket = beacon_socket(interface)?;
+    let group = SocketAddr::from((LAN_DISCOVERY_GROUP, LAN_DISCOVERY_PORT));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BEACON_INTERVAL);
+        let mut failing = false;
+        while running.load(Ordering::Relaxed) {
+            interval.tick().await;
+            let datagram = match lobby.announcement(address.port()).to_datagram() {
+                Ok(datagram) => datagram,
+                Err(err) => {
+                    error!("not announcing the server on the LAN: {err}");
+                    return;
+                }
+            };
+            match socket.send_to(&datagram, group).await {
+                Ok(_) => failing = false,
+                Err(err) if failing => debug!("failed to announce the server: {err}"),
+                Err(err) => {
+                    warn!("failed to announce the server: {err}");
+                    failing = true;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// A socket sending to the discovery group through `interface`, so the address clients take
+/// from the datagrams is one the server listens on. Servers bound to the loopback address are
+/// then only discovered from the same machine.
+fn beacon_socket(interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_multicast_ttl_v4(1)?;
+    socket.set_multicast_loop_v4(true)?;
+    if !interface.is_unspecified() {
+        socket.set_multicast_if_v4(&interface)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((interface, 0)).into())?;
+    UdpSocket::from_std(socket.into())
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::player::GameMode;
+
+/// File of the world directory holding the `Level`.
+pub const LEVEL_FILE: &str = "level.toml";
+/// Directory of the world directory holding the `PlayerData` of each player, by username.
+pub const PLAYERS_DIR: &str = "players";
+
+/// What is kept of a world between runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Level {
+    #[serde(with = "as_i64")]
+    pub seed: u64,
+    /// Ticks since the world was created.
+    pub time: u64,
+}
+
+impl Level {
+    /// Reads the level saved in `world_dir`, if the world was saved before.
+    pub fn load(world_dir: &Path) -> io::Result<Option<Self>> {
+        read_toml(&world_dir.join(LEVEL_FILE))
+    }
+
+    /// Writes the level to `world_dir`, creating the directory if needed. The previous save is
+    /// only replaced once the new one is complete.
+    pub fn save(&self, world_dir: &Path) -> io::Result<()> {
+        write_toml(&world_dir.join(LEVEL_FILE), self)
+    }
+}
+
+/// TOML integers are signed, so a `u64` is kept as the `i64` of the same bits.
+mod as_i64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(*value as i64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        i64::deserialize(deserializer).map(|value| value as u64)
+    }
+}
+
+/// What is kept of a player between sessions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerData {
+    pub position: [f32; 3],
+    pub rotations: [f32; 2],
+    pub game_mode: GameMode,
+    /// Block counts by block name, as ids may change between versions.
+    pub inventory: BTreeMap<String, u32>,
+}
+
+impl PlayerData {
+    /// Reads the data saved for `username` in `world_dir`, if they played before.
+    pub fn load(world_dir: &Path, username: &str) -> io::Result<Option<Self>> {
+        read_toml(&player_path(world_dir, username))
+    }
+
+    pub fn save(&self, world_dir: &Path, username: &str) -> io::Result<()> {
+        write_toml(&player_path(world_dir, username), self)
+    }
+}
+
+/// Usernames are validated during the handshake, so they are safe as file names.
+fn player_path(world_dir: &Path, username: &str) -> PathBuf {
+    world_dir.join(PLAYERS_DIR).join(format!("{username}.toml"))
+}
+
+fn read_toml<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    toml::from_str(&content).map(Some).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid {}: {err}", path.display()),
+        )
+    })
+}
+
+/// Writes `value` next to `path` first, then moves it there, creating the directories needed.
+fn write_toml(path: &Path, value: &impl Serialize) -> io::Result<()> {
+    let content = toml::to_string_pretty(value)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, content)?;
+    fs::rename(temp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_seed_survives_a_save() {
+        let dir = tempfile::tempdir().unwrap();
+        for seed in [0, 42, i64::MAX as u64 + 1, u64::MAX] {
+            let level = Level { seed, time: 7 };
+            level.save(dir.path()).unwrap();
+            assert_eq!(Level::load(dir.path()).unwrap(), Some(level));
+        }
+    }
+}
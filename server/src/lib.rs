@@ -0,0 +1,411 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use quinn::Endpoint;
+use tracing::{debug, error, info, warn};
+
+use shared::protocol::{DisconnectReason, PacketAction, ReliablePacket, CLOSE_SERVER_STOPPED};
+
+use crate::access::AccessList;
+use crate::commands::{run_console_commands, run_player_command, CommandSource, Console};
+use crate::config::ServerConfig;
+use crate::game_loop::{server_game_loop, TickStats};
+use crate::level::{Level, PlayerData};
+use crate::networking::{Lobby, StatusInfo, TcpEvent};
+use crate::permissions::Permissions;
+use crate::player::Player;
+use crate::state::ServerState;
+
+pub mod access;
+pub mod auth;
+pub mod chat;
+pub mod commands;
+pub mod config;
+pub mod game_loop;
+pub mod lan;
+pub mod level;
+pub mod networking;
+pub mod permissions;
+pub mod player;
+pub mod state;
+pub mod terminal;
+pub mod tls;
+
+/// A running server. The game loop runs on its own thread, networking on the tokio runtime
+/// `start` was called from.
+pub struct ServerHandle {
+    endpoint: Endpoint,
+    running: Arc<AtomicBool>,
+    tick_stats: Arc<TickStats>,
+    console: Console,
+    lobby: Arc<Lobby>,
+    game_loop: Option<JoinHandle<ServerState>>,
+}
+
+/// How long players get to receive why they are disconnected when the server stops.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+pub enum ShutdownError {
+    /// Nothing was saved.
+    GameLoopPanicked,
+    /// Some of the world was not saved, the log says what.
+    SaveFailed,
+}
+
+impl Display for ShutdownError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShutdownError::GameLoopPanicked => {
+                write!(f, "the game loop panicked, the world was not saved")
+            }
+            ShutdownError::SaveFailed => write!(f, "the world was not entirely saved"),
+        }
+    }
+}
+
+impl std::error::Error for ShutdownError {}
+
+/// Stops a server from another thread or task, such as one waiting for signals.
+#[derive(Clone)]
+pub struct StopHandle {
+    endpoint: Endpoint,
+    running: Arc<AtomicBool>,
+}
+
+impl StopHandle {
+    /// Refuses new connections and stops the game loop once the current tick is done, the
+    /// server then shuts down as `ServerHandle::join` does.
+    pub fn stop(&self) {
+        self.endpoint.set_server_config(None);
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    pub fn tick_stats(&self) -> &TickStats {
+        &self.tick_stats
+    }
+
+    /// Runs commands as the server console.
+    pub fn console(&self) -> Console {
+        self.console.clone()
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            endpoint: self.endpoint.clone(),
+            running: self.running.clone(),
+        }
+    }
+
+    /// Stops the game loop, then shuts down as `join` does.
+    pub fn stop(mut self) -> Result<(), ShutdownError> {
+        self.stop_handle().stop();
+        self.join()
+    }
+
+    /// Blocks until the game loop exits, which the `stop` command and `StopHandle` make it do.
+    /// Then refuses new connections, saves the world, disconnects every player and closes the
+    /// endpoint with `CLOSE_SERVER_STOPPED`.
+    pub fn join(&mut self) -> Result<(), ShutdownError> {
+        let Some(game_loop) = self.game_loop.take() else {
+            return Ok(());
+        };
+        let result = match game_loop.join() {
+            Ok(state) => {
+                self.endpoint.set_server_config(None);
+                shut_down(state, &self.lobby)
+            }
+            Err(_) => Err(ShutdownError::GameLoopPanicked),
+        };
+        self.endpoint
+            .close(CLOSE_SERVER_STOPPED.into(), b"server stopped");
+        result
+    }
+}
+
+/// Starts a server with `config`, which is expected to be valid.
+pub async fn start(config: ServerConfig) -> std::io::Result<ServerHandle> {
+    let ids = Arc::new(AtomicU32::new(0));
+
+    let identity = tls::load_or_generate(&config.certificate, &config.private_key)?;
+    let auth = config.auth.create_provider()?;
+    let permissions = Permissions::load_or_create(&config.permissions).map_err(io::Error::other)?;
+    let access = AccessList::load_or_create(&config.access_list).map_err(io::Error::other)?;
+    let access = Arc::new(Mutex::new(access));
+    let status = StatusInfo {
+        motd: config.motd.clone(),
+        icon: config.icon.as_deref().map(std::fs::read).transpose()?,
+    };
+    let network = networking::init(
+        config.address(),
+        identity,
+        auth.into(),
+        access.clone(),
+        ids,
+        config.max_players,
+        status,
+    )
+    .await?;
+    let address = network.endpoint.local_addr()?;
+    info!("listening on {address}");
+
+    let running = Arc::new(AtomicBool::new(true));
+    if config.lan_discovery {
+        lan::spawn_beacon(address, network.lobby.clone(), running.clone())?;
+    }
+
+    let level = match Level::load(&config.world_dir)? {
+        Some(level) => {
+            if config.seed.is_some_and(|seed| seed != level.seed) {
+                warn!(
+                    "the world was created with seed {}, ignoring the configured one",
+                    level.seed
+                );
+            }
+            level
+        }
+        None => Level {
+            seed: config.seed.unwrap_or_else(rand::random),
+            time: 0,
+        },
+    };
+    let seed = level.seed;
+    info!("world seed: {seed}");
+
+    let interval = config.tick_interval();
+    let (console, console_receiver) = Console::new();
+    let state = ServerState {
+        config,
+        seed,
+        blocks: Default::default(),
+        time: level.time,
+        commands: Default::default(),
+        permissions,
+        access,
+        console_receiver,
+        tcp_receiver: network.tcp_receiver,
+        udp_receiver: network.udp_receiver,
+        players: Default::default(),
+        running: running.clone(),
+    };
+
+    let tick_stats = Arc::new(TickStats::default());
+    let game_loop = {
+        let running = running.clone();
+        let tick_stats = tick_stats.clone();
+        std::thread::Builder::new()
+            .name("game-loop".into())
+            .spawn(move || {
+                server_game_loop(state, update, fixed_update, interval, &running, &tick_stats)
+            })?
+    };
+
+    Ok(ServerHandle {
+        endpoint: network.endpoint,
+        running,
+        tick_stats,
+        console,
+        lobby: network.lobby,
+        game_loop: Some(game_loop),
+    })
+}
+
+/// Saves the world and players of the stopped game loop, then disconnects the players and
+/// waits a bit for them to get the reason.
+fn shut_down(state: ServerState, lobby: &Lobby) -> Result<(), ShutdownError> {
+    info!("stopping the server");
+    lobby.close();
+    let world_dir = &state.config.world_dir;
+    let mut saved = match state.level().save(world_dir) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("failed to save the world to {}: {err}", world_dir.display());
+            false
+        }
+    };
+    for player in state.players.values() {
+        saved &= save_player(&state, player);
+    }
+    if saved {
+        info!("saved the world to {}", world_dir.display());
+    }
+
+    for player in state.players.values() {
+        player.send_packet_action(PacketAction::Disconnect(DisconnectReason::ServerStopped));
+    }
+    // players handed over after the last tick never made it into the game
+    for event in state.tcp_receiver.try_iter() {
+        if let TcpEvent::NewConnection {
+            packet_action_sender,
+            ..
+        } = event
+        {
+            let _ = packet_action_sender
+                .send(PacketAction::Disconnect(DisconnectReason::ServerStopped));
+        }
+    }
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while lobby.online() > 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    if !saved {
+        return Err(ShutdownError::SaveFailed);
+    }
+    Ok(())
+}
+
+/// Returns whether the player was saved, logging why not.
+fn save_player(state: &ServerState, player: &Player) -> bool {
+    let data = player.data(&state.blocks);
+    match data.save(&state.config.world_dir, player.username()) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("failed to save {}: {err}", player.username());
+            false
+        }
+    }
+}
+
+/// Puts the player back where they left if they played before, then tells their client where
+/// they are.
+fn load_player(state: &ServerState, player: &mut Player) {
+    match PlayerData::load(&state.config.world_dir, player.username()) {
+        Ok(Some(data)) => {
+            player.restore(data, &state.blocks);
+        }
+        Ok(None) => {}
+        Err(err) => warn!(
+            "failed to load {}, they start over: {err}",
+            player.username()
+        ),
+    }
+    // new players too, as movements far from where the server has them are refused
+    player.send_reliable_packet(ReliablePacket::Teleport {
+        position: player.position,
+    });
+}
+
+fn update(_state: &mut ServerState, _dt: &Duration) {}
+
+fn fixed_update(state: &mut ServerState, dt: &Duration) {
+    receive_packets(state, dt);
+    run_console_commands(state);
+    state.time += 1;
+}
+
+fn receive_packets(state: &mut ServerState, dt: &Duration) {
+    receive_tcp_packets(state, dt);
+}
+
+fn receive_tcp_packets(state: &mut ServerState, _dt: &Duration) {
+    while let Ok(packet) = state.tcp_receiver.try_recv() {
+        match packet {
+            TcpEvent::NewConnection {
+                id,
+                addr,
+                username,
+                packet_action_sender,
+            } => {
+                info!("new connection: addr: {addr}, player_id: {id}, username: {username}");
+                let joined = format!("{username} joined the game");
+                let mut player = Player::new(id, addr, username, packet_action_sender);
+                load_player(state, &mut player);
+                state.players.insert(id, player);
+                chat::broadcast_system(&state.players, joined);
+            }
+            TcpEvent::PacketReceived { id, addr, packet } => {
+                handle_tcp_packet_received(state, id, addr, packet)
+            }
+            TcpEvent::Disconnected { id, addr } => {
+                if let Some(player) = state.players.remove(&id) {
+                    save_player(state, &player);
+                    let left = format!("{} left the game", player.username());
+                    chat::broadcast_system(&state.players, left);
+                }
+                info!("{addr} left, player_id: {id}");
+            }
+        }
+    }
+}
+
+fn handle_tcp_packet_received(
+    state: &mut ServerState,
+    id: u32,
+    addr: SocketAddr,
+    packet: ReliablePacket,
+) {
+    match packet {
+        ReliablePacket::MovementInput {
+            directions,
+            rotations,
+            position,
+        } => {
+            if let Some(player) = state.players.get_mut(&id) {
+                player.directions = directions;
+                player.rotations = rotations;
+                if !player.move_to(position) {
+                    // put the client back where the server has them
+                    debug!("{} moved too far, sending them back", player.username());
+                    player.send_reliable_packet(ReliablePacket::Teleport {
+                        position: player.position,
+                    });
+                }
+            }
+        }
+        ReliablePacket::ChatSend { text } => {
+            chat::handle_chat(&mut state.players, &state.permissions, id, &text)
+        }
+        ReliablePacket::RunCommand { input } => {
+            let Some(player) = state.players.get_mut(&id) else {
+                return;
+            };
+            if !player.chat_limiter.try_send() {
+                chat::send_system(player, "you are sending commands too fast");
+                return;
+            }
+            run_player_command(state, id, &input)
+        }
+        ReliablePacket::CompleteCommand { id: request, input } => {
+            // suggestions are only a convenience, the client does without them
+            if !state
+                .players
+                .get_mut(&id)
+                .is_some_and(|player| player.completion_limiter.try_send())
+            {
+                return;
+            }
+            let commands = state.commands.clone();
+            let (start, suggestions) = commands.complete(state, CommandSource::Player(id), &input);
+            if let Some(player) = state.players.get(&id) {
+                player.send_reliable_packet(ReliablePacket::CommandSuggestions {
+                    id: request,
+                    start: start as u32,
+                    suggestions,
+                });
+            }
+        }
+        _ => {
+            warn!("disconnecting {addr}, they sent an unexpected packet: {packet:?}");
+            if let Some(player) = state.players.get(&id) {
+                let reason = DisconnectReason::ProtocolViolation("unexpected packet".to_string());
+                player.send_packet_action(PacketAction::Disconnect(reason));
+            }
+        }
+    }
+}
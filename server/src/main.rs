@@ -1,79 +1,218 @@
-use std::net::SocketAddr;
-use std::sync::atomic::AtomicU32;
-use std::sync::Arc;
+use std::io::BufRead;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::Duration;
-use tokio::net::lookup_host;
 
-use tracing::{info, warn};
+use clap::{Parser, Subcommand};
+use server::auth::{AuthConfig, PasswordFileAuth, TokenAuth};
+use server::config::{LogLevel, ServerConfig, CONFIG_PATH};
+use server::terminal::{spawn_console, LogWriter};
+use shared::protocol::validate_username;
+use tracing::{error, info, warn};
 
-use shared::protocol::ReliablePacket;
+/// Command-line overrides for the values in the config file.
+#[derive(Parser, Debug)]
+#[command(name = "server")]
+struct Args {
+    /// Config file to read, created with the defaults if missing.
+    #[arg(long, default_value = CONFIG_PATH)]
+    config: PathBuf,
+    #[arg(long)]
+    bind_address: Option<IpAddr>,
+    #[arg(long)]
+    port: Option<u16>,
+    /// Fixed updates per second.
+    #[arg(long)]
+    tick_rate: Option<u32>,
+    #[arg(long)]
+    max_players: Option<u32>,
+    #[arg(long)]
+    world_dir: Option<PathBuf>,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long)]
+    motd: Option<String>,
+    /// PNG shown in status queries.
+    #[arg(long)]
+    icon: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    log_level: Option<LogLevel>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-use crate::game_loop::server_game_loop;
-use crate::networking::TcpEvent;
-use crate::player::Player;
-use crate::state::ServerState;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Creates or updates an account of the password file, reading the password from stdin.
+    AddAccount { username: String },
+    /// Prints a token letting `username` join, signed with the token secret.
+    IssueToken {
+        username: String,
+        /// Hours the token stays valid.
+        #[arg(long, default_value_t = 24)]
+        hours: u64,
+    },
+}
 
-mod game_loop;
-mod networking;
-mod player;
-mod state;
+/// Runs an account management command against the auth files named in `config`.
+fn run_command(command: Command, config: &ServerConfig) -> Result<(), String> {
+    match command {
+        Command::AddAccount { username } => {
+            let AuthConfig::PasswordFile { path } = &config.auth else {
+                return Err("auth mode is not password_file".to_string());
+            };
+            validate_username(&username)?;
 
-const INTERVAL: Duration = Duration::from_millis(1000 / 60);
+            eprintln!("password for {username}:");
+            let mut password = String::new();
+            std::io::stdin()
+                .lock()
+                .read_line(&mut password)
+                .map_err(|err| err.to_string())?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            if password.is_empty() {
+                return Err("the password must not be empty".to_string());
+            }
 
-#[tokio::main]
-async fn main() {
-    shared::tracing::init();
-
-    let ids = Arc::new(AtomicU32::new(0));
-    networking::init(ids).await;
-
-    loop {}
-
-    //
-    // let state = ServerState {
-    //     tcp_receiver,
-    //     udp_receiver,
-    //     players: Default::default(),
-    // };
-    //
-    // server_game_loop(state, update, fixed_update, INTERVAL);
+            PasswordFileAuth::set_password(path, &username, password)
+                .map_err(|err| format!("failed to update {}: {err}", path.display()))?;
+            println!("saved the account {username} in {}", path.display());
+        }
+        Command::IssueToken { username, hours } => {
+            let AuthConfig::Token { secret_file } = &config.auth else {
+                return Err("auth mode is not token".to_string());
+            };
+            validate_username(&username)?;
+
+            let auth = TokenAuth::load_or_generate(secret_file)
+                .map_err(|err| format!("failed to read {}: {err}", secret_file.display()))?;
+            println!(
+                "{}",
+                auth.issue(&username, Duration::from_secs(hours * 60 * 60))
+            );
+        }
+    }
+    Ok(())
+}
+
+impl Args {
+    fn apply(&self, config: &mut ServerConfig) {
+        if let Some(bind_address) = self.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(max_players) = self.max_players {
+            config.max_players = max_players;
+        }
+        if let Some(world_dir) = &self.world_dir {
+            config.world_dir = world_dir.clone();
+        }
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+        if let Some(motd) = &self.motd {
+            config.motd = motd.clone();
+        }
+        if let Some(icon) = &self.icon {
+            config.icon = Some(icon.clone());
+        }
+        if let Some(log_level) = self.log_level {
+            config.log_level = log_level;
+        }
+    }
 }
 
-fn update(state: &mut ServerState, _dt: &Duration) {}
+/// Waits for SIGINT or SIGTERM, returning the name of the one received.
+#[cfg(unix)]
+async fn wait_for_signal() -> std::io::Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
 
-fn fixed_update(state: &mut ServerState, dt: &Duration) {
-    receive_packets(state, dt);
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.map(|()| "SIGINT"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
 }
 
-fn receive_packets(state: &mut ServerState, dt: &Duration) {
-    receive_tcp_packets(state, dt);
+/// Waits for Ctrl+C, the only signal other platforms have.
+#[cfg(not(unix))]
+async fn wait_for_signal() -> std::io::Result<&'static str> {
+    tokio::signal::ctrl_c().await.map(|()| "Ctrl+C")
 }
 
-fn receive_tcp_packets(state: &mut ServerState, _dt: &Duration) {
-    while let Ok(packet) = state.tcp_receiver.try_recv() {
-        match packet {
-            TcpEvent::NewConnection {
-                id,
-                tcp_addr,
-                udp_addr,
-                packet_action_sender,
-            } => {
-                let player = Player::new(id, tcp_addr, udp_addr, packet_action_sender);
-                state.players.insert(id, player);
-                info!("new connection: addr: {tcp_addr}, player_id: {id}");
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args = Args::parse();
+
+    let created = !args.config.exists();
+    let mut config = match ServerConfig::load_or_create(&args.config) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    args.apply(&mut config);
+    if let Err(reason) = config.validate() {
+        eprintln!("invalid config: {reason}");
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(command) = args.command.take() {
+        return match run_command(command, &config) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::FAILURE
             }
-            TcpEvent::PacketReceived { addr, packet } => {
-                handle_tcp_packet_received(state, addr, packet)
+        };
+    }
+
+    shared::tracing::init_with_writer(config.log_level.into(), LogWriter);
+    if created {
+        info!("created {} with the defaults", args.config.display());
+    }
+
+    let mut server = match server::start(config).await {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("failed to start the server: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let _raw_mode = match spawn_console(server.console()) {
+        Ok(raw_mode) => raw_mode,
+        Err(err) => {
+            warn!("the console is unavailable: {err}");
+            None
+        }
+    };
+
+    // SIGINT and SIGTERM stop the server as the stop command does
+    let stop_handle = server.stop_handle();
+    tokio::spawn(async move {
+        match wait_for_signal().await {
+            Ok(signal) => {
+                info!("received {signal}");
+                stop_handle.stop();
             }
+            Err(err) => warn!("signals will kill the server: {err}"),
         }
-    }
-}
+    });
 
-fn handle_tcp_packet_received(_state: &mut ServerState, addr: SocketAddr, packet: ReliablePacket) {
-    match packet {
-        _ => {
-            warn!("{addr} sent an invalid packet")
+    // whether stopped by a command or a signal, failing only if the world was not saved
+    match tokio::task::block_in_place(|| server.join()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            ExitCode::FAILURE
         }
     }
-    info!("{addr} sent {packet:?}")
 }
@@ -1,71 +1,587 @@
 use std::net::SocketAddr;
-use std::sync::atomic::AtomicU32;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 
-use quinn::{Endpoint, ServerConfig};
-use tracing::info;
+use quinn::{Connection, Endpoint, RecvStream, SendStream, ServerConfig};
+use rand::seq::SliceRandom;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
 
-use shared::protocol::{PacketAction, ReliablePacket};
+use shared::error::NetworkError;
+use shared::lan::LanAnnouncement;
+use shared::packet_ext::{decode_packet, AsyncPacketReadExt, AsyncPacketWriteExt};
+use shared::protocol::{
+    validate_username, AuthFailure, Credentials, DisconnectReason, PacketAction, ProtocolHello,
+    ReliablePacket, ServerStatus, UnreliablePacket, VersionMismatch, GAME_ALPN, MAX_STATUS_SAMPLE,
+    STATUS_ALPN,
+};
+
+use crate::access::AccessList;
+use crate::auth::AuthProvider;
+use crate::tls::Identity;
+
+/// Handshakes authenticated at once, as checking a password takes a lot of CPU and memory.
+/// Those past it wait their turn.
+const MAX_CONCURRENT_AUTHENTICATIONS: usize = 4;
 
 pub enum TcpEvent {
     NewConnection {
         id: u32,
-        tcp_addr: SocketAddr,
-        udp_addr: SocketAddr,
-        packet_action_sender: Sender<PacketAction>,
+        addr: SocketAddr,
+        username: String,
+        packet_action_sender: UnboundedSender<PacketAction>,
     },
 
     PacketReceived {
+        id: u32,
         addr: SocketAddr,
         packet: ReliablePacket,
     },
+
+    Disconnected {
+        id: u32,
+        addr: SocketAddr,
+    },
+}
+
+pub enum UdpEvent {
+    PacketReceived {
+        id: u32,
+        addr: SocketAddr,
+        packet: UnreliablePacket,
+    },
+}
+
+/// What status queries are answered with, besides the players.
+pub struct StatusInfo {
+    pub motd: String,
+    pub icon: Option<Vec<u8>>,
 }
 
-pub enum UdpEvent {}
+/// The endpoint and the channels the game loop reads connection events from.
+pub struct Network {
+    pub endpoint: Endpoint,
+    pub tcp_receiver: Receiver<TcpEvent>,
+    pub udp_receiver: Receiver<UdpEvent>,
+    pub(crate) lobby: Arc<Lobby>,
+}
 
-pub async fn init(ids: Arc<AtomicU32>) {
-    let server = create_server();
+/// Accepts connections on `address`, authenticating players with `auth` and turning away
+/// those `access` bars or past `max_players` during their handshake. Connections negotiating
+/// `STATUS_ALPN` are answered with the server status instead.
+pub async fn init(
+    address: SocketAddr,
+    identity: Identity,
+    auth: Arc<dyn AuthProvider>,
+    access: Arc<Mutex<AccessList>>,
+    ids: Arc<AtomicU32>,
+    max_players: u32,
+    status: StatusInfo,
+) -> std::io::Result<Network> {
+    let server = create_server(address, identity)?;
+    let (tcp_sender, tcp_receiver) = channel();
+    let (udp_sender, udp_receiver) = channel();
+    let lobby = Arc::new(Lobby {
+        ids,
+        online: AtomicU32::new(0),
+        max_players,
+        auth,
+        access,
+        usernames: Mutex::new(Vec::new()),
+        closed: AtomicBool::new(false),
+        status,
+        authentications: Semaphore::new(MAX_CONCURRENT_AUTHENTICATIONS),
+    });
 
+    let endpoint = server.clone();
+    let accepting_lobby = lobby.clone();
     tokio::spawn(async move {
-        loop {
-            match server.accept().await {
-                None => {}
-                Some(incoming_connection) => {
-                    info!("incoming {}", incoming_connection.remote_address());
-                    match incoming_connection.await {
-                        Ok(connection) => {
-                            info!("{} connected successfully", connection.remote_address());
-                            let (handshake_send, mut handshake_recv) =
-                                connection.accept_bi().await.unwrap();
-
-                            let mut buf = Vec::new();
-                            handshake_recv.read(&mut buf).await.unwrap();
-
-                            let data = String::from_utf8_lossy(&buf[..]);
-                            info!("{} sent {}", connection.remote_address(), data);
-                        }
-                        Err(err) => {
-                            dbg!(&err);
-                        }
+        let lobby = accepting_lobby;
+        while let Some(incoming_connection) = server.accept().await {
+            info!("incoming {}", incoming_connection.remote_address());
+
+            let lobby = lobby.clone();
+            let tcp_sender = tcp_sender.clone();
+            let udp_sender = udp_sender.clone();
+            tokio::spawn(async move {
+                match incoming_connection.await {
+                    Ok(connection) if alpn(&connection).as_deref() == Some(STATUS_ALPN) => {
+                        answer_status(connection, &lobby).await;
+                    }
+                    Ok(connection) => {
+                        handle_connection(connection, lobby, tcp_sender, udp_sender).await;
                     }
+                    Err(err) => warn!("failed to accept connection: {err}"),
                 }
+            });
+        }
+    });
+
+    Ok(Network {
+        endpoint,
+        tcp_receiver,
+        udp_receiver,
+        lobby,
+    })
+}
+
+/// What every handshake is checked against.
+pub(crate) struct Lobby {
+    ids: Arc<AtomicU32>,
+    online: AtomicU32,
+    max_players: u32,
+    auth: Arc<dyn AuthProvider>,
+    access: Arc<Mutex<AccessList>>,
+    /// Of the players past their handshake, and those about to be.
+    usernames: Mutex<Vec<String>>,
+    /// Set once the game loop is gone. Only changed with `usernames` locked, so that no player
+    /// is handed over to the game loop after it is set.
+    closed: AtomicBool,
+    status: StatusInfo,
+    authentications: Semaphore,
+}
+
+impl Lobby {
+    fn take_slot(&self) -> bool {
+        self.online
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |online| {
+                (online < self.max_players).then_some(online + 1)
+            })
+            .is_ok()
+    }
+
+    fn release_slot(&self) {
+        self.online.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Players past their handshake whose connection is still open.
+    pub(crate) fn online(&self) -> u32 {
+        self.online.load(Ordering::Relaxed)
+    }
+
+    /// Reserves `username` for a joining player, unless someone is already online under it or
+    /// the lobby is closed.
+    fn claim_username(&self, username: &str) -> Result<(), DisconnectReason> {
+        let mut usernames = self.usernames.lock().unwrap();
+        if self.closed.load(Ordering::Relaxed) {
+            return Err(DisconnectReason::ServerStopped);
+        }
+        if usernames.iter().any(|name| name == username) {
+            return Err(DisconnectReason::AlreadyOnline);
+        }
+        usernames.push(username.to_string());
+        Ok(())
+    }
+
+    /// Hands a player over to the game loop with `enter`, unless the lobby is closed. Returns
+    /// whether it did.
+    fn enter(&self, enter: impl FnOnce()) -> bool {
+        let _usernames = self.usernames.lock().unwrap();
+        if self.closed.load(Ordering::Relaxed) {
+            return false;
+        }
+        enter();
+        true
+    }
+
+    /// Turns away every handshake finishing from now on. Called once the game loop is gone, so
+    /// that players do not join a server no longer reading their packets.
+    pub(crate) fn close(&self) {
+        let _usernames = self.usernames.lock().unwrap();
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    fn left(&self, username: &str) {
+        let mut usernames = self.usernames.lock().unwrap();
+        if let Some(index) = usernames.iter().position(|name| name == username) {
+            usernames.swap_remove(index);
+        }
+    }
+
+    pub(crate) fn announcement(&self, port: u16) -> LanAnnouncement {
+        LanAnnouncement {
+            motd: self.status.motd.clone(),
+            port,
+            online: self.usernames.lock().unwrap().len() as u32,
+            max_players: self.max_players,
+        }
+    }
+
+    pub(crate) fn status(&self) -> ServerStatus {
+        let usernames = self.usernames.lock().unwrap();
+        ServerStatus {
+            motd: self.status.motd.clone(),
+            online: usernames.len() as u32,
+            max_players: self.max_players,
+            sample: usernames
+                .choose_multiple(&mut rand::thread_rng(), MAX_STATUS_SAMPLE)
+                .cloned()
+                .collect(),
+            icon: self.status.icon.clone(),
+        }
+    }
+
+    /// Runs the auth provider on the blocking pool, as checking a password hash takes a while.
+    async fn authenticate(
+        &self,
+        username: &str,
+        credentials: Credentials,
+    ) -> Result<(), AuthFailure> {
+        let Ok(_permit) = self.authentications.acquire().await else {
+            return Err(AuthFailure::Unavailable);
+        };
+        let auth = self.auth.clone();
+        let username = username.to_string();
+        tokio::task::spawn_blocking(move || auth.authenticate(&username, &credentials))
+            .await
+            .unwrap_or(Err(AuthFailure::Unavailable))
+    }
+}
+
+/// Tells the client why it is being turned away, then closes the connection once it got it.
+async fn reject(connection: &Connection, send: &mut SendStream, reason: DisconnectReason) {
+    info!("{} turned away: {reason}", connection.remote_address());
+    disconnect(connection, send, reason).await;
+}
+
+/// Sends `reason` to the client and closes the connection once it got it.
+async fn disconnect(connection: &Connection, send: &mut SendStream, reason: DisconnectReason) {
+    let _ = send
+        .send_reliable(&ReliablePacket::Disconnect {
+            reason: reason.clone(),
+        })
+        .await;
+    let _ = send.finish().await;
+    connection.close(0u8.into(), reason.to_string().as_bytes());
+}
+
+/// Reads the client's `ProtocolHello` and answers with ours, so both sides can tell which one is
+/// outdated. Returns whether the versions match.
+async fn exchange_versions(
+    connection: &Connection,
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+) -> bool {
+    let addr = connection.remote_address();
+    let hello = match recv.recv_packet::<ProtocolHello>().await {
+        Ok(hello) if hello.is_rbmp() => hello,
+        Ok(_) => {
+            warn!("{addr} is not an rbmp client");
+            connection.close(0u8.into(), b"not an rbmp client");
+            return false;
+        }
+        Err(err) => {
+            warn!("{addr} failed the handshake: {err}");
+            if err.is_peer_fault() {
+                connection.close(0u8.into(), err.to_string().as_bytes());
             }
+            return false;
+        }
+    };
+
+    if let Err(err) = send.send_packet(&ProtocolHello::CURRENT).await {
+        warn!("failed to answer the handshake of {addr}: {err}");
+        return false;
+    }
+
+    match VersionMismatch::check(hello.version, ProtocolHello::CURRENT.version) {
+        Ok(()) => true,
+        Err(mismatch) => {
+            info!("{addr} turned away: {mismatch}");
+            let _ = send.finish().await;
+            connection.close(0u8.into(), mismatch.to_string().as_bytes());
+            false
+        }
+    }
+}
+
+/// The protocol negotiated through ALPN, if any.
+fn alpn(connection: &Connection) -> Option<Vec<u8>> {
+    connection
+        .handshake_data()?
+        .downcast::<quinn::crypto::rustls::HandshakeData>()
+        .ok()?
+        .protocol
+}
+
+/// Exchanges versions and sends the status if they match, without going through the lobby.
+async fn answer_status(connection: Connection, lobby: &Lobby) {
+    let addr = connection.remote_address();
+    let (mut send, mut recv) = match connection.accept_bi().await {
+        Ok(streams) => streams,
+        Err(err) => {
+            warn!("{addr} closed before asking for the status: {err}");
+            return;
+        }
+    };
+
+    if !exchange_versions(&connection, &mut send, &mut recv).await {
+        return;
+    }
+    if let Err(err) = send.send_packet(&lobby.status()).await {
+        warn!("failed to send the status to {addr}: {err}");
+        return;
+    }
+    let _ = send.finish().await;
+    connection.close(0u8.into(), b"status sent");
+}
+
+async fn handle_connection(
+    connection: Connection,
+    lobby: Arc<Lobby>,
+    tcp_sender: Sender<TcpEvent>,
+    udp_sender: Sender<UdpEvent>,
+) {
+    let addr = connection.remote_address();
+
+    let (mut send, mut recv) = match connection.accept_bi().await {
+        Ok(streams) => streams,
+        Err(err) => {
+            warn!("{addr} closed before the handshake: {err}");
+            return;
+        }
+    };
+
+    if !exchange_versions(&connection, &mut send, &mut recv).await {
+        return;
+    }
+
+    let (username, credentials) = match recv.recv_reliable().await {
+        Ok(ReliablePacket::Handshake {
+            username,
+            credentials,
+        }) => (username, credentials),
+        Ok(packet) => {
+            warn!("{addr} sent {packet:?} instead of a handshake");
+            let reason = DisconnectReason::ProtocolViolation("expected a handshake".to_string());
+            reject(&connection, &mut send, reason).await;
+            return;
+        }
+        Err(err) if err.is_peer_fault() => {
+            let reason = DisconnectReason::ProtocolViolation(err.to_string());
+            reject(&connection, &mut send, reason).await;
+            return;
         }
+        Err(err) => {
+            warn!("{addr} failed the handshake: {err}");
+            return;
+        }
+    };
+
+    if validate_username(&username).is_err() {
+        reject(&connection, &mut send, DisconnectReason::InvalidUsername).await;
+        return;
+    }
+    if let Err(failure) = lobby.authenticate(&username, credentials).await {
+        let reason = DisconnectReason::AuthFailed(failure);
+        reject(&connection, &mut send, reason).await;
+        return;
+    }
+    let access = lobby.access.lock().unwrap().check(&username, addr.ip());
+    if let Err(reason) = access {
+        reject(&connection, &mut send, reason).await;
+        return;
+    }
+    if !lobby.take_slot() {
+        reject(&connection, &mut send, DisconnectReason::ServerFull).await;
+        return;
+    }
+    if let Err(reason) = lobby.claim_username(&username) {
+        lobby.release_slot();
+        reject(&connection, &mut send, reason).await;
+        return;
+    }
+
+    let id = lobby.ids.fetch_add(1, Ordering::Relaxed);
+    let response = ReliablePacket::HandshakeRes { player_id: id };
+    if let Err(err) = send.send_reliable(&response).await {
+        warn!("failed to answer the handshake of {addr}: {err}");
+        lobby.left(&username);
+        lobby.release_slot();
+        return;
+    }
+
+    let (packet_action_sender, packet_action_receiver) = unbounded_channel();
+    let entered = lobby.enter(|| {
+        let _ = tcp_sender.send(TcpEvent::NewConnection {
+            id,
+            addr,
+            username: username.clone(),
+            packet_action_sender: packet_action_sender.clone(),
+        });
     });
-    // (tcp_receiver, udp_receiver)
+    if !entered {
+        reject(&connection, &mut send, DisconnectReason::ServerStopped).await;
+        lobby.left(&username);
+        lobby.release_slot();
+        return;
+    }
+
+    info!("{addr} connected successfully as {username}");
+
+    tokio::spawn(write_packets(
+        connection.clone(),
+        send,
+        packet_action_receiver,
+    ));
+    tokio::spawn(read_datagrams(
+        connection.clone(),
+        id,
+        udp_sender,
+        packet_action_sender.clone(),
+    ));
+
+    if let Err(err) = read_packets(recv, id, addr, &tcp_sender).await {
+        if err.is_peer_fault() {
+            disconnect_offender(addr, &packet_action_sender, err);
+        } else {
+            info!("{addr} disconnected: {err}");
+        }
+    }
+    let _ = tcp_sender.send(TcpEvent::Disconnected { id, addr });
+    lobby.left(&username);
+    lobby.release_slot();
+}
+
+/// Tells a client that sent something it should not have why it is being disconnected, once
+/// the packets queued before are sent.
+fn disconnect_offender(
+    addr: SocketAddr,
+    packet_action_sender: &UnboundedSender<PacketAction>,
+    err: NetworkError,
+) {
+    warn!("disconnecting {addr}: {err}");
+    let reason = DisconnectReason::ProtocolViolation(err.to_string());
+    let _ = packet_action_sender.send(PacketAction::Disconnect(reason));
+}
+
+/// Forwards packets to the game loop until the stream fails, or until the game loop is gone.
+async fn read_packets(
+    mut recv: RecvStream,
+    id: u32,
+    addr: SocketAddr,
+    sender: &Sender<TcpEvent>,
+) -> Result<(), NetworkError> {
+    loop {
+        let packet = recv.recv_reliable().await?;
+        if sender
+            .send(TcpEvent::PacketReceived { id, addr, packet })
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+async fn read_datagrams(
+    connection: Connection,
+    id: u32,
+    sender: Sender<UdpEvent>,
+    packet_action_sender: UnboundedSender<PacketAction>,
+) {
+    let addr = connection.remote_address();
+    while let Ok(datagram) = connection.read_datagram().await {
+        match decode_packet(&datagram) {
+            Ok(packet) => {
+                if sender
+                    .send(UdpEvent::PacketReceived { id, addr, packet })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(err) => {
+                disconnect_offender(addr, &packet_action_sender, err);
+                return;
+            }
+        }
+    }
+}
+
+async fn write_packets(
+    connection: Connection,
+    mut send: SendStream,
+    mut receiver: UnboundedReceiver<PacketAction>,
+) {
+    while let Some(action) = receiver.recv().await {
+        let result = match action {
+            PacketAction::Reliable(packet) => send.send_reliable(&packet).await,
+            PacketAction::Unreliable(packet) => packet.to_buf().and_then(|buf| {
+                connection
+                    .send_datagram(buf.into())
+                    .map_err(|err| NetworkError::ConnectionLost(std::io::Error::other(err)))
+            }),
+            PacketAction::Disconnect(reason) => {
+                disconnect(&connection, &mut send, reason).await;
+                return;
+            }
+        };
+
+        if let Err(err) = result {
+            warn!("failed to send to {}: {err}", connection.remote_address());
+            return;
+        }
+    }
 }
 
-fn create_server() -> Endpoint {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-    let cert_der = cert.serialize_der().unwrap();
-    let priv_key = cert.serialize_private_key_der();
-    let priv_key = rustls::PrivateKey(priv_key);
-    let cert_chain = vec![rustls::Certificate(cert_der.clone())];
+fn create_server(address: SocketAddr, identity: Identity) -> std::io::Result<Endpoint> {
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?
+        .with_no_client_auth()
+        .with_single_cert(identity.chain, identity.key)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    crypto.alpn_protocols = vec![GAME_ALPN.to_vec(), STATUS_ALPN.to_vec()];
 
-    let mut server_config = ServerConfig::with_single_cert(cert_chain, priv_key).unwrap();
+    let mut server_config = ServerConfig::with_crypto(Arc::new(crypto));
     let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
     transport_config.max_concurrent_uni_streams(0_u8.into());
 
-    Endpoint::server(server_config, shared::TCP_ADDRESS.parse().unwrap()).unwrap()
+    Endpoint::server(server_config, address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::OfflineAuth;
+
+    fn lobby() -> Lobby {
+        Lobby {
+            ids: Default::default(),
+            online: AtomicU32::new(0),
+            max_players: 20,
+            auth: Arc::new(OfflineAuth),
+            access: Default::default(),
+            usernames: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+            status: StatusInfo {
+                motd: String::new(),
+                icon: None,
+            },
+            authentications: Semaphore::new(MAX_CONCURRENT_AUTHENTICATIONS),
+        }
+    }
+
+    #[test]
+    fn closed_lobbies_let_nobody_in() {
+        let lobby = lobby();
+        assert_eq!(lobby.claim_username("alice"), Ok(()));
+        assert_eq!(
+            lobby.claim_username("alice"),
+            Err(DisconnectReason::AlreadyOnline)
+        );
+        assert_eq!(lobby.claim_username("bob"), Ok(()));
+        assert!(lobby.enter(|| {}));
+
+        lobby.close();
+        assert_eq!(
+            lobby.claim_username("carol"),
+            Err(DisconnectReason::ServerStopped)
+        );
+        assert!(!lobby.enter(|| panic!("entered a closed lobby")));
+    }
 }
@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use shared::protocol::validate_username;
+use shared::toml_file::{self, TomlFileError};
+
+/// Group every player is in, whether listed in it or not.
+pub const DEFAULT_GROUP: &str = "default";
+
+/// Sending chat messages.
+pub const CHAT: &str = "chat.send";
+
+/// What players may do unless the permissions file says otherwise.
+const DEFAULT_PERMISSIONS: &[&str] = &[CHAT, "command.help", "command.list", "command.seed"];
+
+/// Permissions shared by the players of a group and of the groups inheriting it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Group {
+    /// Groups whose permissions apply where this one's do not decide.
+    pub inherits: Vec<String>,
+    /// Rules such as `command.tp`, `command.*` or `*`, denying instead when prefixed with `-`.
+    pub permissions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayerPermissions {
+    /// Groups the player is in besides `DEFAULT_GROUP`, the first ones taking precedence.
+    pub groups: Vec<String>,
+    /// Rules overriding those of the player's groups.
+    pub permissions: Vec<String>,
+}
+
+/// Who may do what, read from `permissions.toml`.
+///
+/// Permission nodes are dot-separated, such as `chat.send` or `command.tp`. For a player, the
+/// most specific rule matching a node decides: operators may do anything, then the player's
+/// own rules apply, then those of their groups and of the groups these inherit, and last those
+/// of `DEFAULT_GROUP`. Nodes no rule matches are denied.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Permissions {
+    pub ops: BTreeSet<String>,
+    pub groups: BTreeMap<String, Group>,
+    pub players: BTreeMap<String, PlayerPermissions>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        let default_group = Group {
+            inherits: Vec::new(),
+            permissions: DEFAULT_PERMISSIONS
+                .iter()
+                .map(|node| node.to_string())
+                .collect(),
+        };
+        Self {
+            ops: BTreeSet::new(),
+            groups: BTreeMap::from([(DEFAULT_GROUP.to_string(), default_group)]),
+            players: BTreeMap::new(),
+        }
+    }
+}
+
+impl Permissions {
+    /// Loads and validates the permissions at `path`.
+    pub fn load(path: &Path) -> Result<Self, TomlFileError> {
+        let permissions: Self = toml_file::load(path)?;
+        permissions
+            .validate()
+            .map_err(|reason| TomlFileError::Invalid(path.to_path_buf(), reason))?;
+        Ok(permissions)
+    }
+
+    /// Loads the permissions at `path`, writing the defaults there first if the file is
+    /// missing.
+    pub fn load_or_create(path: &Path) -> Result<Self, TomlFileError> {
+        toml_file::load_or_create(path, Self::load)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TomlFileError> {
+        toml_file::save(path, self)
+    }
+
+    /// Checks every username, group reference and rule, reporting the first bad one.
+    pub fn validate(&self) -> Result<(), String> {
+        for op in &self.ops {
+            if let Err(err) = validate_username(op) {
+                return Err(format!("op {op}: {err}"));
+            }
+        }
+        for (name, group) in &self.groups {
+            for parent in &group.inherits {
+                if !self.groups.contains_key(parent) {
+                    return Err(format!("group {name} inherits unknown group {parent}"));
+                }
+            }
+            for rule in &group.permissions {
+                if let Err(err) = validate_rule(rule) {
+                    return Err(format!("group {name}: {err}"));
+                }
+            }
+        }
+        for (username, player) in &self.players {
+            if let Err(err) = validate_username(username) {
+                return Err(format!("player {username}: {err}"));
+            }
+            for group in &player.groups {
+                if !self.groups.contains_key(group) {
+                    return Err(format!("player {username} is in unknown group {group}"));
+                }
+            }
+            for rule in &player.permissions {
+                if let Err(err) = validate_rule(rule) {
+                    return Err(format!("player {username}: {err}"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_op(&self, username: &str) -> bool {
+        self.ops.contains(username)
+    }
+
+    /// Whether `username` has the permission `node`.
+    pub fn check(&self, username: &str, node: &str) -> bool {
+        if self.is_op(username) {
+            return true;
+        }
+
+        let player = self.players.get(username);
+        if let Some(granted) = player.and_then(|player| decide(&player.permissions, node)) {
+            return granted;
+        }
+        let groups = player.map_or(&[][..], |player| &player.groups);
+        groups
+            .iter()
+            .map(String::as_str)
+            .chain([DEFAULT_GROUP])
+            .find_map(|group| self.group_decides(group, node, &mut Vec::new()))
+            .unwrap_or(false)
+    }
+
+    /// What `group`, or else the groups it inherits, say about `node`. `visited` guards against
+    /// groups inheriting each other.
+    fn group_decides<'a>(
+        &'a self,
+        group: &'a str,
+        node: &str,
+        visited: &mut Vec<&'a str>,
+    ) -> Option<bool> {
+        if visited.contains(&group) {
+            return None;
+        }
+        visited.push(group);
+
+        let group = self.groups.get(group)?;
+        decide(&group.permissions, node).or_else(|| {
+            group
+                .inherits
+                .iter()
+                .find_map(|parent| self.group_decides(parent, node, visited))
+        })
+    }
+}
+
+/// A node is dot-separated words of letters, digits, `_` and `-`; a rule is a node, possibly
+/// ending in `*` to match every node below, and prefixed with `-` to deny.
+pub fn validate_rule(rule: &str) -> Result<(), String> {
+    let node = rule.strip_prefix('-').unwrap_or(rule);
+    let node = match node.strip_suffix('*') {
+        Some("") => return Ok(()),
+        Some(prefix) => prefix.strip_suffix('.').unwrap_or("."),
+        None => node,
+    };
+    let valid = node.split('.').all(|word| {
+        !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    });
+    if !valid {
+        return Err(format!("invalid permission rule \"{rule}\""));
+    }
+    Ok(())
+}
+
+/// How specific `rule`, without its `-`, is about `node`, if it applies to it at all.
+fn specificity(rule: &str, node: &str) -> Option<usize> {
+    if rule == node {
+        return Some(usize::MAX);
+    }
+    if rule == "*" {
+        return Some(0);
+    }
+    let prefix = rule.strip_suffix(".*")?;
+    node.strip_prefix(prefix)?
+        .starts_with('.')
+        .then_some(prefix.len() + 1)
+}
+
+/// Whether the most specific of `rules` matching `node` grants it. Denials win ties.
+fn decide(rules: &[String], node: &str) -> Option<bool> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let (granted, rule) = match rule.strip_prefix('-') {
+                Some(rule) => (false, rule),
+                None => (true, rule.as_str()),
+            };
+            Some((specificity(rule, node)?, !granted))
+        })
+        .max()
+        .map(|(_, denied)| !denied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> Vec<String> {
+        rules.iter().map(|rule| rule.to_string()).collect()
+    }
+
+    fn group(inherits: &[&str], permissions: &[&str]) -> Group {
+        Group {
+            inherits: rules(inherits),
+            permissions: rules(permissions),
+        }
+    }
+
+    #[test]
+    fn rules_are_as_specific_as_their_prefix() {
+        assert_eq!(specificity("command.tp", "command.tp"), Some(usize::MAX));
+        assert_eq!(specificity("*", "command.tp"), Some(0));
+        assert_eq!(specificity("command.*", "command.tp"), Some(8));
+        assert_eq!(specificity("command.tp.*", "command.tp.others"), Some(11));
+        assert!(
+            specificity("command.tp.*", "command.tp.others")
+                > specificity("command.*", "command.tp.others")
+        );
+
+        assert_eq!(specificity("command.*", "command"), None);
+        assert_eq!(specificity("command.*", "commands.tp"), None);
+        assert_eq!(specificity("command.tp", "command.tpa"), None);
+        assert_eq!(specificity("command.tp", "command"), None);
+    }
+
+    #[test]
+    fn the_most_specific_rule_decides() {
+        let rules = rules(&["*", "-command.*", "command.tp"]);
+        assert_eq!(decide(&rules, "command.tp"), Some(true));
+        assert_eq!(decide(&rules, "command.kick"), Some(false));
+        assert_eq!(decide(&rules, "chat.send"), Some(true));
+
+        assert_eq!(
+            decide(&self::rules(&["command.tp", "-command.tp"]), "command.tp"),
+            Some(false)
+        );
+        assert_eq!(
+            decide(&self::rules(&["-command.*", "command.*"]), "command.tp"),
+            Some(false)
+        );
+        assert_eq!(decide(&self::rules(&["command.tp"]), "chat.send"), None);
+        assert_eq!(decide(&[], "chat.send"), None);
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        for rule in [
+            "*",
+            "-*",
+            "chat.send",
+            "-command.*",
+            "a_b.c-d.*",
+            "command.tp.others",
+        ] {
+            assert_eq!(validate_rule(rule), Ok(()), "{rule}");
+        }
+        for rule in [
+            "",
+            "-",
+            "command.",
+            ".tp",
+            "command..tp",
+            "comm*",
+            "command.t*",
+            "*.tp",
+            "command tp",
+            "command.*.*",
+            "chät",
+        ] {
+            assert!(validate_rule(rule).is_err(), "{rule} is valid");
+        }
+    }
+
+    #[test]
+    fn groups_fall_back_to_those_they_inherit() {
+        let mut permissions = Permissions::default();
+        permissions
+            .groups
+            .insert("builder".into(), group(&["moderator"], &["-command.kick"]));
+        permissions.groups.insert(
+            "moderator".into(),
+            group(&[], &["command.kick", "command.tp"]),
+        );
+        let decides = |group, node| permissions.group_decides(group, node, &mut Vec::new());
+
+        assert_eq!(decides("builder", "command.kick"), Some(false));
+        assert_eq!(decides("builder", "command.tp"), Some(true));
+        assert_eq!(decides("builder", "command.stop"), None);
+        assert_eq!(decides("nobody", "command.tp"), None);
+    }
+
+    #[test]
+    fn inheritance_cycles_end() {
+        let mut permissions = Permissions::default();
+        permissions.groups.insert("a".into(), group(&["b"], &[]));
+        permissions
+            .groups
+            .insert("b".into(), group(&["a"], &["command.tp"]));
+        permissions.groups.insert("c".into(), group(&["c"], &[]));
+        permissions.validate().unwrap();
+
+        assert_eq!(
+            permissions.group_decides("a", "command.tp", &mut Vec::new()),
+            Some(true)
+        );
+        assert_eq!(
+            permissions.group_decides("a", "command.kick", &mut Vec::new()),
+            None
+        );
+        assert_eq!(
+            permissions.group_decides("c", "command.kick", &mut Vec::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn players_then_their_groups_then_the_default_decide() {
+        let mut permissions = Permissions::default();
+        permissions.ops.insert("root".into());
+        permissions
+            .groups
+            .insert("muted".into(), group(&[], &["-chat.*"]));
+        permissions
+            .groups
+            .insert("staff".into(), group(&[], &["chat.send", "command.*"]));
+        permissions.players.insert(
+            "alice".into(),
+            PlayerPermissions {
+                groups: rules(&["muted", "staff"]),
+                permissions: rules(&["-command.stop"]),
+            },
+        );
+        permissions.players.insert(
+            "bob".into(),
+            PlayerPermissions {
+                groups: rules(&["staff", "muted"]),
+                permissions: Vec::new(),
+            },
+        );
+        permissions.validate().unwrap();
+
+        // the first group listed wins
+        assert!(!permissions.check("alice", CHAT));
+        assert!(permissions.check("bob", CHAT));
+        // the player's own rules come before their groups'
+        assert!(permissions.check("alice", "command.tp"));
+        assert!(!permissions.check("alice", "command.stop"));
+        assert!(permissions.check("bob", "command.stop"));
+        // everyone else only has the default group
+        assert!(permissions.check("carol", CHAT));
+        assert!(permissions.check("carol", "command.seed"));
+        assert!(!permissions.check("carol", "command.tp"));
+        // nothing matching means no
+        assert!(!permissions.check("alice", "world.fly"));
+        // operators may do anything
+        assert!(permissions.check("root", "command.stop"));
+        assert!(permissions.check("root", "world.fly"));
+    }
+}
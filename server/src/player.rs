@@ -1,47 +1,226 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::net::SocketAddr;
-use std::sync::mpsc::Sender;
+use std::str::FromStr;
 
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, warn};
+
+use shared::block::{BlockId, BlockRegistry};
+use shared::movement::MOVE_SPEED;
 use shared::protocol::{PacketAction, ReliablePacket, UnreliablePacket};
 
+use crate::chat::RateLimiter;
+use crate::level::PlayerData;
+
+/// Where players appear when joining.
+pub const SPAWN_POSITION: [f32; 3] = [0.0, 80.0, 0.0];
+/// Blocks a player may move at once after standing still, enough for packets held up by a slow
+/// frame or the network.
+const MOVE_BURST: f32 = MOVE_SPEED / 2.0;
+/// How much faster than `MOVE_SPEED` players may seem to move, as their packets arrive unevenly.
+const MOVE_LEEWAY: f32 = 1.5;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    #[default]
+    Survival,
+    Creative,
+    Spectator,
+}
+
+impl GameMode {
+    pub const NAMES: &'static [&'static str] = &["survival", "creative", "spectator"];
+}
+
+impl Display for GameMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameMode::Survival => write!(f, "survival"),
+            GameMode::Creative => write!(f, "creative"),
+            GameMode::Spectator => write!(f, "spectator"),
+        }
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "survival" => Ok(GameMode::Survival),
+            "creative" => Ok(GameMode::Creative),
+            "spectator" => Ok(GameMode::Spectator),
+            _ => Err(format!("unknown game mode \"{s}\"")),
+        }
+    }
+}
+
 pub struct Player {
     id: u32,
-    tcp_addr: SocketAddr,
-    udp_addr: SocketAddr,
-    packet_action_sender: Sender<PacketAction>,
+    addr: SocketAddr,
+    username: String,
+    packet_action_sender: UnboundedSender<PacketAction>,
+    pub directions: [bool; 6],
+    pub rotations: [f32; 2],
+    /// As last reported by the client.
+    pub position: [f32; 3],
+    pub game_mode: GameMode,
+    /// Blocks given to the player, by count.
+    pub inventory: BTreeMap<BlockId, u32>,
+    /// Shared by chat messages and commands.
+    pub chat_limiter: RateLimiter,
+    pub completion_limiter: RateLimiter,
+    /// Holds the distance the player may still move, in blocks.
+    move_limiter: RateLimiter,
 }
 
 impl Player {
     pub fn new(
         id: u32,
-        tcp_addr: SocketAddr,
-        udp_addr: SocketAddr,
-        packet_action_sender: Sender<PacketAction>,
+        addr: SocketAddr,
+        username: String,
+        packet_action_sender: UnboundedSender<PacketAction>,
     ) -> Self {
         Self {
             id,
-            tcp_addr,
-            udp_addr,
+            addr,
+            username,
             packet_action_sender,
+            directions: [false; 6],
+            rotations: [0.0; 2],
+            position: SPAWN_POSITION,
+            game_mode: GameMode::default(),
+            inventory: BTreeMap::new(),
+            chat_limiter: RateLimiter::chat(),
+            completion_limiter: RateLimiter::completion(),
+            move_limiter: RateLimiter::new(MOVE_BURST, MOVE_SPEED * MOVE_LEEWAY),
         }
     }
 
-    pub fn tcp_addr(&self) -> &SocketAddr {
-        &self.tcp_addr
+    pub fn id(&self) -> u32 {
+        self.id
     }
 
-    pub fn udp_addr(&self) -> &SocketAddr {
-        &self.udp_addr
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// What to save of the player, naming blocks with `blocks`.
+    pub fn data(&self, blocks: &BlockRegistry) -> PlayerData {
+        PlayerData {
+            position: self.position,
+            rotations: self.rotations,
+            game_mode: self.game_mode,
+            inventory: self
+                .inventory
+                .iter()
+                .filter_map(|(&id, &count)| Some((blocks.get(id)?.name().to_string(), count)))
+                .collect(),
+        }
+    }
+
+    /// Moves the player to where the client says they are, unless that is not a position at all
+    /// or further than `MOVE_SPEED` lets them go in the time since they last moved, however many
+    /// packets that took. Returns whether they moved.
+    pub fn move_to(&mut self, position: [f32; 3]) -> bool {
+        if !position.iter().all(|axis| axis.is_finite()) {
+            return false;
+        }
+        let distance = (0..3)
+            .map(|axis| (position[axis] - self.position[axis]).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        if !self.move_limiter.try_take(distance) {
+            return false;
+        }
+        self.position = position;
+        true
+    }
+
+    /// Restores what was saved of the player, dropping blocks `blocks` no longer has.
+    pub fn restore(&mut self, data: PlayerData, blocks: &BlockRegistry) {
+        if data.position.iter().all(|axis| axis.is_finite()) {
+            self.position = data.position;
+        }
+        self.rotations = data.rotations;
+        self.game_mode = data.game_mode;
+        self.inventory.clear();
+        for (name, count) in data.inventory {
+            match blocks.id(&name) {
+                Some(id) => {
+                    self.inventory.insert(id, count);
+                }
+                None => warn!("{} had {count} of unknown block {name}", self.username),
+            }
+        }
     }
 
     pub fn send_packet_action(&self, action: PacketAction) {
-        self.packet_action_sender.send(action).unwrap();
+        // the connection task is gone once the player disconnected, the game loop learns about
+        // it right after.
+        if self.packet_action_sender.send(action).is_err() {
+            debug!("dropped a packet to {}, they disconnected", self.addr);
+        }
     }
 
-    pub async fn send_reliable_packet(&self, message: ReliablePacket) {
+    pub fn send_reliable_packet(&self, message: ReliablePacket) {
         self.send_packet_action(PacketAction::Reliable(message))
     }
 
-    pub async fn send_unreliable_packet(&self, message: UnreliablePacket) {
+    pub fn send_unreliable_packet(&self, message: UnreliablePacket) {
         self.send_packet_action(PacketAction::Unreliable(message))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn player() -> Player {
+        let (sender, _) = unbounded_channel();
+        Player::new(1, "127.0.0.1:1".parse().unwrap(), "player".into(), sender)
+    }
+
+    #[test]
+    fn players_cannot_jump_far() {
+        let mut player = player();
+        let [x, y, z] = SPAWN_POSITION;
+        assert!(player.move_to([x + 0.6, y - 0.6, z + 0.5]));
+        assert_eq!(player.position, [x + 0.6, y - 0.6, z + 0.5]);
+
+        assert!(!player.move_to([x + MOVE_BURST + 1.0, y - 0.6, z + 0.5]));
+        assert!(!player.move_to([x, y + 1000.0, z]));
+        assert!(!player.move_to([f32::NAN, y, z]));
+        assert!(!player.move_to([x, f32::INFINITY, z]));
+        assert_eq!(player.position, [x + 0.6, y - 0.6, z + 0.5]);
+    }
+
+    #[test]
+    fn flooding_moves_is_no_faster() {
+        let mut player = player();
+        let [x, y, z] = SPAWN_POSITION;
+        let mut moves = 0;
+        for _ in 0..100 {
+            if player.move_to([player.position[0] + 0.25, y, z]) {
+                moves += 1;
+            }
+        }
+        assert_eq!(moves, (MOVE_BURST / 0.25) as usize);
+        assert_eq!(player.position, [x + MOVE_BURST, y, z]);
+
+        // the allowance comes back with time
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(player.move_to([x + MOVE_BURST + MOVE_SPEED * 0.2, y, z]));
+    }
+}
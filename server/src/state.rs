@@ -1,10 +1,91 @@
+use crate::access::AccessList;
+use crate::commands::{CommandRegistry, ConsoleCommand};
+use crate::config::ServerConfig;
+use crate::level::Level;
 use crate::networking::{TcpEvent, UdpEvent};
+use crate::permissions::Permissions;
 use crate::player::Player;
+use shared::block::BlockRegistry;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+/// Ticks in a day.
+pub const DAY_LENGTH: u64 = 24000;
 
 pub struct ServerState {
+    pub config: ServerConfig,
+    pub seed: u64,
+    pub blocks: BlockRegistry,
+    /// Ticks since the world was created.
+    pub time: u64,
+    pub commands: Arc<CommandRegistry>,
+    pub permissions: Permissions,
+    /// Shared with the handshakes.
+    pub access: Arc<Mutex<AccessList>>,
     pub tcp_receiver: Receiver<TcpEvent>,
     pub udp_receiver: Receiver<UdpEvent>,
+    pub console_receiver: Receiver<ConsoleCommand>,
     pub players: HashMap<u32, Player>,
+    /// Cleared to stop the game loop after the current tick.
+    pub running: Arc<AtomicBool>,
+}
+
+impl ServerState {
+    pub fn player_by_name(&self, username: &str) -> Option<&Player> {
+        self.players
+            .values()
+            .find(|player| player.username() == username)
+    }
+
+    /// Whether player `id` has the permission `node`.
+    pub fn has_permission(&self, id: u32, node: &str) -> bool {
+        self.players
+            .get(&id)
+            .is_some_and(|player| self.permissions.check(player.username(), node))
+    }
+
+    pub fn level(&self) -> Level {
+        Level {
+            seed: self.seed,
+            time: self.time,
+        }
+    }
+
+    /// Stops the game loop once the current tick is done, the server then shuts down.
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl ServerState {
+    /// A state with the default settings, no players and nothing connected to it.
+    pub(crate) fn for_tests() -> Self {
+        Self {
+            config: ServerConfig::default(),
+            seed: 0,
+            blocks: BlockRegistry::default(),
+            time: 0,
+            commands: Default::default(),
+            permissions: Permissions::default(),
+            access: Default::default(),
+            tcp_receiver: std::sync::mpsc::channel().1,
+            udp_receiver: std::sync::mpsc::channel().1,
+            console_receiver: std::sync::mpsc::channel().1,
+            players: HashMap::new(),
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Adds an online player, returning their id.
+    pub(crate) fn add_player(&mut self, username: &str) -> u32 {
+        let id = self.players.len() as u32 + 1;
+        let (sender, _) = tokio::sync::mpsc::unbounded_channel();
+        let addr = format!("127.0.0.{id}:1000").parse().unwrap();
+        let player = Player::new(id, addr, username.to_string(), sender);
+        self.players.insert(id, player);
+        id
+    }
 }
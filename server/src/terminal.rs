@@ -0,0 +1,490 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::Mutex;
+
+use crossterm::cursor::{MoveLeft, MoveToColumn};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::queue;
+use crossterm::terminal::{self, Clear, ClearType};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::commands::Console;
+
+const PROMPT: &str = "> ";
+/// Lines kept for the up and down keys.
+const MAX_HISTORY: usize = 100;
+
+static LINE: Mutex<Line> = Mutex::new(Line::new());
+
+/// The line being typed at the prompt.
+struct Line {
+    /// Only while the terminal is in raw mode, otherwise the terminal echoes input itself.
+    shown: bool,
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            shown: false,
+            chars: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.chars).into_iter().collect()
+    }
+
+    /// Draws the prompt over the current terminal line, leaving the cursor where it edits.
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let text: String = self.chars.iter().collect();
+        queue!(out, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+        write!(out, "{PROMPT}{text}")?;
+        let back = self.chars.len() - self.cursor;
+        if back > 0 {
+            queue!(out, MoveLeft(back as u16))?;
+        }
+        out.flush()
+    }
+}
+
+/// Writes `text` over the prompt. Raw mode leaves line feeds alone, so each one also returns
+/// the cursor to the start of the line.
+fn print_raw(out: &mut impl Write, text: &[u8]) -> io::Result<()> {
+    queue!(out, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+    for (i, part) in text.split(|&byte| byte == b'\n').enumerate() {
+        if i > 0 {
+            out.write_all(b"\r\n")?;
+        }
+        out.write_all(part)?;
+    }
+    Ok(())
+}
+
+/// Prints `text` above the prompt.
+pub fn print(text: &[u8]) {
+    let line = LINE.lock().unwrap();
+    let mut out = io::stdout().lock();
+    let _ = if line.shown {
+        print_raw(&mut out, text).and_then(|()| line.draw(&mut out))
+    } else {
+        out.write_all(text).and_then(|()| out.flush())
+    };
+}
+
+pub fn println(text: &str) {
+    print(format!("{text}\n").as_bytes());
+}
+
+/// Makes tracing print each event above the prompt.
+pub struct LogWriter;
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = EventWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        EventWriter(Vec::new())
+    }
+}
+
+/// Collects one event, printed whole once dropped so the prompt is redrawn once per event.
+pub struct EventWriter(Vec<u8>);
+
+impl Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventWriter {
+    fn drop(&mut self) {
+        if !self.0.is_empty() {
+            print(&self.0);
+        }
+    }
+}
+
+/// Keeps the terminal in raw mode for line editing, restoring it once dropped.
+pub struct RawMode(());
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // keystrokes come one by one, unechoed, and Ctrl-C reaches us instead of killing the
+        // server with the terminal left raw
+        terminal::enable_raw_mode()?;
+        Ok(Self(()))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut line = LINE.lock().unwrap();
+        line.shown = false;
+        let mut out = io::stdout().lock();
+        let _ =
+            queue!(out, MoveToColumn(0), Clear(ClearType::CurrentLine)).and_then(|()| out.flush());
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Reads commands from stdin on a thread of its own and runs them with `console`, printing
+/// their output. When stdin and stdout are a terminal, lines are edited at a prompt for as long
+/// as the returned guard lives; otherwise they are read as they come.
+pub fn spawn_console(console: Console) -> io::Result<Option<RawMode>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        std::thread::Builder::new()
+            .name("console".into())
+            .spawn(move || read_lines(console))?;
+        return Ok(None);
+    }
+
+    let raw_mode = RawMode::enable()?;
+    {
+        let mut line = LINE.lock().unwrap();
+        line.shown = true;
+        let _ = line.draw(&mut io::stdout().lock());
+    }
+    std::thread::Builder::new()
+        .name("console".into())
+        .spawn(move || edit_lines(console))?;
+    Ok(Some(raw_mode))
+}
+
+fn run(console: &Console, input: &str) {
+    if input.trim().is_empty() {
+        return;
+    }
+    for output in console.run(input) {
+        println(&output);
+    }
+}
+
+fn read_lines(console: Console) {
+    for input in io::stdin().lock().lines() {
+        let Ok(input) = input else {
+            return;
+        };
+        run(&console, &input);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    ClearLine,
+    /// Ctrl-C.
+    Interrupt,
+    /// Ctrl-D.
+    EndOfFile,
+    Other,
+}
+
+/// The next key pressed, or `None` once the terminal can no longer be read.
+fn read_key() -> Option<Key> {
+    loop {
+        match crossterm::event::read().ok()? {
+            Event::Key(event) if event.kind != KeyEventKind::Release => return Some(key(event)),
+            _ => {}
+        }
+    }
+}
+
+/// What a key does, with the usual Emacs bindings for the control keys.
+fn key(event: KeyEvent) -> Key {
+    if event.modifiers.contains(KeyModifiers::CONTROL) {
+        return match event.code {
+            KeyCode::Char('a') => Key::Home,
+            KeyCode::Char('b') => Key::Left,
+            KeyCode::Char('c') => Key::Interrupt,
+            KeyCode::Char('d') => Key::EndOfFile,
+            KeyCode::Char('e') => Key::End,
+            KeyCode::Char('f') => Key::Right,
+            KeyCode::Char('h') => Key::Backspace,
+            KeyCode::Char('n') => Key::Down,
+            KeyCode::Char('p') => Key::Up,
+            KeyCode::Char('u') => Key::ClearLine,
+            _ => Key::Other,
+        };
+    }
+    match event.code {
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        _ => Key::Other,
+    }
+}
+
+/// Lines entered before, browsed with the up and down keys.
+#[derive(Default)]
+struct History {
+    lines: Vec<String>,
+    /// Of the line shown, `lines.len()` being the one typed before browsing.
+    position: usize,
+    draft: String,
+}
+
+impl History {
+    fn push(&mut self, input: &str) {
+        if !input.trim().is_empty() && self.lines.last().map(String::as_str) != Some(input) {
+            if self.lines.len() == MAX_HISTORY {
+                self.lines.remove(0);
+            }
+            self.lines.push(input.to_string());
+        }
+        self.position = self.lines.len();
+    }
+
+    fn up(&mut self, line: &mut Line) {
+        if self.position == 0 {
+            return;
+        }
+        if self.position == self.lines.len() {
+            self.draft = line.chars.iter().collect();
+        }
+        self.position -= 1;
+        line.set(&self.lines[self.position]);
+    }
+
+    fn down(&mut self, line: &mut Line) {
+        if self.position == self.lines.len() {
+            return;
+        }
+        self.position += 1;
+        match self.lines.get(self.position) {
+            Some(input) => line.set(input),
+            None => line.set(&self.draft),
+        }
+    }
+}
+
+/// Edits `line` with `key`, returning the input once entered.
+fn edit(line: &mut Line, history: &mut History, key: Key) -> Option<String> {
+    match key {
+        Key::Char(c) => {
+            line.chars.insert(line.cursor, c);
+            line.cursor += 1;
+        }
+        Key::Enter => {
+            let input = line.take();
+            history.push(&input);
+            return Some(input);
+        }
+        Key::Backspace if line.cursor > 0 => {
+            line.cursor -= 1;
+            line.chars.remove(line.cursor);
+        }
+        Key::Delete | Key::EndOfFile if line.cursor < line.chars.len() => {
+            line.chars.remove(line.cursor);
+        }
+        Key::Left => line.cursor = line.cursor.saturating_sub(1),
+        Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+        Key::Home => line.cursor = 0,
+        Key::End => line.cursor = line.chars.len(),
+        Key::Up => history.up(line),
+        Key::Down => history.down(line),
+        Key::ClearLine => {
+            line.take();
+        }
+        // on an empty line, as the server would otherwise only stop with a signal
+        Key::Interrupt | Key::EndOfFile if line.chars.is_empty() => {
+            return Some("stop".to_string());
+        }
+        Key::Interrupt => {
+            line.take();
+        }
+        _ => {}
+    }
+    None
+}
+
+fn edit_lines(console: Console) {
+    let mut history = History::default();
+    while let Some(key) = read_key() {
+        let entered = {
+            let mut line = LINE.lock().unwrap();
+            if !line.shown {
+                return;
+            }
+            let entered = edit(&mut line, &mut history, key);
+            let mut out = io::stdout().lock();
+            if let Some(input) = &entered {
+                // keep what was run above the prompt, as a terminal would
+                let _ = print_raw(&mut out, format!("{PROMPT}{input}\n").as_bytes());
+            }
+            let _ = line.draw(&mut out);
+            entered
+        };
+        if let Some(input) = entered {
+            run(&console, &input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(line: &mut Line, history: &mut History, keys: &[Key]) -> Vec<String> {
+        keys.iter()
+            .filter_map(|&key| edit(line, history, key))
+            .collect()
+    }
+
+    fn type_text(line: &mut Line, history: &mut History, text: &str) {
+        for c in text.chars() {
+            assert_eq!(edit(line, history, Key::Char(c)), None);
+        }
+    }
+
+    fn text(line: &Line) -> String {
+        line.chars.iter().collect()
+    }
+
+    #[test]
+    fn keys_edit_at_the_cursor() {
+        let mut line = Line::new();
+        let mut history = History::default();
+        type_text(&mut line, &mut history, "hello");
+        type_keys(
+            &mut line,
+            &mut history,
+            &[Key::Home, Key::Right, Key::Delete],
+        );
+        assert_eq!(text(&line), "hllo");
+        assert_eq!(line.cursor, 1);
+
+        type_text(&mut line, &mut history, "e");
+        type_keys(&mut line, &mut history, &[Key::End, Key::Backspace]);
+        assert_eq!(text(&line), "hell");
+        assert_eq!(line.cursor, 4);
+
+        // the cursor stays within the line
+        type_keys(&mut line, &mut history, &[Key::Right, Key::Delete]);
+        assert_eq!((text(&line).as_str(), line.cursor), ("hell", 4));
+        type_keys(
+            &mut line,
+            &mut history,
+            &[Key::Home, Key::Left, Key::Backspace],
+        );
+        assert_eq!((text(&line).as_str(), line.cursor), ("hell", 0));
+
+        type_keys(&mut line, &mut history, &[Key::ClearLine]);
+        assert_eq!((text(&line).as_str(), line.cursor), ("", 0));
+    }
+
+    #[test]
+    fn enter_takes_the_line() {
+        let mut line = Line::new();
+        let mut history = History::default();
+        type_text(&mut line, &mut history, "list");
+        type_keys(&mut line, &mut history, &[Key::Left, Key::Left]);
+        assert_eq!(
+            edit(&mut line, &mut history, Key::Enter),
+            Some("list".into())
+        );
+        assert_eq!((text(&line).as_str(), line.cursor), ("", 0));
+        assert_eq!(history.lines, ["list"]);
+    }
+
+    #[test]
+    fn interrupting_an_empty_line_stops_the_server() {
+        let mut line = Line::new();
+        let mut history = History::default();
+        type_text(&mut line, &mut history, "say hi");
+        assert!(type_keys(&mut line, &mut history, &[Key::Interrupt]).is_empty());
+        assert_eq!(text(&line), "");
+        assert_eq!(
+            type_keys(&mut line, &mut history, &[Key::Interrupt]),
+            ["stop"]
+        );
+
+        // Ctrl-D deletes while there is something to delete
+        type_text(&mut line, &mut history, "ab");
+        assert!(type_keys(&mut line, &mut history, &[Key::Home, Key::EndOfFile]).is_empty());
+        assert_eq!(text(&line), "b");
+        assert!(type_keys(&mut line, &mut history, &[Key::End, Key::EndOfFile]).is_empty());
+        type_keys(&mut line, &mut history, &[Key::ClearLine]);
+        assert_eq!(
+            type_keys(&mut line, &mut history, &[Key::EndOfFile]),
+            ["stop"]
+        );
+        assert!(history.lines.is_empty());
+    }
+
+    #[test]
+    fn up_and_down_browse_the_history() {
+        let mut line = Line::new();
+        let mut history = History::default();
+        for input in ["first", "second"] {
+            type_text(&mut line, &mut history, input);
+            edit(&mut line, &mut history, Key::Enter);
+        }
+
+        type_text(&mut line, &mut history, "draft");
+        type_keys(&mut line, &mut history, &[Key::Up]);
+        assert_eq!(text(&line), "second");
+        assert_eq!(line.cursor, 6);
+        type_keys(&mut line, &mut history, &[Key::Up, Key::Up]);
+        assert_eq!(text(&line), "first");
+
+        type_keys(&mut line, &mut history, &[Key::Down]);
+        assert_eq!(text(&line), "second");
+        type_keys(&mut line, &mut history, &[Key::Down, Key::Down]);
+        assert_eq!(text(&line), "draft");
+
+        // entering a line from the history starts browsing from the end again
+        type_keys(&mut line, &mut history, &[Key::Up, Key::Up]);
+        assert_eq!(type_keys(&mut line, &mut history, &[Key::Enter]), ["first"]);
+        type_keys(&mut line, &mut history, &[Key::Up]);
+        assert_eq!(text(&line), "first");
+        assert_eq!(history.lines, ["first", "second", "first"]);
+    }
+
+    #[test]
+    fn history_skips_blank_and_repeated_lines() {
+        let mut history = History::default();
+        for input in ["", "  ", "list", "list", "help", "list"] {
+            history.push(input);
+        }
+        assert_eq!(history.lines, ["list", "help", "list"]);
+        assert_eq!(history.position, 3);
+
+        for i in 0..MAX_HISTORY + 5 {
+            history.push(&i.to_string());
+        }
+        assert_eq!(history.lines.len(), MAX_HISTORY);
+        assert_eq!(history.lines[0], "5");
+        assert_eq!(
+            history.lines.last().unwrap(),
+            &(MAX_HISTORY + 4).to_string()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use rustls::{Certificate, PrivateKey};
+use rustls_pemfile::Item;
+use tracing::info;
+
+/// The certificate chain presented to clients and its private key.
+pub struct Identity {
+    pub chain: Vec<Certificate>,
+    pub key: PrivateKey,
+}
+
+/// Loads the PEM certificate chain and private key at the given paths, generating and saving a
+/// self-signed pair first if neither exists. Clients pin the certificate the first time they
+/// connect, so it has to survive restarts.
+pub fn load_or_generate(certificate: &Path, private_key: &Path) -> io::Result<Identity> {
+    match (certificate.exists(), private_key.exists()) {
+        (true, true) => load(certificate, private_key),
+        (false, false) => {
+            info!(
+                "generating a self-signed certificate in {}",
+                certificate.display()
+            );
+            generate(certificate, private_key)
+        }
+        (true, false) => Err(missing(private_key)),
+        (false, true) => Err(missing(certificate)),
+    }
+}
+
+fn missing(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} is missing", path.display()),
+    )
+}
+
+fn invalid(path: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {reason}", path.display()),
+    )
+}
+
+fn read_pem(path: &Path) -> io::Result<Vec<Item>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::read_all(&mut reader).map_err(|err| invalid(path, &err.to_string()))
+}
+
+fn load(certificate: &Path, private_key: &Path) -> io::Result<Identity> {
+    let chain: Vec<_> = read_pem(certificate)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if chain.is_empty() {
+        return Err(invalid(certificate, "no certificate found"));
+    }
+
+    let key = read_pem(private_key)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| invalid(private_key, "no private key found"))?;
+
+    Ok(Identity { chain, key })
+}
+
+fn generate(certificate: &Path, private_key: &Path) -> io::Result<Identity> {
+    let generated =
+        rcgen::generate_simple_self_signed(vec!["localhost".into()]).map_err(io::Error::other)?;
+    fs::write(
+        certificate,
+        generated.serialize_pem().map_err(io::Error::other)?,
+    )?;
+    write_private(
+        private_key,
+        generated.serialize_private_key_pem().as_bytes(),
+    )?;
+
+    // every serialization signs the certificate anew, so read back exactly what was saved.
+    load(certificate, private_key)
+}
+
+#[cfg(unix)]
+pub(crate) fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(content)
+}
+
+/// Replaces the file at `path` with `content`, readable only by the owner. The content is
+/// written next to it first, so the file is never left half written.
+pub(crate) fn replace_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = Path::new(&temporary);
+
+    match fs::remove_file(temporary) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    write_private(temporary, content)?;
+    fs::rename(temporary, path)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    fs::write(path, content)
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::time::Duration;
+
+use server::player::SPAWN_POSITION;
+use shared::movement::MOVE_SPEED;
+use shared::packet_ext::{AsyncPacketReadExt, AsyncPacketWriteExt};
+use shared::protocol::ReliablePacket;
+use tokio::task::block_in_place;
+use tokio::time::timeout;
+
+/// Reads packets until one matches, failing after a few seconds.
+async fn expect(
+    recv: &mut quinn::RecvStream,
+    what: &str,
+    mut matches: impl FnMut(&ReliablePacket) -> bool,
+) {
+    let found = timeout(Duration::from_secs(5), async {
+        loop {
+            let packet = recv.recv_reliable().await.unwrap();
+            if matches(&packet) {
+                return;
+            }
+        }
+    })
+    .await;
+    assert!(found.is_ok(), "no {what}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn players_cannot_teleport_or_spam_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(dir.path()).await;
+    let (_connection, mut send, mut recv) = common::connect_raw(&server).await;
+    common::join_raw(&mut send, &mut recv, "mallory").await;
+
+    let teleported_to_spawn = |packet: &ReliablePacket| matches!(packet, ReliablePacket::Teleport { position } if *position == SPAWN_POSITION);
+    expect(&mut recv, "teleport to spawn on join", teleported_to_spawn).await;
+
+    let [x, y, z] = SPAWN_POSITION;
+    send.send_reliable(&ReliablePacket::MovementInput {
+        directions: [false; 6],
+        rotations: [0.0; 2],
+        position: [x + 500.0, y, z],
+    })
+    .await
+    .unwrap();
+    expect(&mut recv, "teleport back", teleported_to_spawn).await;
+
+    for _ in 0..10 {
+        send.send_reliable(&ReliablePacket::RunCommand {
+            input: "seed".to_string(),
+        })
+        .await
+        .unwrap();
+    }
+    expect(&mut recv, "rate limit warning", |packet| {
+        matches!(packet, ReliablePacket::ChatBroadcast { message } if message.text.contains("too fast"))
+    })
+    .await;
+
+    block_in_place(|| server.stop()).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flooding_moves_does_not_speed_players_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(dir.path()).await;
+    let (_connection, mut send, mut recv) = common::connect_raw(&server).await;
+    common::join_raw(&mut send, &mut recv, "mallory").await;
+    expect(&mut recv, "teleport to spawn on join", |packet| {
+        matches!(packet, ReliablePacket::Teleport { .. })
+    })
+    .await;
+
+    // 25 blocks in a few milliseconds
+    let [x, y, z] = SPAWN_POSITION;
+    for step in 1..=100 {
+        send.send_reliable(&ReliablePacket::MovementInput {
+            directions: [false, true, false, false, false, false],
+            rotations: [90.0, 0.0],
+            position: [x + step as f32 * 0.25, y, z],
+        })
+        .await
+        .unwrap();
+    }
+
+    let mut sent_back_to = None;
+    let _ = timeout(Duration::from_millis(500), async {
+        loop {
+            if let ReliablePacket::Teleport { position } = recv.recv_reliable().await.unwrap() {
+                sent_back_to = Some(position[0]);
+            }
+        }
+    })
+    .await;
+    let sent_back_to = sent_back_to.expect("the server never sent the player back");
+    assert!(
+        sent_back_to <= x + MOVE_SPEED,
+        "the server let the player get to x = {sent_back_to}"
+    );
+
+    block_in_place(|| server.stop()).unwrap();
+}
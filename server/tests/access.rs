@@ -0,0 +1,37 @@
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use server::ServerHandle;
+use tokio::task::block_in_place;
+
+fn run(server: &ServerHandle, input: &str) -> String {
+    let receiver = server.console().run(input);
+    block_in_place(|| receiver.recv_timeout(Duration::from_secs(5))).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn access_changes_that_fail_to_save_are_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(dir.path()).await;
+
+    // a directory in the way of the file makes every save fail
+    let access_list = dir.path().join("access.toml");
+    fs::remove_file(&access_list).unwrap();
+    fs::create_dir(&access_list).unwrap();
+
+    let output = run(&server, "ban alice");
+    assert!(output.contains("access.toml"), "ban printed {output:?}");
+    assert_eq!(run(&server, "banlist"), "nobody is banned");
+    let (_connection, mut send, mut recv) = common::connect_raw(&server).await;
+    common::join_raw(&mut send, &mut recv, "alice").await;
+
+    fs::remove_dir(&access_list).unwrap();
+    run(&server, "whitelist add bob");
+    let saved = fs::read_to_string(&access_list).unwrap();
+    assert!(saved.contains("bob"));
+    assert!(!saved.contains("alice"));
+
+    block_in_place(|| server.stop()).unwrap();
+}
@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use server::config::ServerConfig;
+use server::ServerHandle;
+use shared::packet_ext::{AsyncPacketReadExt, AsyncPacketWriteExt};
+use shared::protocol::{Credentials, DisconnectReason, ProtocolHello, ReliablePacket, GAME_ALPN};
+
+/// A server on a free port of the loopback address, keeping its files in `dir`.
+pub fn server_config(dir: &Path) -> ServerConfig {
+    ServerConfig {
+        port: 0,
+        certificate: dir.join("server.crt"),
+        private_key: dir.join("server.key"),
+        world_dir: dir.join("world"),
+        permissions: dir.join("permissions.toml"),
+        access_list: dir.join("access.toml"),
+        lan_discovery: false,
+        ..Default::default()
+    }
+}
+
+pub async fn start_server(dir: &Path) -> ServerHandle {
+    server::start(server_config(dir))
+        .await
+        .expect("the server failed to start")
+}
+
+/// Trusts any certificate, the tests talking to their own server.
+struct TrustAnything;
+
+impl ServerCertVerifier for TrustAnything {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// A game connection to `server` without a client on top, to send whatever bytes a test needs.
+pub async fn connect_raw(server: &ServerHandle) -> (Connection, SendStream, RecvStream) {
+    let mut crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(TrustAnything))
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![GAME_ALPN.to_vec()];
+
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(ClientConfig::new(Arc::new(crypto)));
+    let connection = endpoint
+        .connect(server.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("failed to connect");
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    send.send_packet(&ProtocolHello::CURRENT).await.unwrap();
+    let hello = recv.recv_packet::<ProtocolHello>().await.unwrap();
+    assert_eq!(hello, ProtocolHello::CURRENT);
+    (connection, send, recv)
+}
+
+/// Logs in as `username` over a raw connection.
+pub async fn join_raw(send: &mut SendStream, recv: &mut RecvStream, username: &str) -> u32 {
+    let handshake = ReliablePacket::Handshake {
+        username: username.to_string(),
+        credentials: Credentials::None,
+    };
+    send.send_reliable(&handshake).await.unwrap();
+    match recv.recv_reliable().await.unwrap() {
+        ReliablePacket::HandshakeRes { player_id } => player_id,
+        packet => panic!("expected a handshake response, got {packet:?}"),
+    }
+}
+
+/// Reads packets until the server disconnects, returning why.
+pub async fn disconnect_reason(recv: &mut RecvStream) -> DisconnectReason {
+    loop {
+        match recv.recv_reliable().await {
+            Ok(ReliablePacket::Disconnect { reason }) => return reason,
+            Ok(_) => {}
+            Err(err) => panic!("the connection ended without a disconnect: {err}"),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::time::Duration;
+
+use client::game::{GameState, PlayerMovement};
+use client::headless::HeadlessClient;
+use server::level::PlayerData;
+use server::player::SPAWN_POSITION;
+use tokio::task::block_in_place;
+use tokio::time::{sleep, Instant};
+
+const CLIENTS: usize = 4;
+const TICKS: u64 = 20;
+
+/// Holds forward facing +x, leaving it to the client to move the player.
+fn walk(_tick: u64, _game: &GameState) -> PlayerMovement {
+    PlayerMovement {
+        directions: [false, true, false, false, false, false],
+        rotations: [90.0, 0.0],
+        ..Default::default()
+    }
+}
+
+/// Runs `input` as the console until its output starts with `expected`, for up to a few seconds.
+async fn wait_for_console(server: &server::ServerHandle, input: &str, expected: &str) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut output = String::new();
+    while Instant::now() < deadline {
+        let receiver = server.console().run(input);
+        output = block_in_place(|| receiver.recv_timeout(Duration::from_secs(1))).unwrap();
+        if output.starts_with(expected) {
+            return;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    panic!("/{input} printed {output:?}, expected {expected:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn headless_clients_join_move_and_leave() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(dir.path()).await;
+
+    let mut clients = Vec::new();
+    for i in 0..CLIENTS {
+        let client =
+            HeadlessClient::connect(server.local_addr().unwrap(), &format!("bot{i}"), walk)
+                .await
+                .expect("the handshake failed");
+        clients.push(client);
+    }
+    let mut ids: Vec<u32> = clients
+        .iter()
+        .map(|client| client.game().player.id())
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), CLIENTS, "player ids are not unique");
+    wait_for_console(&server, "list", &format!("{CLIENTS}/")).await;
+
+    // a fresh interval fires right away, so tick on sleeps to give the server time to follow
+    for _ in 0..TICKS {
+        sleep(Duration::from_millis(10)).await;
+        for client in &mut clients {
+            client.tick();
+        }
+    }
+    sleep(Duration::from_millis(200)).await;
+
+    let mut positions = Vec::new();
+    for client in clients {
+        assert!(client.game().is_connected());
+        assert_eq!(client.tick_count(), TICKS);
+        let position = client.game().player.movement.position;
+        let [x, y, z] = SPAWN_POSITION;
+        assert!(
+            position[0] > x + 0.5,
+            "bot{} only got to {position:?}",
+            positions.len()
+        );
+        assert!((position[1] - y).abs() < 1e-3 && (position[2] - z).abs() < 1e-3);
+        positions.push(position);
+        client.disconnect();
+    }
+    wait_for_console(&server, "list", "0/").await;
+
+    // players are saved as they leave
+    let world_dir = dir.path().join("world");
+    for (i, position) in positions.into_iter().enumerate() {
+        let data = PlayerData::load(&world_dir, &format!("bot{i}"))
+            .unwrap()
+            .expect("the player was not saved");
+        assert_eq!(data.position, position);
+    }
+
+    block_in_place(|| server.stop()).unwrap();
+}
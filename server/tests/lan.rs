@@ -0,0 +1,49 @@
+mod common;
+
+use client::lan::LanDiscovery;
+use server::lan::BEACON_INTERVAL;
+use shared::protocol::PROTOCOL_VERSION;
+use tokio::task::block_in_place;
+use tokio::time::{sleep, Instant};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn loopback_servers_are_discovered() {
+    let mut discovery = LanDiscovery::bind().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let server = server::start(server::config::ServerConfig {
+        motd: "discover me".to_string(),
+        lan_discovery: true,
+        ..common::server_config(dir.path())
+    })
+    .await
+    .unwrap();
+    let port = server.local_addr().unwrap().port();
+
+    let deadline = Instant::now() + BEACON_INTERVAL * 3;
+    let found = loop {
+        discovery.poll();
+        let found = discovery.servers().find(|lan_server| {
+            lan_server
+                .announcement
+                .as_ref()
+                .is_some_and(|announcement| announcement.port == port)
+        });
+        if let Some(found) = found {
+            break found.clone();
+        }
+        assert!(
+            Instant::now() < deadline,
+            "the server was not discovered within {:?}",
+            BEACON_INTERVAL * 3
+        );
+        sleep(BEACON_INTERVAL / 10).await;
+    };
+    assert_eq!(found.version, PROTOCOL_VERSION);
+    assert!(found.compatibility().is_ok());
+    let announcement = found.announcement.unwrap();
+    assert_eq!(announcement.motd, "discover me");
+    assert_eq!(announcement.online, 0);
+
+    block_in_place(|| server.stop()).unwrap();
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::time::Duration;
+
+use shared::packet_ext::{AsyncPacketReadExt, AsyncPacketWriteExt};
+use shared::protocol::{Credentials, DisconnectReason, ReliablePacket};
+use tokio::task::block_in_place;
+use tokio::time::{sleep, Instant};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_username_is_online_once_at_most() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(dir.path()).await;
+
+    let (connection, mut send, mut recv) = common::connect_raw(&server).await;
+    common::join_raw(&mut send, &mut recv, "alice").await;
+
+    let (_second, mut second_send, mut second_recv) = common::connect_raw(&server).await;
+    second_send
+        .send_reliable(&ReliablePacket::Handshake {
+            username: "alice".to_string(),
+            credentials: Credentials::None,
+        })
+        .await
+        .unwrap();
+    let reason = common::disconnect_reason(&mut second_recv).await;
+    assert_eq!(reason, DisconnectReason::AlreadyOnline);
+
+    // the first session is left alone
+    send.send_reliable(&ReliablePacket::ChatSend {
+        text: "still here".to_string(),
+    })
+    .await
+    .unwrap();
+    loop {
+        match recv.recv_reliable().await.unwrap() {
+            ReliablePacket::ChatBroadcast { message } if message.text == "still here" => break,
+            ReliablePacket::Disconnect { reason } => panic!("alice was disconnected: {reason}"),
+            _ => {}
+        }
+    }
+
+    // and the name is free again once it leaves
+    connection.close(0u8.into(), b"bye");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let (_connection, mut send, mut recv) = common::connect_raw(&server).await;
+        send.send_reliable(&ReliablePacket::Handshake {
+            username: "alice".to_string(),
+            credentials: Credentials::None,
+        })
+        .await
+        .unwrap();
+        match recv.recv_reliable().await.unwrap() {
+            ReliablePacket::HandshakeRes { .. } => break,
+            ReliablePacket::Disconnect {
+                reason: DisconnectReason::AlreadyOnline,
+            } if Instant::now() < deadline => sleep(Duration::from_millis(20)).await,
+            packet => panic!("expected a handshake response, got {packet:?}"),
+        }
+    }
+
+    block_in_place(|| server.stop()).unwrap();
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use client::headless::{HeadlessClient, ScriptedMovement};
+use shared::protocol::DisconnectReason;
+use tokio::task::block_in_place;
+
+/// A handshake whose username claims to be 2^40 bytes long, framed.
+const LENGTH_BOMB: [u8; 14] = [0, 0, 0, 10, 0x00, 0xfd, 0, 0, 0, 0, 0, 1, 0, 0];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn undecodable_frames_disconnect_the_sender() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(dir.path()).await;
+
+    let (_connection, mut send, mut recv) = common::connect_raw(&server).await;
+    common::join_raw(&mut send, &mut recv, "mallory").await;
+    send.write_all(&[0, 0, 0, 3, 0xff, 0xff, 0xff])
+        .await
+        .unwrap();
+    let reason = common::disconnect_reason(&mut recv).await;
+    assert!(
+        matches!(reason, DisconnectReason::ProtocolViolation(_)),
+        "disconnected for {reason:?}"
+    );
+
+    let (_connection, mut send, mut recv) = common::connect_raw(&server).await;
+    send.write_all(&LENGTH_BOMB).await.unwrap();
+    let reason = common::disconnect_reason(&mut recv).await;
+    assert!(
+        matches!(reason, DisconnectReason::ProtocolViolation(_)),
+        "rejected for {reason:?}"
+    );
+
+    assert!(server.is_running());
+    let client = HeadlessClient::connect(
+        server.local_addr().unwrap(),
+        "player",
+        ScriptedMovement::new(),
+    )
+    .await
+    .expect("the server stopped accepting players");
+    client.disconnect();
+
+    block_in_place(|| server.stop()).unwrap();
+}
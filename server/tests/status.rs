@@ -0,0 +1,79 @@
+mod common;
+
+use std::collections::HashSet;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use client::known_hosts::KnownHosts;
+use client::networking::{ping, ServerAddress, ServerPing};
+use shared::protocol::{ServerStatus, MAX_STATUS_SAMPLE, PROTOCOL_VERSION};
+use tokio::task::block_in_place;
+use tokio::time::{sleep, Instant};
+
+const ICON: &[u8] = b"\x89PNG\r\n\x1a\nnot quite an image";
+
+async fn query_status(address: &ServerAddress) -> ServerStatus {
+    let known_hosts = Arc::new(Mutex::new(KnownHosts::in_memory()));
+    let ServerPing {
+        version, status, ..
+    } = ping(address, known_hosts).await.expect("the ping failed");
+    assert_eq!(version, PROTOCOL_VERSION);
+    status.expect("no status for a client of the same version")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_status_describes_the_running_server() {
+    let dir = tempfile::tempdir().unwrap();
+    let icon = dir.path().join("icon.png");
+    fs::write(&icon, ICON).unwrap();
+    let server = server::start(server::config::ServerConfig {
+        motd: "Status test".to_string(),
+        icon: Some(icon),
+        max_players: 20,
+        ..common::server_config(dir.path())
+    })
+    .await
+    .unwrap();
+    let address = ServerAddress::new("127.0.0.1", server.local_addr().unwrap().port());
+
+    let status = query_status(&address).await;
+    assert_eq!(status.motd, "Status test");
+    assert_eq!(status.online, 0);
+    assert_eq!(status.max_players, 20);
+    assert!(status.sample.is_empty());
+    assert_eq!(status.icon.as_deref(), Some(ICON));
+
+    let usernames: Vec<_> = (0..MAX_STATUS_SAMPLE + 2)
+        .map(|i| format!("player{i}"))
+        .collect();
+    let mut connections = Vec::new();
+    for username in &usernames {
+        let (connection, mut send, mut recv) = common::connect_raw(&server).await;
+        common::join_raw(&mut send, &mut recv, username).await;
+        connections.push((connection, send, recv));
+    }
+
+    let status = query_status(&address).await;
+    assert_eq!(status.online, usernames.len() as u32);
+    assert_eq!(status.sample.len(), MAX_STATUS_SAMPLE);
+    let sample: HashSet<_> = status.sample.iter().collect();
+    assert_eq!(sample.len(), MAX_STATUS_SAMPLE, "{:?}", status.sample);
+    assert!(sample.iter().all(|username| usernames.contains(username)));
+
+    // players leaving are no longer counted
+    let (connection, ..) = connections.pop().unwrap();
+    connection.close(0u8.into(), b"bye");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let status = query_status(&address).await;
+        if status.online == usernames.len() as u32 - 1 {
+            assert!(!status.sample.contains(usernames.last().unwrap()));
+            break;
+        }
+        assert!(Instant::now() < deadline, "the player is still counted");
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    block_in_place(|| server.stop()).unwrap();
+}
@@ -1,44 +1,39 @@
 use std::io::{Read, Write};
 
+use bincode::config::{Configuration, Limit, LittleEndian, Varint};
 use bincode::{Decode, Encode};
 
+use crate::error::NetworkError;
+use crate::packet_ext::MAX_FRAME_SIZE;
+
+/// How everything going over the wire is encoded. The limit keeps the length claimed by a
+/// string or list from allocating more than a frame can hold.
+pub const WIRE_CONFIG: Configuration<LittleEndian, Varint, Limit<MAX_FRAME_SIZE>> =
+    bincode::config::standard().with_limit::<MAX_FRAME_SIZE>();
+
 pub trait BincodeStreamReadExt {
-    fn read_decoded<D: Decode>(&mut self) -> D;
+    fn read_decoded<D: Decode>(&mut self) -> Result<D, NetworkError>;
 }
 
 impl<T> BincodeStreamReadExt for T
 where
     T: Read,
 {
-    fn read_decoded<D: Decode>(&mut self) -> D {
-        bincode::decode_from_std_read(self, bincode::config::standard()).unwrap()
+    fn read_decoded<D: Decode>(&mut self) -> Result<D, NetworkError> {
+        Ok(bincode::decode_from_std_read(self, WIRE_CONFIG)?)
     }
 }
 
 pub trait BincodeStreamWriteExt {
-    fn write_encoded<E: Encode>(&mut self, data: &E);
+    fn write_encoded<E: Encode>(&mut self, data: &E) -> Result<(), NetworkError>;
 }
 
 impl<T> BincodeStreamWriteExt for T
 where
     T: Write,
 {
-    fn write_encoded<E: Encode>(&mut self, data: &E) {
-        bincode::encode_into_std_write(data, self, bincode::config::standard()).unwrap();
-    }
-}
-
-trait ToBuf {
-    fn to_buf(self) -> Vec<u8>;
-}
-
-impl<D> ToBuf for &D
-where
-    D: Encode,
-{
-    fn to_buf(self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        bincode::encode_into_slice(self, &mut buf, bincode::config::standard()).unwrap();
-        buf
+    fn write_encoded<E: Encode>(&mut self, data: &E) -> Result<(), NetworkError> {
+        bincode::encode_into_std_write(data, self, WIRE_CONFIG)?;
+        Ok(())
     }
 }
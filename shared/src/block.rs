@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+pub type BlockId = u16;
+
+pub const AIR: BlockId = 0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlockFace {
+    East,
+    West,
+    Up,
+    Down,
+    South,
+    North,
+}
+
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::East,
+        BlockFace::West,
+        BlockFace::Up,
+        BlockFace::Down,
+        BlockFace::South,
+        BlockFace::North,
+    ];
+
+    pub fn normal(self) -> [i32; 3] {
+        match self {
+            BlockFace::East => [1, 0, 0],
+            BlockFace::West => [-1, 0, 0],
+            BlockFace::Up => [0, 1, 0],
+            BlockFace::Down => [0, -1, 0],
+            BlockFace::South => [0, 0, 1],
+            BlockFace::North => [0, 0, -1],
+        }
+    }
+
+    pub fn opposite(self) -> BlockFace {
+        match self {
+            BlockFace::East => BlockFace::West,
+            BlockFace::West => BlockFace::East,
+            BlockFace::Up => BlockFace::Down,
+            BlockFace::Down => BlockFace::Up,
+            BlockFace::South => BlockFace::North,
+            BlockFace::North => BlockFace::South,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    name: &'static str,
+    opaque: bool,
+    textures: [&'static str; 6],
+}
+
+impl Block {
+    pub fn new(name: &'static str, opaque: bool, textures: [&'static str; 6]) -> Self {
+        Self {
+            name,
+            opaque,
+            textures,
+        }
+    }
+
+    pub fn uniform(name: &'static str, opaque: bool, texture: &'static str) -> Self {
+        Self::new(name, opaque, [texture; 6])
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.opaque
+    }
+
+    pub fn texture(&self, face: BlockFace) -> &'static str {
+        self.textures[face as usize]
+    }
+
+    pub fn textures(&self) -> &[&'static str; 6] {
+        &self.textures
+    }
+}
+
+pub struct BlockRegistry {
+    blocks: Vec<Block>,
+    ids: HashMap<&'static str, BlockId>,
+}
+
+impl BlockRegistry {
+    pub fn empty() -> Self {
+        let mut registry = Self {
+            blocks: Vec::new(),
+            ids: HashMap::new(),
+        };
+        registry.register(Block::uniform("air", false, ""));
+        registry
+    }
+
+    pub fn register(&mut self, block: Block) -> BlockId {
+        let id = self.blocks.len() as BlockId;
+        self.ids.insert(block.name, id);
+        self.blocks.push(block);
+        id
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&Block> {
+        self.blocks.get(id as usize)
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn is_opaque(&self, id: BlockId) -> bool {
+        self.get(id).map(Block::is_opaque).unwrap_or(false)
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &Block)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(id, block)| (id as BlockId, block))
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Block::uniform("stone", true, "stone"));
+        registry.register(Block::uniform("dirt", true, "dirt"));
+        registry.register(Block::new(
+            "grass",
+            true,
+            [
+                "grass_side",
+                "grass_side",
+                "grass_top",
+                "dirt",
+                "grass_side",
+                "grass_side",
+            ],
+        ));
+        registry.register(Block::uniform("sand", true, "sand"));
+        registry.register(Block::uniform("cobblestone", true, "cobblestone"));
+        registry.register(Block::uniform("planks", true, "planks"));
+        registry.register(Block::new(
+            "log",
+            true,
+            [
+                "log_side", "log_side", "log_top", "log_top", "log_side", "log_side",
+            ],
+        ));
+        registry.register(Block::uniform("glass", false, "glass"));
+        registry.register(Block::uniform("leaves", false, "leaves"));
+        registry
+    }
+}
@@ -0,0 +1,85 @@
+use crate::block::{BlockFace, BlockId, AIR};
+
+pub const SECTION_SIZE: usize = 16;
+pub const SECTION_AREA: usize = SECTION_SIZE * SECTION_SIZE;
+pub const SECTION_VOLUME: usize = SECTION_AREA * SECTION_SIZE;
+
+pub const MAX_LIGHT: u8 = 15;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SectionPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl SectionPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn of_block(x: i32, y: i32, z: i32) -> Self {
+        let size = SECTION_SIZE as i32;
+        Self::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size))
+    }
+
+    pub fn offset(self, face: BlockFace) -> Self {
+        let [dx, dy, dz] = face.normal();
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    pub fn origin(self) -> [i32; 3] {
+        let size = SECTION_SIZE as i32;
+        [self.x * size, self.y * size, self.z * size]
+    }
+}
+
+#[derive(Clone)]
+pub struct ChunkSection {
+    blocks: Box<[BlockId; SECTION_VOLUME]>,
+    light: Box<[u8; SECTION_VOLUME]>,
+}
+
+impl ChunkSection {
+    pub fn new() -> Self {
+        Self::filled(AIR)
+    }
+
+    pub fn filled(block: BlockId) -> Self {
+        Self {
+            blocks: Box::new([block; SECTION_VOLUME]),
+            light: Box::new([MAX_LIGHT; SECTION_VOLUME]),
+        }
+    }
+
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        debug_assert!(x < SECTION_SIZE && y < SECTION_SIZE && z < SECTION_SIZE);
+        (y * SECTION_SIZE + z) * SECTION_SIZE + x
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockId {
+        self.blocks[Self::index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        self.blocks[Self::index(x, y, z)] = block;
+    }
+
+    pub fn light(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.light[Self::index(x, y, z)]
+    }
+
+    pub fn set_light(&mut self, x: usize, y: usize, z: usize, light: u8) {
+        self.light[Self::index(x, y, z)] = light.min(MAX_LIGHT);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|block| *block == AIR)
+    }
+}
+
+impl Default for ChunkSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use bincode::error::{DecodeError, EncodeError};
+
+/// What can go wrong moving packets between the client and the server.
+#[derive(Debug)]
+pub enum NetworkError {
+    /// The bytes received are not a valid packet.
+    Decode(DecodeError),
+    /// A packet could not be encoded, which only a bug in its type can cause.
+    Encode(EncodeError),
+    /// The length prefix of a frame exceeds `packet_ext::MAX_FRAME_SIZE`.
+    FrameTooLarge(usize),
+    /// The connection failed or was closed while reading or writing.
+    ConnectionLost(io::Error),
+    /// A valid packet, but one the peer should not have sent.
+    ProtocolViolation(String),
+}
+
+impl NetworkError {
+    /// Whether the peer sent something it should not have, rather than going away.
+    pub fn is_peer_fault(&self) -> bool {
+        matches!(
+            self,
+            NetworkError::Decode(_)
+                | NetworkError::FrameTooLarge(_)
+                | NetworkError::ProtocolViolation(_)
+        )
+    }
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Decode(err) => write!(f, "invalid packet: {err}"),
+            NetworkError::Encode(err) => write!(f, "failed to encode a packet: {err}"),
+            NetworkError::FrameTooLarge(size) => write!(
+                f,
+                "frame of {size} bytes exceeds {}",
+                crate::packet_ext::MAX_FRAME_SIZE
+            ),
+            NetworkError::ConnectionLost(err) => write!(f, "connection lost: {err}"),
+            NetworkError::ProtocolViolation(reason) => write!(f, "protocol violation: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NetworkError::Decode(err) => Some(err),
+            NetworkError::Encode(err) => Some(err),
+            NetworkError::ConnectionLost(err) => Some(err),
+            NetworkError::FrameTooLarge(_) | NetworkError::ProtocolViolation(_) => None,
+        }
+    }
+}
+
+impl From<DecodeError> for NetworkError {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            // the stream ended or failed, the bytes read so far may well be fine
+            DecodeError::Io { inner, .. } => NetworkError::ConnectionLost(inner),
+            err => NetworkError::Decode(err),
+        }
+    }
+}
+
+impl From<EncodeError> for NetworkError {
+    fn from(err: EncodeError) -> Self {
+        match err {
+            EncodeError::Io { inner, .. } => NetworkError::ConnectionLost(inner),
+            err => NetworkError::Encode(err),
+        }
+    }
+}
+
+impl From<io::Error> for NetworkError {
+    fn from(err: io::Error) -> Self {
+        NetworkError::ConnectionLost(err)
+    }
+}
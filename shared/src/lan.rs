@@ -0,0 +1,46 @@
+use std::net::Ipv4Addr;
+
+use crate::bincode_ext::{BincodeStreamWriteExt, WIRE_CONFIG};
+use crate::error::NetworkError;
+use crate::protocol::ProtocolHello;
+
+/// Multicast group servers announce themselves to, reaching clients on the same network.
+pub const LAN_DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 66, 77);
+
+pub const LAN_DISCOVERY_PORT: u16 = 4446;
+
+/// A server telling the local network it is there. Sent after a `ProtocolHello`, so clients
+/// of other versions still learn the server exists and which version it speaks.
+#[derive(bincode::Decode, bincode::Encode, Clone, Debug, PartialEq, Eq)]
+pub struct LanAnnouncement {
+    pub motd: String,
+    /// Of the game endpoint, the address being the one the datagram came from.
+    pub port: u16,
+    pub online: u32,
+    pub max_players: u32,
+}
+
+impl LanAnnouncement {
+    pub fn to_datagram(&self) -> Result<Vec<u8>, NetworkError> {
+        let mut buf = Vec::new();
+        buf.write_encoded(&ProtocolHello::CURRENT)?;
+        buf.write_encoded(self)?;
+        Ok(buf)
+    }
+
+    /// Reads the hello of a datagram and, if it speaks our protocol version, the announcement.
+    /// Returns `None` for datagrams that are not rbmp announcements.
+    pub fn from_datagram(datagram: &[u8]) -> Option<(ProtocolHello, Option<Self>)> {
+        let (hello, read) =
+            bincode::decode_from_slice::<ProtocolHello, _>(datagram, WIRE_CONFIG).ok()?;
+        if !hello.is_rbmp() {
+            return None;
+        }
+        if hello != ProtocolHello::CURRENT {
+            return Some((hello, None));
+        }
+
+        let (announcement, _) = bincode::decode_from_slice(&datagram[read..], WIRE_CONFIG).ok()?;
+        Some((hello, Some(announcement)))
+    }
+}
@@ -1,7 +1,12 @@
 pub mod bincode_ext;
+pub mod block;
+pub mod chunk;
+pub mod error;
+pub mod lan;
+pub mod movement;
 pub mod packet_ext;
 pub mod protocol;
+pub mod toml_file;
 pub mod tracing;
 
-pub const TCP_ADDRESS: &str = "127.0.0.1:8080";
-pub const UDP_ADDRESS: &str = "127.0.0.1:8081";
+pub const DEFAULT_PORT: u16 = 8080;
@@ -0,0 +1,66 @@
+/// Blocks per second a player flies at, whichever way they go.
+pub const MOVE_SPEED: f32 = 5.0;
+
+/// Where a player at `position` ends up after holding `directions` (left, forward, right,
+/// backward, down and up) for `dt` seconds, facing `yaw` degrees. A yaw of zero faces -Z and
+/// grows turning right, as the client's camera does.
+pub fn step(position: [f32; 3], directions: [bool; 6], yaw: f32, dt: f32) -> [f32; 3] {
+    let [left, forward, right, backward, down, up] = directions.map(|held| held as i32 as f32);
+    let (sin_yaw, cos_yaw) = yaw.to_radians().sin_cos();
+    let ahead = forward - backward;
+    let aside = right - left;
+    let direction = [
+        ahead * sin_yaw + aside * cos_yaw,
+        up - down,
+        -ahead * cos_yaw + aside * sin_yaw,
+    ];
+
+    let length = direction.iter().map(|axis| axis * axis).sum::<f32>().sqrt();
+    if length == 0.0 {
+        return position;
+    }
+    let distance = MOVE_SPEED * dt / length;
+    [0, 1, 2].map(|axis| position[axis] + direction[axis] * distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORWARD: [bool; 6] = [false, true, false, false, false, false];
+
+    fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+        for axis in 0..3 {
+            assert!(
+                (actual[axis] - expected[axis]).abs() < 1e-5,
+                "{actual:?} is not {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn players_move_where_they_face() {
+        assert_near(step([0.0; 3], FORWARD, 0.0, 1.0), [0.0, 0.0, -MOVE_SPEED]);
+        assert_near(step([0.0; 3], FORWARD, 90.0, 1.0), [MOVE_SPEED, 0.0, 0.0]);
+        let right = [false, false, true, false, false, false];
+        assert_near(step([1.0, 2.0, 3.0], right, 0.0, 0.5), [3.5, 2.0, 3.0]);
+        let up = [false, false, false, false, false, true];
+        assert_near(step([0.0; 3], up, 45.0, 0.2), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn nothing_held_or_opposites_stay_put() {
+        assert_eq!(
+            step([1.0, 2.0, 3.0], [false; 6], 30.0, 1.0),
+            [1.0, 2.0, 3.0]
+        );
+        assert_eq!(step([1.0, 2.0, 3.0], [true; 6], 30.0, 1.0), [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn diagonals_are_not_faster() {
+        let everything = [true, true, false, false, false, true];
+        let [x, y, z] = step([0.0; 3], everything, 10.0, 1.0);
+        assert!(((x * x + y * y + z * z).sqrt() - MOVE_SPEED).abs() < 1e-4);
+    }
+}
@@ -1,91 +1,277 @@
 use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpStream, UdpSocket};
 
-use crate::bincode_ext::{BincodeStreamReadExt, BincodeStreamWriteExt};
+use bincode::{Decode, Encode};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::bincode_ext::{BincodeStreamWriteExt, WIRE_CONFIG};
+use crate::error::NetworkError;
 use crate::protocol::{ReliablePacket, UnreliablePacket};
 
+/// Upper bound for the length prefix of a framed packet, so a corrupt prefix cannot make the
+/// reader allocate gigabytes.
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Largest payload of a UDP datagram.
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Reads framed reliable packets from a TCP stream.
 pub trait PacketStreamReadExt {
-    fn read_reliable(&mut self) -> ReliablePacket;
-    fn read_unreliable(&mut self) -> UnreliablePacket;
-    fn read_unreliable_from(&mut self) -> (UnreliablePacket, SocketAddr);
+    fn read_reliable(&mut self) -> Result<ReliablePacket, NetworkError>;
 }
 
 impl PacketStreamReadExt for TcpStream {
-    fn read_reliable(&mut self) -> ReliablePacket {
+    fn read_reliable(&mut self) -> Result<ReliablePacket, NetworkError> {
         let mut buf = [0u8; 4];
-        self.read_exact(&mut buf).unwrap();
-        let size = u32::from_be_bytes(buf);
+        self.read_exact(&mut buf)?;
+        let size = frame_size(buf)?;
 
-        let mut buf = vec![0u8; size as usize];
-        self.read_exact(&mut buf).unwrap();
-        (&buf[..]).read_decoded()
+        let mut buf = vec![0u8; size];
+        self.read_exact(&mut buf)?;
+        decode_packet(&buf)
     }
+}
+
+/// Writes framed reliable packets to a TCP stream.
+pub trait PacketStreamWriteExt {
+    fn write_reliable(&mut self, packet: &ReliablePacket) -> Result<(), NetworkError>;
+}
+
+impl PacketStreamWriteExt for TcpStream {
+    fn write_reliable(&mut self, packet: &ReliablePacket) -> Result<(), NetworkError> {
+        self.write_all(&frame(packet)?)?;
+        Ok(())
+    }
+}
+
+/// Sends and receives unreliable packets, one framed packet per datagram.
+pub trait PacketDatagramExt {
+    fn read_unreliable(&mut self) -> Result<UnreliablePacket, NetworkError>;
+    fn read_unreliable_from(&mut self) -> Result<(UnreliablePacket, SocketAddr), NetworkError>;
+    fn write_unreliable(&mut self, packet: &UnreliablePacket) -> Result<(), NetworkError>;
+}
 
-    fn read_unreliable(&mut self) -> UnreliablePacket {
-        panic!("unreliable packets should not be received through TCP.")
+impl PacketDatagramExt for UdpSocket {
+    fn read_unreliable(&mut self) -> Result<UnreliablePacket, NetworkError> {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        let read = self.recv(&mut buf)?;
+        decode_packet(unframe_datagram(&buf[..read])?)
     }
 
-    fn read_unreliable_from(&mut self) -> (UnreliablePacket, SocketAddr) {
-        panic!("unreliable packets should not be received through TCP.")
+    fn read_unreliable_from(&mut self) -> Result<(UnreliablePacket, SocketAddr), NetworkError> {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        let (read, addr) = self.recv_from(&mut buf)?;
+        Ok((decode_packet(unframe_datagram(&buf[..read])?)?, addr))
     }
+
+    fn write_unreliable(&mut self, packet: &UnreliablePacket) -> Result<(), NetworkError> {
+        self.send(&frame(packet)?)?;
+        Ok(())
+    }
+}
+
+/// Encodes `packet` after a big endian `u32` of its length.
+fn frame<E: Encode>(packet: &E) -> Result<Vec<u8>, NetworkError> {
+    let mut buf = vec![0u8; 4];
+    buf.write_encoded(packet)?;
+    let size = (buf.len() - 4) as u32;
+    buf[..4].copy_from_slice(&size.to_be_bytes());
+    Ok(buf)
 }
 
-impl PacketStreamReadExt for UdpSocket {
-    fn read_reliable(&mut self) -> ReliablePacket {
-        panic!("reliable packets should not be received through UDP.")
+/// The length of a frame from its prefix, refusing lengths above `MAX_FRAME_SIZE`.
+fn frame_size(prefix: [u8; 4]) -> Result<usize, NetworkError> {
+    let size = u32::from_be_bytes(prefix) as usize;
+    if size > MAX_FRAME_SIZE {
+        return Err(NetworkError::FrameTooLarge(size));
     }
+    Ok(size)
+}
 
-    fn read_unreliable(&mut self) -> UnreliablePacket {
-        let mut buf = [0u8; 4];
-        self.recv(&mut buf).unwrap();
-        let size = u32::from_be_bytes(buf);
+/// The packet of a datagram holding exactly one frame.
+fn unframe_datagram(datagram: &[u8]) -> Result<&[u8], NetworkError> {
+    let Some((prefix, packet)) = datagram.split_first_chunk::<4>() else {
+        return Err(NetworkError::ProtocolViolation(format!(
+            "datagram of {} bytes is shorter than a length prefix",
+            datagram.len()
+        )));
+    };
+    let size = frame_size(*prefix)?;
+    if size != packet.len() {
+        return Err(NetworkError::ProtocolViolation(format!(
+            "frame of {size} bytes in a datagram of {}",
+            datagram.len()
+        )));
+    }
+    Ok(packet)
+}
 
-        let mut buf = Vec::new();
-        self.recv(&mut buf).unwrap();
-        (&buf[..]).read_decoded()
+/// Reads length prefixed packets from an async stream, such as a quinn `RecvStream`.
+#[allow(async_fn_in_trait)]
+pub trait AsyncPacketReadExt {
+    async fn recv_packet<D: Decode>(&mut self) -> Result<D, NetworkError>;
+
+    async fn recv_reliable(&mut self) -> Result<ReliablePacket, NetworkError> {
+        self.recv_packet().await
     }
+}
 
-    fn read_unreliable_from(&mut self) -> (UnreliablePacket, SocketAddr) {
-        let mut buf = [0u8; 4];
-        let (_, addr) = self.recv_from(&mut buf).unwrap();
-        let size = u32::from_be_bytes(buf);
+impl<T> AsyncPacketReadExt for T
+where
+    T: AsyncRead + Unpin,
+{
+    async fn recv_packet<D: Decode>(&mut self) -> Result<D, NetworkError> {
+        let mut prefix = [0u8; 4];
+        self.read_exact(&mut prefix).await?;
+        let size = frame_size(prefix)?;
 
-        let mut buf = Vec::new();
-        self.recv(&mut buf).unwrap();
-        ((&buf[..]).read_decoded(), addr)
+        let mut buf = vec![0u8; size];
+        self.read_exact(&mut buf).await?;
+        decode_packet(&buf)
     }
 }
 
-pub trait PacketStreamWriteExt {
-    fn write_reliable(&mut self, packet: &ReliablePacket);
-    fn write_unreliable(&mut self, packet: &UnreliablePacket);
+/// Writes length prefixed packets to an async stream, such as a quinn `SendStream`.
+#[allow(async_fn_in_trait)]
+pub trait AsyncPacketWriteExt {
+    async fn send_packet<E: Encode>(&mut self, packet: &E) -> Result<(), NetworkError>;
+
+    async fn send_reliable(&mut self, packet: &ReliablePacket) -> Result<(), NetworkError> {
+        self.send_packet(packet).await
+    }
 }
 
-impl PacketStreamWriteExt for TcpStream {
-    fn write_reliable(&mut self, packet: &ReliablePacket) {
-        let mut buf = Vec::new();
-        buf.write_encoded(packet);
-        self.write_all(buf.len().to_be_bytes().as_slice()).unwrap();
-        self.write_all(&buf[..]).unwrap();
+impl<T> AsyncPacketWriteExt for T
+where
+    T: AsyncWrite + Unpin,
+{
+    async fn send_packet<E: Encode>(&mut self, packet: &E) -> Result<(), NetworkError> {
+        self.write_all(&frame(packet)?).await?;
+        Ok(())
     }
+}
 
-    fn write_unreliable(&mut self, _packet: &UnreliablePacket) {
-        panic!("unreliable packets should not be sent through TCP.")
+/// Decodes a whole packet from `buf`, such as the payload of a frame or a datagram.
+pub fn decode_packet<D: Decode>(buf: &[u8]) -> Result<D, NetworkError> {
+    let (packet, read) = bincode::decode_from_slice(buf, WIRE_CONFIG)?;
+    if read < buf.len() {
+        return Err(NetworkError::ProtocolViolation(format!(
+            "{} bytes left after the packet",
+            buf.len() - read
+        )));
     }
+    Ok(packet)
 }
 
-impl PacketStreamWriteExt for UdpSocket {
-    fn write_reliable(&mut self, _packet: &ReliablePacket) {
-        panic!("reliable packets should not be sent through UDP.")
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::protocol::{Credentials, ServerStatus};
+
+    /// A handshake whose username claims to be 2^40 bytes long.
+    const LENGTH_BOMB: [u8; 10] = [0x00, 0xfd, 0, 0, 0, 0, 0, 1, 0, 0];
+
+    fn framed(payload: &[u8]) -> Vec<u8> {
+        let mut buf = (payload.len() as u32).to_be_bytes().to_vec();
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn random_bytes_do_not_decode() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10_000 {
+            let mut buf = vec![0u8; rng.gen_range(1..256)];
+            rng.fill(&mut buf[..]);
+            assert!(
+                decode_packet::<ReliablePacket>(&buf).is_err(),
+                "{buf:?} decoded"
+            );
+        }
     }
 
-    fn write_unreliable(&mut self, packet: &UnreliablePacket) {
+    #[test]
+    fn length_bombs_hit_the_limit() {
+        assert!(matches!(
+            decode_packet::<ReliablePacket>(&LENGTH_BOMB),
+            Err(NetworkError::Decode(_))
+        ));
+        // a status listing 2^40 players
+        let status = [0x00, 0x00, 0x00, 0xfd, 0, 0, 0, 0, 0, 1, 0, 0];
+        assert!(matches!(
+            decode_packet::<ServerStatus>(&status),
+            Err(NetworkError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn trailing_bytes_are_a_violation() {
+        let mut buf =
+            bincode::encode_to_vec(ReliablePacket::HandshakeRes { player_id: 1 }, WIRE_CONFIG)
+                .unwrap();
+        buf.push(0);
+        assert!(matches!(
+            decode_packet::<ReliablePacket>(&buf),
+            Err(NetworkError::ProtocolViolation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn packets_survive_a_round_trip() {
+        let packet = ReliablePacket::Handshake {
+            username: "player".to_string(),
+            credentials: Credentials::None,
+        };
         let mut buf = Vec::new();
-        buf.write_encoded(packet);
-        buf.reserve(4);
-        let mut v = buf.split_off(0);
-        buf.extend_from_slice(buf.len().to_be_bytes().as_slice());
-        buf.append(&mut v);
-        self.send(&buf[..]).unwrap();
+        buf.send_reliable(&packet).await.unwrap();
+
+        let mut stream = &buf[..];
+        let received = stream.recv_reliable().await.unwrap();
+        assert!(matches!(
+            received,
+            ReliablePacket::Handshake { username, .. } if username == "player"
+        ));
+    }
+
+    #[tokio::test]
+    async fn garbage_frames_are_errors() {
+        let mut stream = &framed(&LENGTH_BOMB)[..];
+        assert!(matches!(
+            stream.recv_reliable().await,
+            Err(NetworkError::Decode(_))
+        ));
+
+        let mut stream = &framed(&[0xff, 0xff, 0xff])[..];
+        assert!(matches!(
+            stream.recv_reliable().await,
+            Err(NetworkError::Decode(_))
+        ));
+
+        let mut stream = &[0xff, 0xff, 0xff, 0xff, 0x00][..];
+        assert!(matches!(
+            stream.recv_reliable().await,
+            Err(NetworkError::FrameTooLarge(_))
+        ));
+
+        // the stream ends halfway through the frame
+        let mut stream = &framed(&LENGTH_BOMB)[..6];
+        assert!(matches!(
+            stream.recv_reliable().await,
+            Err(NetworkError::ConnectionLost(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn random_frames_are_errors() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1_000 {
+            let mut payload = vec![0u8; rng.gen_range(1..256)];
+            rng.fill(&mut payload[..]);
+            let mut stream = &framed(&payload)[..];
+            assert!(stream.recv_reliable().await.is_err(), "{payload:?} decoded");
+        }
     }
 }
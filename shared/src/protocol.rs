@@ -1,29 +1,331 @@
+use std::fmt::{Display, Formatter};
+
 use crate::bincode_ext::BincodeStreamWriteExt;
+use crate::error::NetworkError;
 
 pub enum PacketAction {
     Reliable(ReliablePacket),
     Unreliable(UnreliablePacket),
+    /// Sends `ReliablePacket::Disconnect` with the reason, then closes the connection.
+    Disconnect(DisconnectReason),
+}
+
+/// Bumped whenever the wire encoding of a packet changes, so mismatched clients and servers
+/// turn each other away instead of misreading packets. `check_wire_format` catches encoding
+/// changes that forgot to.
+pub const PROTOCOL_VERSION: u32 = 10;
+
+/// Opens every `ProtocolHello`, telling rbmp peers apart from anything else reaching the port.
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"RBMP";
+
+/// The first frame each side sends on a new connection, before any `ReliablePacket`. Its
+/// encoding must never change, or peers of different versions could not even compare versions.
+#[derive(bincode::Decode, bincode::Encode, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolHello {
+    pub magic: [u8; 4],
+    pub version: u32,
+}
+
+impl ProtocolHello {
+    pub const CURRENT: Self = Self {
+        magic: PROTOCOL_MAGIC,
+        version: PROTOCOL_VERSION,
+    };
+
+    pub fn is_rbmp(&self) -> bool {
+        self.magic == PROTOCOL_MAGIC
+    }
+}
+
+/// ALPN of connections joining the game.
+pub const GAME_ALPN: &[u8] = b"rbmp";
+
+/// ALPN of connections only asking for the `ServerStatus`, without logging in. The client sends
+/// a `ProtocolHello`, the server answers with its own and, if the versions match, the status.
+pub const STATUS_ALPN: &[u8] = b"rbmp-status";
+
+/// QUIC application close code of the connections of a server shutting down, after they were
+/// sent `DisconnectReason::ServerStopped`.
+pub const CLOSE_SERVER_STOPPED: u32 = 1;
+
+/// Most player names listed in a `ServerStatus`.
+pub const MAX_STATUS_SAMPLE: usize = 12;
+
+/// Largest server icon, a PNG, sent in a `ServerStatus`.
+pub const MAX_ICON_SIZE: usize = 64 * 1024;
+
+/// What a server tells anyone asking, logged in or not.
+#[derive(bincode::Decode, bincode::Encode, Clone, Debug, PartialEq, Eq)]
+pub struct ServerStatus {
+    pub motd: String,
+    pub online: u32,
+    pub max_players: u32,
+    /// Some of the online players, at most `MAX_STATUS_SAMPLE`.
+    pub sample: Vec<String>,
+    /// PNG image.
+    pub icon: Option<Vec<u8>>,
+}
+
+/// A client and a server speaking different protocol versions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VersionMismatch {
+    pub client: u32,
+    pub server: u32,
+}
+
+impl VersionMismatch {
+    pub fn check(client: u32, server: u32) -> Result<(), Self> {
+        if client == server {
+            Ok(())
+        } else {
+            Err(Self { client, server })
+        }
+    }
+}
+
+impl Display for VersionMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let outdated = if self.client < self.server {
+            "client"
+        } else {
+            "server"
+        };
+        write!(
+            f,
+            "outdated {outdated}: the client speaks protocol version {} and the server {}",
+            self.client, self.server
+        )
+    }
+}
+
+pub const MAX_USERNAME_LENGTH: usize = 16;
+
+/// Usernames are 1 to 16 letters, digits or underscores.
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(format!(
+            "username must be 1 to {MAX_USERNAME_LENGTH} characters long"
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err("username may only contain letters, digits and underscores".to_string());
+    }
+    Ok(())
+}
+
+/// Longest chat message, in characters. Longer ones are cut.
+pub const MAX_CHAT_LENGTH: usize = 256;
+
+/// Makes `text` fit for a chat message: control characters such as line breaks become spaces,
+/// surrounding whitespace is trimmed and the rest is cut at `MAX_CHAT_LENGTH`. Returns `None`
+/// when nothing is left.
+pub fn sanitize_chat(text: &str) -> Option<String> {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let text: String = text.trim().chars().take(MAX_CHAT_LENGTH).collect();
+    let text = text.trim_end();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[derive(bincode::Decode, bincode::Encode, Clone, Debug, PartialEq, Eq)]
+pub enum ChatSender {
+    Player(String),
+    /// The server itself, e.g. announcing players joining.
+    System,
+}
+
+#[derive(bincode::Decode, bincode::Encode, Clone, Debug, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub sender: ChatSender,
+    pub text: String,
+    /// Milliseconds since the Unix epoch, when the server received it.
+    pub timestamp: u64,
+}
+
+impl Display for ChatMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let minutes = self.timestamp / 60_000;
+        write!(f, "[{:02}:{:02}] ", minutes / 60 % 24, minutes % 60)?;
+        match &self.sender {
+            ChatSender::Player(username) => write!(f, "<{username}> {}", self.text),
+            ChatSender::System => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// Proof of identity sent along with the username, checked by the server's auth provider.
+#[derive(bincode::Decode, bincode::Encode, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    None,
+    Password(String),
+    Token(String),
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::None => write!(f, "None"),
+            Credentials::Password(_) => write!(f, "Password(..)"),
+            Credentials::Token(_) => write!(f, "Token(..)"),
+        }
+    }
+}
+
+#[derive(bincode::Decode, bincode::Encode, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// The server requires a password or token and none was sent.
+    MissingCredentials,
+    /// The account does not exist or the password is wrong, deliberately not telling which.
+    InvalidCredentials,
+    InvalidToken,
+    ExpiredToken,
+    /// The token was issued for another username.
+    UsernameMismatch,
+    /// The server could not check the credentials.
+    Unavailable,
+}
+
+impl Display for AuthFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthFailure::MissingCredentials => write!(f, "this server requires a login"),
+            AuthFailure::InvalidCredentials => write!(f, "wrong username or password"),
+            AuthFailure::InvalidToken => write!(f, "invalid token"),
+            AuthFailure::ExpiredToken => write!(f, "the token expired"),
+            AuthFailure::UsernameMismatch => write!(f, "the token belongs to another username"),
+            AuthFailure::Unavailable => write!(f, "authentication is unavailable"),
+        }
+    }
+}
+
+/// Why the server closed a connection, sent right before closing it.
+#[derive(bincode::Decode, bincode::Encode, Clone, Debug, PartialEq)]
+pub enum DisconnectReason {
+    ServerFull,
+    InvalidUsername,
+    AuthFailed(AuthFailure),
+    Kicked(String),
+    ServerStopped,
+    /// `expires` is the Unix time, in seconds, the ban is lifted at, if ever.
+    Banned {
+        reason: String,
+        expires: Option<u64>,
+    },
+    NotWhitelisted,
+    /// A player with the same username is already on the server.
+    AlreadyOnline,
+    /// The client sent something it should not have, such as a packet that does not decode.
+    ProtocolViolation(String),
+}
+
+/// `YYYY-MM-DD HH:MM UTC` of the Unix time `secs`.
+fn format_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let minutes = secs % 86_400 / 60;
+
+    // days to the civil date, counting 400-year eras from 0000-03-01
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::ServerFull => write!(f, "the server is full"),
+            DisconnectReason::InvalidUsername => write!(f, "invalid username"),
+            DisconnectReason::AuthFailed(failure) => write!(f, "authentication failed: {failure}"),
+            DisconnectReason::Kicked(reason) => write!(f, "kicked: {reason}"),
+            DisconnectReason::ServerStopped => write!(f, "the server stopped"),
+            DisconnectReason::Banned { reason, expires } => {
+                write!(f, "banned: {reason}")?;
+                match expires {
+                    Some(expires) => write!(f, " (until {})", format_utc(*expires)),
+                    None => write!(f, " (permanently)"),
+                }
+            }
+            DisconnectReason::NotWhitelisted => write!(f, "you are not whitelisted on this server"),
+            DisconnectReason::AlreadyOnline => write!(f, "you are already on this server"),
+            DisconnectReason::ProtocolViolation(reason) => {
+                write!(f, "protocol violation: {reason}")
+            }
+        }
+    }
 }
 
 #[derive(bincode::Decode, bincode::Encode, Clone, Debug)]
 pub enum ReliablePacket {
     Handshake {
-        udp_port: u16,
+        username: String,
+        credentials: Credentials,
     },
     HandshakeRes {
         player_id: u32,
     },
     MovementInput {
         directions: [bool; 6],
+        /// Yaw and pitch in degrees.
         rotations: [f32; 2],
+        position: [f32; 3],
+    },
+    Disconnect {
+        reason: DisconnectReason,
+    },
+    /// A message the player typed, broadcast by the server if it lets it through.
+    ChatSend {
+        text: String,
+    },
+    ChatBroadcast {
+        message: ChatMessage,
+    },
+    /// A command typed by the player, without the leading `/`. Its output comes back as chat.
+    RunCommand {
+        input: String,
+    },
+    /// Asks for ways to complete the last word of a partially typed command.
+    CompleteCommand {
+        id: u32,
+        input: String,
+    },
+    /// Answers the `CompleteCommand` of the same id. Each suggestion replaces the input from
+    /// the character `start` on.
+    CommandSuggestions {
+        id: u32,
+        start: u32,
+        suggestions: Vec<String>,
+    },
+    Teleport {
+        position: [f32; 3],
     },
 }
 
 impl ReliablePacket {
-    pub fn to_buf(self) -> Vec<u8> {
+    pub fn to_buf(self) -> Result<Vec<u8>, NetworkError> {
         let mut buf = Vec::new();
-        buf.write_encoded(&self);
-        buf
+        buf.write_encoded(&self)?;
+        Ok(buf)
     }
 }
 
@@ -31,9 +333,224 @@ impl ReliablePacket {
 pub enum UnreliablePacket {}
 
 impl UnreliablePacket {
-    pub fn to_buf(self) -> Vec<u8> {
+    pub fn to_buf(self) -> Result<Vec<u8>, NetworkError> {
         let mut buf = Vec::new();
-        buf.write_encoded(&self);
-        buf
+        buf.write_encoded(&self)?;
+        Ok(buf)
+    }
+}
+
+/// The wire fingerprint of every protocol version, oldest first. Append an entry when bumping
+/// `PROTOCOL_VERSION`; never edit an existing one.
+const WIRE_FINGERPRINTS: &[(u32, u64)] = &[
+    (1, 0x4bc80a9fe2b01f15),
+    (2, 0x91fb6861317424c3),
+    (3, 0x265c8cb0582b31cc),
+    (4, 0x70b8edef594a1675),
+    (5, 0x12d7885b50069eaf),
+    (6, 0x68cb2a752c334f97),
+    (7, 0x93897347cf71739c),
+    (8, 0x315b93c228be6bd0),
+    (9, 0x576f5e5d778d4099),
+    (10, 0x6615d4733980e401),
+];
+
+const _: () = {
+    let mut i = 1;
+    while i < WIRE_FINGERPRINTS.len() {
+        assert!(WIRE_FINGERPRINTS[i - 1].0 < WIRE_FINGERPRINTS[i].0);
+        i += 1;
+    }
+    assert!(
+        WIRE_FINGERPRINTS[WIRE_FINGERPRINTS.len() - 1].0 == PROTOCOL_VERSION,
+        "PROTOCOL_VERSION has no entry in WIRE_FINGERPRINTS"
+    );
+};
+
+/// Encodes one packet of every kind and compares the result with the fingerprint pinned for
+/// `PROTOCOL_VERSION`, failing if the encoding changed without a version bump.
+#[cfg(test)]
+fn check_wire_format() -> Result<(), String> {
+    let (version, expected) = WIRE_FINGERPRINTS[WIRE_FINGERPRINTS.len() - 1];
+    let actual =
+        wire_fingerprint().map_err(|err| format!("failed to encode the samples: {err}"))?;
+    if actual == expected {
+        return Ok(());
+    }
+    Err(format!(
+        "the wire encoding of protocol version {version} changed (fingerprint {actual:#018x}, \
+         pinned {expected:#018x}): bump PROTOCOL_VERSION to {} and append ({}, {actual:#018x}) \
+         to WIRE_FINGERPRINTS",
+        version + 1,
+        version + 1
+    ))
+}
+
+/// FNV-1a over the length prefixed encoding of `wire_samples`, stable across Rust versions
+/// unlike `std::hash`.
+#[cfg(test)]
+fn wire_fingerprint() -> Result<u64, NetworkError> {
+    let mut buf = Vec::new();
+    buf.write_encoded(&ProtocolHello::CURRENT)?;
+    buf.write_encoded(&ServerStatus {
+        motd: "motd".to_string(),
+        online: 2,
+        max_players: 20,
+        sample: vec!["player".to_string(), "other".to_string()],
+        icon: Some(vec![0x89, b'P', b'N', b'G']),
+    })?;
+    buf.write_encoded(&crate::lan::LanAnnouncement {
+        motd: "motd".to_string(),
+        port: 8080,
+        online: 2,
+        max_players: 20,
+    })?;
+    for packet in wire_samples() {
+        let packet = packet.to_buf()?;
+        buf.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&packet);
+    }
+
+    Ok(buf.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    }))
+}
+
+/// One packet of every kind and every nested variant. The matches below stop compiling when a
+/// variant is added, as a reminder to give it a sample.
+#[cfg(test)]
+fn wire_samples() -> Vec<ReliablePacket> {
+    let failures = [
+        AuthFailure::MissingCredentials,
+        AuthFailure::InvalidCredentials,
+        AuthFailure::InvalidToken,
+        AuthFailure::ExpiredToken,
+        AuthFailure::UsernameMismatch,
+        AuthFailure::Unavailable,
+    ];
+    let mut reasons = vec![
+        DisconnectReason::ServerFull,
+        DisconnectReason::InvalidUsername,
+    ];
+    reasons.extend(failures.map(DisconnectReason::AuthFailed));
+    reasons.push(DisconnectReason::Kicked("reason".to_string()));
+    reasons.push(DisconnectReason::ServerStopped);
+    reasons.push(DisconnectReason::Banned {
+        reason: "reason".to_string(),
+        expires: Some(1_700_000_000),
+    });
+    reasons.push(DisconnectReason::Banned {
+        reason: String::new(),
+        expires: None,
+    });
+    reasons.push(DisconnectReason::NotWhitelisted);
+    reasons.push(DisconnectReason::AlreadyOnline);
+    reasons.push(DisconnectReason::ProtocolViolation("reason".to_string()));
+
+    let mut samples = vec![
+        ReliablePacket::Handshake {
+            username: "player".to_string(),
+            credentials: Credentials::None,
+        },
+        ReliablePacket::Handshake {
+            username: "player".to_string(),
+            credentials: Credentials::Password("password".to_string()),
+        },
+        ReliablePacket::Handshake {
+            username: "player".to_string(),
+            credentials: Credentials::Token("token".to_string()),
+        },
+        ReliablePacket::HandshakeRes { player_id: 300 },
+        ReliablePacket::MovementInput {
+            directions: [true, false, true, false, true, false],
+            rotations: [90.5, -45.25],
+            position: [0.5, 80.0, -12.25],
+        },
+        ReliablePacket::ChatSend {
+            text: "hello".to_string(),
+        },
+        ReliablePacket::ChatBroadcast {
+            message: ChatMessage {
+                sender: ChatSender::Player("player".to_string()),
+                text: "hello".to_string(),
+                timestamp: 1_700_000_000_000,
+            },
+        },
+        ReliablePacket::ChatBroadcast {
+            message: ChatMessage {
+                sender: ChatSender::System,
+                text: "player joined the game".to_string(),
+                timestamp: 1_700_000_000_000,
+            },
+        },
+        ReliablePacket::RunCommand {
+            input: "tp player ~ ~10 ~".to_string(),
+        },
+        ReliablePacket::CompleteCommand {
+            id: 7,
+            input: "give player st".to_string(),
+        },
+        ReliablePacket::CommandSuggestions {
+            id: 7,
+            start: 12,
+            suggestions: vec!["stone".to_string()],
+        },
+        ReliablePacket::Teleport {
+            position: [0.5, 90.0, -12.25],
+        },
+    ];
+    samples.extend(
+        reasons
+            .into_iter()
+            .map(|reason| ReliablePacket::Disconnect { reason }),
+    );
+
+    for sample in &samples {
+        match sample {
+            ReliablePacket::Handshake { credentials, .. } => match credentials {
+                Credentials::None | Credentials::Password(_) | Credentials::Token(_) => {}
+            },
+            ReliablePacket::HandshakeRes { .. }
+            | ReliablePacket::MovementInput { .. }
+            | ReliablePacket::ChatSend { .. }
+            | ReliablePacket::RunCommand { .. }
+            | ReliablePacket::CompleteCommand { .. }
+            | ReliablePacket::CommandSuggestions { .. }
+            | ReliablePacket::Teleport { .. } => {}
+            ReliablePacket::ChatBroadcast { message } => match message.sender {
+                ChatSender::Player(_) | ChatSender::System => {}
+            },
+            ReliablePacket::Disconnect { reason } => match reason {
+                DisconnectReason::ServerFull
+                | DisconnectReason::InvalidUsername
+                | DisconnectReason::AuthFailed(
+                    AuthFailure::MissingCredentials
+                    | AuthFailure::InvalidCredentials
+                    | AuthFailure::InvalidToken
+                    | AuthFailure::ExpiredToken
+                    | AuthFailure::UsernameMismatch
+                    | AuthFailure::Unavailable,
+                )
+                | DisconnectReason::Kicked(_)
+                | DisconnectReason::ServerStopped
+                | DisconnectReason::Banned { .. }
+                | DisconnectReason::NotWhitelisted
+                | DisconnectReason::AlreadyOnline
+                | DisconnectReason::ProtocolViolation(_) => {}
+            },
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_format_is_pinned() {
+        if let Err(err) = check_wire_format() {
+            panic!("{err}");
+        }
     }
 }
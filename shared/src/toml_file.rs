@@ -0,0 +1,184 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// What can go wrong reading or writing a settings file.
+#[derive(Debug)]
+pub enum TomlFileError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Serialize(PathBuf, toml::ser::Error),
+    /// The file parsed, but holds values that cannot be used.
+    Invalid(PathBuf, String),
+}
+
+impl TomlFileError {
+    /// Whether the file does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, TomlFileError::Io(_, err) if err.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl Display for TomlFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TomlFileError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            TomlFileError::Parse(path, err) => write!(f, "invalid {}: {err}", path.display()),
+            TomlFileError::Serialize(path, err) => {
+                write!(f, "failed to serialize {}: {err}", path.display())
+            }
+            TomlFileError::Invalid(path, reason) => {
+                write!(f, "invalid {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TomlFileError {}
+
+/// Reads and parses the file at `path`.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T, TomlFileError> {
+    let content =
+        fs::read_to_string(path).map_err(|err| TomlFileError::Io(path.to_path_buf(), err))?;
+    toml::from_str(&content).map_err(|err| TomlFileError::Parse(path.to_path_buf(), err))
+}
+
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), TomlFileError> {
+    let content = toml::to_string_pretty(value)
+        .map_err(|err| TomlFileError::Serialize(path.to_path_buf(), err))?;
+    fs::write(path, content).map_err(|err| TomlFileError::Io(path.to_path_buf(), err))
+}
+
+/// Reads the file at `path` with `load`, writing the defaults there first if it is missing.
+pub fn load_or_create<T: Serialize + Default>(
+    path: &Path,
+    load: impl FnOnce(&Path) -> Result<T, TomlFileError>,
+) -> Result<T, TomlFileError> {
+    match load(path) {
+        Err(err) if err.is_not_found() => {
+            let value = T::default();
+            save(path, &value)?;
+            Ok(value)
+        }
+        result => result,
+    }
+}
+
+/// Reads the file at `path` with `load`, falling back to the defaults if it is missing or
+/// invalid. An invalid file is renamed to `broken_path(path)` first, so that saving the
+/// defaults does not overwrite it.
+pub fn load_or_default<T: Default>(
+    path: &Path,
+    load: impl FnOnce(&Path) -> Result<T, TomlFileError>,
+) -> T {
+    match load(path) {
+        Ok(value) => value,
+        Err(err) if err.is_not_found() => {
+            info!("{} not found, using the defaults", path.display());
+            T::default()
+        }
+        Err(err @ (TomlFileError::Parse(..) | TomlFileError::Invalid(..))) => {
+            let broken = broken_path(path);
+            match fs::rename(path, &broken) {
+                Ok(()) => warn!(
+                    "{err}, using the defaults and moving it to {}",
+                    broken.display()
+                ),
+                Err(rename_err) => warn!(
+                    "{err}, using the defaults. It could not be moved to {}: {rename_err}",
+                    broken.display()
+                ),
+            }
+            T::default()
+        }
+        Err(err) => {
+            warn!("{err}, using the defaults");
+            T::default()
+        }
+    }
+}
+
+/// Where `load_or_default` keeps the invalid file at `path`.
+pub fn broken_path(path: &Path) -> PathBuf {
+    let mut broken = path.as_os_str().to_owned();
+    broken.push(".broken");
+    PathBuf::from(broken)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        name: String,
+        count: u32,
+    }
+
+    #[test]
+    fn missing_files_are_created_with_the_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        let settings: Settings = load_or_create(&path, load).unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(load::<Settings>(&path).unwrap(), Settings::default());
+
+        let settings = Settings {
+            name: "world".to_string(),
+            count: 3,
+        };
+        save(&path, &settings).unwrap();
+        assert_eq!(load_or_create::<Settings>(&path, load).unwrap(), settings);
+    }
+
+    #[test]
+    fn errors_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        let err = load::<Settings>(&path).unwrap_err();
+        assert!(err.is_not_found());
+        assert!(err.to_string().contains("settings.toml"));
+
+        fs::write(&path, "count = \"three\"").unwrap();
+        let err = load::<Settings>(&path).unwrap_err();
+        assert!(matches!(err, TomlFileError::Parse(..)));
+        assert!(err.to_string().contains("settings.toml"));
+        assert!(load_or_create::<Settings>(&path, load).is_err());
+    }
+
+    #[test]
+    fn invalid_files_are_kept_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        assert_eq!(
+            load_or_default::<Settings>(&path, load),
+            Settings::default()
+        );
+        assert!(!path.exists());
+
+        fs::write(&path, "count = \"three\"").unwrap();
+        assert_eq!(
+            load_or_default::<Settings>(&path, load),
+            Settings::default()
+        );
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(broken_path(&path)).unwrap(),
+            "count = \"three\""
+        );
+
+        let invalid = |path: &Path| -> Result<Settings, _> {
+            Err(TomlFileError::Invalid(path.to_path_buf(), "no".to_string()))
+        };
+        fs::write(&path, "count = 4").unwrap();
+        assert_eq!(load_or_default(&path, invalid), Settings::default());
+        assert_eq!(fs::read_to_string(broken_path(&path)).unwrap(), "count = 4");
+    }
+}
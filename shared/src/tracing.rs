@@ -1,5 +1,18 @@
 pub fn init() {
+    init_with_level(tracing::Level::INFO);
+}
+
+pub fn init_with_level(level: tracing::Level) {
+    tracing_subscriber::fmt::fmt().with_max_level(level).init();
+}
+
+/// Like `init_with_level`, writing events with `writer` instead of to stdout.
+pub fn init_with_writer<W>(level: tracing::Level, writer: W)
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
     tracing_subscriber::fmt::fmt()
-        .with_max_level(tracing::Level::INFO)
+        .with_max_level(level)
+        .with_writer(writer)
         .init();
 }